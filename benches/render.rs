@@ -0,0 +1,40 @@
+//! measures the time to render a document with many spans
+//! to an html string.
+//! Run it with `cargo bench --features html`
+
+use std::time::Instant;
+
+use rust_web_markdown::{render_html, MarkdownProps};
+
+/// the number of times the document is rendered
+const ITERATIONS: u32 = 200;
+
+fn document() -> String {
+    let paragraph = "Some *emphasis*, some **strong text**, `inline code` \
+        and a [link](https://example.com) in a sentence.\n\n";
+    let list = "- first item\n- second item with `code`\n- third item\n\n";
+    let code = "```rust\nfn main() {\n    println!(\"hello\");\n}\n```\n\n";
+    format!("# Title\n\n{}", [paragraph, list, code].concat().repeat(100))
+}
+
+fn main() {
+    let source = document();
+    let props = MarkdownProps {
+        code_line_numbers: true,
+        ..Default::default()
+    };
+
+    // warm up the caches, like the syntax highlighting one
+    let len = render_html(&source, props).len();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(render_html(std::hint::black_box(&source), props));
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "rendered {} bytes of markdown into {len} bytes of html in {:?} per iteration",
+        source.len(),
+        elapsed / ITERATIONS
+    );
+}
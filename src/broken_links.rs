@@ -0,0 +1,123 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, LinkType, CowStr};
+
+use crate::diagnostics::link_references;
+use crate::utils::is_verbatim;
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// turns the references to undefined links, like `[text][label]` or `[label][]`,
+/// that the parser keeps as text, into links of type
+/// [`LinkType::ReferenceUnknown`] or [`LinkType::CollapsedUnknown`],
+/// with their label as id and without url.
+/// Adjacent text events are merged before looking for references,
+/// and the text of links, code and metadata blocks is skipped
+pub fn mark_broken_links<'a>(source: &'a str, events: Events<'a>) -> Events<'a> {
+    let mut result: Events<'a> = Vec::with_capacity(events.len());
+    // the depth of the links and images, and of the code and metadata blocks
+    let mut skipped = 0;
+    let mut text: Events<'a> = Vec::new();
+
+    for (event, range) in events {
+        match &event {
+            Event::Text(_) if skipped == 0 => {
+                if text.last().is_some_and(|(_, r)| r.end != range.start) {
+                    push_text(source, std::mem::take(&mut text), &mut result);
+                }
+                text.push((event, range));
+                continue
+            },
+            Event::Start(Tag::Link{..} | Tag::Image{..} | Tag::CodeBlock(_) | Tag::MetadataBlock(_)) =>
+                skipped += 1,
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) =>
+                skipped -= 1,
+            _ => ()
+        }
+        push_text(source, std::mem::take(&mut text), &mut result);
+        result.push((event, range));
+    }
+    push_text(source, text, &mut result);
+    result
+}
+
+/// pushes the adjacent text events of `text` into `events`,
+/// with the broken references they contain
+fn push_text<'a>(source: &'a str, text: Events<'a>, events: &mut Events<'a>) {
+    let (Some((_, first)), Some((_, last))) = (text.first(), text.last()) else { return };
+    let range = first.start..last.end;
+    // with escapes or entities, the references can't be located in the source
+    let exact = text.iter()
+        .all(|(e, r)| matches!(e, Event::Text(t) if is_verbatim(source, t, r)));
+    let s = source.get(range.clone()).filter(|_| exact);
+    let references = s.map(link_references).unwrap_or_default();
+    if references.is_empty() {
+        events.extend(text);
+        return
+    }
+
+    let mut start = range.start;
+    for (r, label) in references {
+        let r = range.start + r.start..range.start + r.end;
+        if start < r.start {
+            events.push((Event::Text(CowStr::from(&source[start..r.start])), start..r.start));
+        }
+        let reference = &source[r.clone()];
+        let text_end = reference.find("][").unwrap_or(0);
+        let link_type = match reference.ends_with("[]") {
+            true => LinkType::CollapsedUnknown,
+            false => LinkType::ReferenceUnknown,
+        };
+        let tag = Tag::Link {
+            link_type,
+            dest_url: "".into(),
+            title: "".into(),
+            id: label.into(),
+        };
+        let text_range = r.start + 1..r.start + text_end;
+        events.push((Event::Start(tag), r.clone()));
+        events.push((Event::Text(CowStr::from(&source[text_range.clone()])), text_range));
+        events.push((Event::End(TagEnd::Link), r.clone()));
+        start = r.end;
+    }
+    if start < range.end {
+        events.push((Event::Text(CowStr::from(&source[start..range.end])), start..range.end));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn broken_references(){
+        let source = "see [a][b] and [c][]";
+        // the brackets of unresolved references are separate text events
+        let events = vec![
+            (Event::Text("see ".into()), 0..4),
+            (Event::Text("[a]".into()), 4..7),
+            (Event::Text("[b] and [c][]".into()), 7..20),
+        ];
+        let link = |link_type, id: &'static str| Event::Start(Tag::Link {
+            link_type,
+            dest_url: "".into(),
+            title: "".into(),
+            id: id.into(),
+        });
+        assert_eq!(mark_broken_links(source, events), vec![
+            (Event::Text("see ".into()), 0..4),
+            (link(LinkType::ReferenceUnknown, "b"), 4..10),
+            (Event::Text("a".into()), 5..6),
+            (Event::End(TagEnd::Link), 4..10),
+            (Event::Text(" and ".into()), 10..15),
+            (link(LinkType::CollapsedUnknown, "c"), 15..20),
+            (Event::Text("c".into()), 16..17),
+            (Event::End(TagEnd::Link), 15..20),
+        ]);
+
+        // the text with escapes is kept as is
+        let source = r"\*[a][b]";
+        let events = vec![(Event::Text("*[a][b]".into()), 0..8)];
+        assert_eq!(mark_broken_links(source, events.clone()), events);
+    }
+}
@@ -0,0 +1,293 @@
+use core::ops::Range;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use pulldown_cmark_wikilink::{Event, Options};
+
+use crate::render::{element_key, StateChange, FOOTNOTES_KEY};
+use crate::{Context, MarkdownProps};
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// a source, with everything in the props that changes its events
+struct ParseKey {
+    source: String,
+    options: Options,
+    wikilinks: bool,
+    /// the address of the `event_preprocessor`
+    preprocessor: Option<usize>,
+}
+
+impl ParseKey {
+    fn new(source: &str, props: &MarkdownProps) -> Self {
+        Self {
+            source: source.to_string(),
+            options: crate::parse_options(props),
+            wikilinks: props.wikilinks,
+            preprocessor: props.event_preprocessor.map(|f| f as usize),
+        }
+    }
+
+    /// returns true if `source` parsed with `props` gives the events of this key
+    fn matches(&self, source: &str, props: &MarkdownProps) -> bool {
+        self.source == source
+            && self.options == crate::parse_options(props)
+            && self.wikilinks == props.wikilinks
+            && self.preprocessor == props.event_preprocessor.map(|f| f as usize)
+    }
+}
+
+/// a top-level element of a document rendered with
+/// [`RenderCache::render_incremental`]
+struct CachedBlock<V> {
+    /// the events of the element
+    events: Events<'static>,
+    /// the line of the element in the source, starting at 0
+    line: usize,
+    view: V,
+    /// the changes made by this element to the state of the document
+    changes: Vec<StateChange<V>>,
+}
+
+impl<V> CachedBlock<V> {
+    /// the position of the element in the source
+    fn range(&self) -> Range<usize> {
+        self.events.first().map_or(0..0, |(_, range)| range.clone())
+    }
+}
+
+/// the line of `position` in `source`, starting at 0
+fn line_of(source: &str, position: usize) -> usize {
+    source.as_bytes()[..position].iter().filter(|&&b| b == b'\n').count()
+}
+
+/// the parsed events of the last document rendered with
+/// [`crate::render_markdown_cached`] or [`RenderCache::render_incremental`],
+/// kept by the caller across renders.
+/// When the document and its parsing options didn't change,
+/// like when a live preview re-renders, it is not parsed again.
+///
+/// With [`RenderCache::render_incremental`], it also keeps the views `V`
+/// of the top-level elements. The cache must then be cleared with
+/// [`RenderCache::clear`] when the props or the context change
+pub struct RenderCache<V = ()> {
+    /// the source and the parsing options of `events`
+    key: Option<ParseKey>,
+    /// the events of the source
+    events: Events<'static>,
+    /// the top-level elements of the last incremental render
+    blocks: Vec<CachedBlock<V>>,
+    /// the abbreviations used to render `blocks`
+    abbreviations: BTreeMap<String, String>,
+}
+
+impl<V> Default for RenderCache<V> {
+    fn default() -> Self {
+        Self {
+            key: None,
+            events: Vec::new(),
+            blocks: Vec::new(),
+            abbreviations: BTreeMap::new(),
+        }
+    }
+}
+
+/// the result of [`RenderCache::render_incremental`]
+pub struct IncrementalRender<V> {
+    /// the whole document
+    pub view: V,
+    /// for each top-level element of the document, in order,
+    /// true if its view was reused from the previous render,
+    /// false if it was rebuilt
+    pub reused: Vec<bool>,
+}
+
+impl<V> RenderCache<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// forgets the cached events and views
+    pub fn clear(&mut self) {
+        *self = Self::default()
+    }
+
+    /// the events of `source` parsed with `props`, from the cache if possible
+    pub(crate) fn parse<'a>(&mut self, source: &'a str, props: &MarkdownProps) -> Events<'a> {
+        if !self.key.as_ref().is_some_and(|key| key.matches(source, props)) {
+            self.events = crate::parse(source, props).into_iter()
+                .map(|(e, r)| (e.into_static(), r))
+                .collect();
+            self.key = Some(ParseKey::new(source, props));
+        }
+        self.events.clone()
+    }
+}
+
+impl<V: Clone> RenderCache<V> {
+    /// renders `source` like [`crate::render_markdown`], reusing the views
+    /// of the top-level elements whose events didn't change since the last call,
+    /// at the start and at the end of the document.
+    /// The elements in between are rebuilt. The elements at the end are reused
+    /// only if they kept their position and their line in the source,
+    /// used by their handlers, and if the rebuilt elements left the footnote
+    /// numbers, heading ids and image numbers they use unchanged
+    pub fn render_incremental<'a, 'callback, F>(&mut self, cx: F, source: &'a str) -> IncrementalRender<V>
+    where F: Context<'a, 'callback, View=V>,
+          'callback: 'a,
+    {
+        let stream = self.parse(source, &cx.props());
+        let prepared = crate::prepare(cx, source, stream);
+        if prepared.abbreviations != self.abbreviations {
+            self.blocks.clear();
+            self.abbreviations = prepared.abbreviations.clone();
+        }
+        let mut old = std::mem::take(&mut self.blocks).into_iter();
+
+        // the unchanged elements at the start of the document.
+        // The last element may have been ended by the end of the document,
+        // so it is reused only if the document still ends with it
+        let mut position = 0;
+        let total = old.len();
+        for i in 0..total {
+            let block = &old.as_slice()[0];
+            let end = position + block.events.len();
+            let is_last = i + 1 == total;
+            if prepared.stream.get(position..end) != Some(&block.events[..])
+                || (is_last && end != prepared.stream.len()) {
+                break
+            }
+            position = end;
+            self.blocks.extend(old.next());
+        }
+
+        // the unchanged elements at the end of the document,
+        // with the position of their first event in the stream
+        let mut suffix = Vec::new();
+        let mut end = prepared.stream.len();
+        while let Some(block) = old.next_back() {
+            let Some(start) = end.checked_sub(block.events.len()).filter(|&start| start >= position)
+                else { break };
+            if prepared.stream[start..end] != block.events[..]
+                || line_of(source, block.range().start) != block.line {
+                break
+            }
+            end = start;
+            suffix.push((start, block));
+        }
+        suffix.reverse();
+
+        let mut views: Vec<(String, V)> = self.blocks.iter()
+            .map(|block| (element_key(&block.range()), block.view.clone()))
+            .collect();
+        let mut reused = vec![true; self.blocks.len()];
+
+        // the events consumed by the renderer for the current element
+        let consumed: Rc<RefCell<Events<'a>>> = Default::default();
+        let events = prepared.stream.into_iter()
+            .skip(position)
+            .inspect(|event| consumed.borrow_mut().push(event.clone()));
+        let mut renderer = crate::render_events(cx, source, events)
+            .with_abbreviations(prepared.abbreviations)
+            .recording_changes();
+        for block in &self.blocks {
+            renderer.replay(&block.changes);
+        }
+
+        let mut footnotes = None;
+        while let Some(view) = renderer.next() {
+            let events: Events<'static> = consumed.borrow_mut()
+                .drain(..)
+                .map(|(e, r)| (e.into_static(), r))
+                .collect();
+            // the footnote section comes last, without events
+            if events.is_empty() {
+                footnotes = Some(view);
+                continue
+            }
+            position += events.len();
+            self.blocks.push(CachedBlock {
+                line: line_of(source, events[0].1.start),
+                events,
+                view: view.clone(),
+                changes: renderer.take_changes(),
+            });
+            views.push((renderer.key(), view));
+            reused.push(false);
+
+            // the remaining elements are reused if they read the same state as before
+            let Some(k) = suffix.iter().position(|(start, _)| *start == position)
+                else { continue };
+            let state = renderer.state();
+            if suffix[k..].iter().all(|(_, block)| renderer.replay(&block.changes)) {
+                for (_, block) in suffix.drain(k..) {
+                    views.push((element_key(&block.range()), block.view.clone()));
+                    reused.push(true);
+                    self.blocks.push(block);
+                }
+                footnotes = renderer.footnote_section();
+                break
+            }
+            renderer.restore_state(state);
+        }
+
+        let elements = views.into_iter()
+            .chain(footnotes.map(|view| (FOOTNOTES_KEY.to_string(), view)))
+            .collect();
+        IncrementalRender {
+            view: crate::finish(cx, elements, prepared.has_math, prepared.truncated),
+            reused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys(){
+        let props = MarkdownProps::default();
+        let key = ParseKey::new("# title", &props);
+        assert!(key.matches("# title", &props));
+        assert!(!key.matches("# title!", &props));
+        let options = Options::empty();
+        let props = MarkdownProps {
+            parse_options: Some(&options),
+            ..Default::default()
+        };
+        assert!(!key.matches("# title", &props));
+        let props = MarkdownProps {
+            smart_punctuation: Some(false),
+            ..Default::default()
+        };
+        assert!(!key.matches("# title", &props));
+    }
+
+    #[cfg(feature="html")]
+    #[test]
+    fn incremental(){
+        crate::html::with_html_context(MarkdownProps::default(), |cx| {
+            let mut cache = RenderCache::new();
+            let first = cache.render_incremental(cx, "a\n\nb\n\nc");
+            assert_eq!(first.reused, vec![false, false, false]);
+            let second = cache.render_incremental(cx, "a\n\nb\n\nd");
+            assert_eq!(second.reused, vec![true, true, false]);
+            assert_eq!(second.view, crate::render_html("a\n\nb\n\nd", MarkdownProps::default()));
+            let third = cache.render_incremental(cx, "x\n\nb\n\nd");
+            assert_eq!(third.reused, vec![false, true, true]);
+            assert_eq!(third.view, crate::render_html("x\n\nb\n\nd", MarkdownProps::default()));
+            // the following elements moved
+            let fourth = cache.render_incremental(cx, "xy\n\nb\n\nd");
+            assert_eq!(fourth.reused, vec![false, false, false]);
+            // the footnote is numbered after the new one
+            let source = "a[^2]\n\nb[^1]\n\n[^1]: one\n\n[^2]: two";
+            cache.render_incremental(cx, source);
+            let source = "a[^1]\n\nb[^1]\n\n[^1]: one\n\n[^2]: two";
+            let fifth = cache.render_incremental(cx, source);
+            assert_eq!(fifth.reused[..2], [false, false]);
+            assert_eq!(fifth.view, crate::render_html(source, MarkdownProps::default()));
+        })
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeSet;
+
+#[derive(Debug, Default, PartialEq)]
+/// the information string of a fenced code block,
+/// like `rust {2,4-6}`
+pub struct CodeFence {
+    /// the whole information string, like `python title="app.py"`,
+    /// to read the metadata that is not parsed, like `ignore` in `rust,ignore`.
+    /// It is empty for indented code blocks
+    pub info: String,
+    /// the language of the code, like `rust`
+    pub lang: Option<String>,
+    /// the lines to highlight, starting at 1
+    pub highlighted_lines: BTreeSet<usize>,
+    /// the line of the code block in the markdown source, starting at 1,
+    /// for the top-level code blocks with `MarkdownProps::source_line_attributes`.
+    /// It is not part of the information string, and is left empty by `parse`
+    pub source_line: Option<usize>,
+}
+
+/// the maximum number of lines a single range can highlight
+const MAX_RANGE_LEN: usize = 10_000;
+
+/// parses a list of line ranges, like `2,4-6`.
+/// Returns None if the list is invalid
+fn parse_line_ranges(spec: &str) -> Option<BTreeSet<usize>> {
+    let mut lines = BTreeSet::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end): (usize, usize) = match part.split_once('-') {
+            Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+            None => {
+                let n = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if start == 0 || start > end || end - start >= MAX_RANGE_LEN {
+            return None
+        }
+        lines.extend(start..=end);
+    }
+    Some(lines)
+}
+
+impl CodeFence {
+    /// parses the information string of a code block.
+    /// The language is the first word, and the lines to highlight
+    /// are given between braces. Invalid line ranges are ignored
+    pub fn parse(info: &str) -> Self {
+        let raw = info;
+        let (info, spec) = match (info.find('{'), info.rfind('}')) {
+            (Some(start), Some(end)) if start < end => 
+                (format!("{} {}", &info[..start], &info[end+1..]), Some(&info[start+1..end])),
+            _ => (info.to_string(), None)
+        };
+
+        let lang = info
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .find(|s| !s.is_empty())
+            .map(str::to_string);
+
+        Self {
+            info: raw.to_string(),
+            lang,
+            highlighted_lines: spec.and_then(parse_line_ranges).unwrap_or_default(),
+            source_line: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_lang_and_lines(){
+        let fence = CodeFence::parse("rust {2,4-6}");
+        assert_eq!(fence.lang.as_deref(), Some("rust"));
+        assert_eq!(fence.highlighted_lines, BTreeSet::from([2, 4, 5, 6]));
+    }
+
+    #[test]
+    fn parse_lang_only(){
+        assert_eq!(CodeFence::parse("python").lang.as_deref(), Some("python"));
+        assert_eq!(CodeFence::parse("rust,ignore").lang.as_deref(), Some("rust"));
+        assert_eq!(CodeFence::parse("rust,ignore").info, "rust,ignore");
+        assert_eq!(CodeFence::parse(""), CodeFence::default());
+    }
+
+    #[test]
+    fn parse_invalid_lines(){
+        let fence = CodeFence::parse("rust {2,x}");
+        assert_eq!(fence.lang.as_deref(), Some("rust"));
+        assert!(fence.highlighted_lines.is_empty());
+        assert!(CodeFence::parse("rust {6-4}").highlighted_lines.is_empty());
+        assert!(CodeFence::parse("rust {0}").highlighted_lines.is_empty());
+    }
+}
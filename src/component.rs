@@ -24,59 +24,96 @@ pub enum CustomHtmlTag {
 
 type ParseError = String;
 
-fn parse_attribute_value(stream: &mut Peekable<std::str::Chars>) 
-    -> Result<String, ParseError> {
-    let mut attribute = String::new();
-
-    if stream.next() != Some('"') {
-        return Err("please use `\"` to wrap your attribute values".into())
+fn skip_whitespace(stream: &mut Peekable<std::str::Chars>) {
+    while stream.peek().is_some_and(|c| c.is_whitespace()) {
+        stream.next();
     }
+}
 
-    loop {
-        match stream.peek() {
-            None => return Err("expected attribute value".into()),
-            Some(&'"') => break,
-            _ => attribute.push(stream.next().unwrap())
-        }
-    }
-    stream.next();
+/// the characters that can't appear in an unquoted attribute value
+fn ends_unquoted_value(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`' | '/')
+}
 
-    Ok(attribute)
+/// the characters that can't appear in a component or attribute name
+fn ends_name(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '/')
 }
 
-fn parse_attribute_name(stream: &mut Peekable<std::str::Chars>) 
-    -> Result<String, ParseError> {
+fn parse_name(stream: &mut Peekable<std::str::Chars>) -> String {
     let mut name = String::new();
-
-    while stream.peek() == Some(&' ') {
+    while let Some(&c) = stream.peek() {
+        if ends_name(c) {
+            break
+        }
+        name.push(c);
         stream.next();
     }
+    name
+}
 
-    loop {
-        match stream.peek() {
-            None => return Err("expected equal sign after attribute name".into()),
-            Some(&'=') => break,
-            _ => name.push(stream.next().unwrap()),
+fn parse_attribute_value(stream: &mut Peekable<std::str::Chars>) 
+    -> Result<String, ParseError> {
+    let mut attribute = String::new();
+
+    match stream.peek() {
+        Some(&quote @ ('"' | '\'')) => {
+            stream.next();
+            loop {
+                match stream.next() {
+                    None => return Err(format!("expected `{quote}` at the end of the attribute value")),
+                    Some(c) if c == quote => break,
+                    Some(c) => attribute.push(c)
+                }
+            }
+        },
+        _ => {
+            while let Some(&c) = stream.peek() {
+                if ends_unquoted_value(c) {
+                    break
+                }
+                attribute.push(c);
+                stream.next();
+            }
+            if attribute.is_empty() {
+                return Err("expected attribute value".into())
+            }
         }
     }
 
-    Ok(name)
+    Ok(attribute)
 }
 
+/// parses an attribute, with or without value.
+/// Boolean attributes, without value, are given an empty value
 fn parse_attribute(stream: &mut Peekable<std::str::Chars>) -> 
     Result<(String, String), ParseError> {
-    let name = parse_attribute_name(stream)?;
+    let name = parse_name(stream);
+    if name.is_empty() {
+        return Err("expected attribute name".into())
+    }
+    skip_whitespace(stream);
+    if stream.peek() != Some(&'=') {
+        return Ok((name, String::new()))
+    }
     // equal sign
     stream.next();
-    // spaces
-    while stream.peek() == Some(&' ') {
-        stream.next();
-    }
+    skip_whitespace(stream);
     let attribute = parse_attribute_value(stream)?;
 
     Ok((name, attribute))
 }
 
+/// Parses a custom component tag, with the grammar
+/// ```text
+/// tag       := '<' '/'? name (space+ attribute)* space* '/'? '>' space*
+/// attribute := name (space* '=' space* value)?
+/// value     := '"' [^"]* '"' | "'" [^']* "'" | [^ "'=<>`/]+
+/// ```
+/// where `name` is made of any characters except spaces, quotes and `=<>/`.
+/// A tag ending with `/>` is self-closing and has no children,
+/// and an attribute without value, like `disabled`, has an empty value.
+/// End tags can't have attributes nor be self-closing
 impl FromStr for CustomHtmlTag {
     type Err = String;
 
@@ -97,20 +134,20 @@ impl FromStr for CustomHtmlTag {
             false
         };
 
-        let mut name = String::new();
-
-        loop {
-            match stream.peek() {
-                Some(&' ') | Some(&'/') | Some(&'>') => break,
-                _ => name.push(stream.next().unwrap())
-            }
+        let name = parse_name(&mut stream);
+        if name.is_empty() {
+            return Err("expected component name".into())
         }
 
         let mut attributes = BTreeMap::new();
         loop {
+            let before = stream.peek().copied();
+            skip_whitespace(&mut stream);
             match stream.peek() {
                 None => return Err("expected end of tag".into()),
                 Some(&'>') | Some(&'/') => break,
+                _ if !before.is_some_and(char::is_whitespace) =>
+                    return Err("expected space before attribute".into()),
                 _ => {
                     let (name, value) = parse_attribute(&mut stream)?;
                     attributes.insert(name, value);
@@ -118,25 +155,31 @@ impl FromStr for CustomHtmlTag {
             }
         }
 
-        if stream.peek() == Some(&'/') {
-            return Ok(CustomHtmlTag::Inline(ComponentCall {
-                name,
-                attributes: attributes.into(),
-            }))
-        }
-
-        while stream.peek() == Some(&' ') {
+        let is_inline = stream.peek() == Some(&'/');
+        if is_inline {
             stream.next();
         }
-
-        if is_end {
-            Ok(CustomHtmlTag::End(name))
+        if stream.next() != Some('>') {
+            return Err("expected end of tag".into())
         }
-        else {
-            Ok(CustomHtmlTag::Start(ComponentCall {
+        skip_whitespace(&mut stream);
+        if stream.peek().is_some() {
+            return Err("unexpected characters after the tag".into())
+        }
+
+        match (is_end, is_inline) {
+            (true, true) => Err("an end tag can't be self-closing".into()),
+            (true, false) if !attributes.is_empty() =>
+                Err("an end tag can't have attributes".into()),
+            (true, false) => Ok(CustomHtmlTag::End(name)),
+            (false, true) => Ok(CustomHtmlTag::Inline(ComponentCall {
+                name,
+                attributes,
+            })),
+            (false, false) => Ok(CustomHtmlTag::Start(ComponentCall {
                 name,
                 attributes
-            }))
+            })),
         }
     }
 }
@@ -187,4 +230,31 @@ mod test {
                 )
         )
     }
+
+    #[test]
+    fn parse_attribute_values(){
+        let c : CustomHtmlTag = "<a  key='val' other = \"x y\" n=1 disabled\n/>".parse().unwrap();
+        assert_eq!(c, Inline(
+                ComponentCall {
+                    name: "a".into(),
+                    attributes: BTreeMap::from([
+                        ("key".into(), "val".into()),
+                        ("other".into(), "x y".into()),
+                        ("n".into(), "1".into()),
+                        ("disabled".into(), "".into()),
+                    ])
+                },
+                )
+        )
+    }
+
+    #[test]
+    fn parse_errors(){
+        assert!("<a key=\"val>".parse::<CustomHtmlTag>().is_err());
+        assert!("<a key=>".parse::<CustomHtmlTag>().is_err());
+        assert!("<a key=\"x\"other>".parse::<CustomHtmlTag>().is_err());
+        assert!("</a/>".parse::<CustomHtmlTag>().is_err());
+        assert!("</a key=\"x\">".parse::<CustomHtmlTag>().is_err());
+        assert!("<>".parse::<CustomHtmlTag>().is_err());
+    }
 }
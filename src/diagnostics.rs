@@ -0,0 +1,255 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CodeBlockKind};
+
+use crate::component::CustomHtmlTag;
+use crate::render::can_be_custom_component;
+use crate::Context;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// how serious a [`Diagnostic`] is
+pub enum Severity {
+    /// the document renders, but probably not as intended
+    Warning,
+    /// the document renders with an error message
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// a malformed construct found in a document,
+/// like an unterminated code fence or an unknown component
+pub struct Diagnostic {
+    /// the position of the construct in the source
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}..{}: {}", self.severity, self.range.start, self.range.end, self.message)
+    }
+}
+
+impl Diagnostic {
+    fn warning(range: Range<usize>, message: String) -> Self {
+        Self { range, severity: Severity::Warning, message }
+    }
+
+    fn error(range: Range<usize>, message: String) -> Self {
+        Self { range, severity: Severity::Error, message }
+    }
+}
+
+/// the line without its indentation and blockquote markers
+fn line_content(line: &str) -> &str {
+    line.trim_start_matches(|c: char| c == '>' || c.is_whitespace()).trim_end()
+}
+
+/// returns true if the fenced code block `code` has no closing fence
+fn is_unterminated_fence(code: &str) -> bool {
+    let mut lines = code.lines();
+    let opening = line_content(lines.next().unwrap_or(""));
+    let fence = match opening.chars().next() {
+        Some(c @ ('`' | '~')) => c,
+        _ => return false
+    };
+    let len = opening.len() - opening.trim_start_matches(fence).len();
+    match lines.last().map(line_content) {
+        Some(closing) => closing.len() < len || closing.chars().any(|c| c != fence),
+        None => true
+    }
+}
+
+/// finds the full and collapsed link references, like `[text][label]`
+/// or `[label][]`, in the raw markdown `s`.
+/// Returns the position and the label of each one
+pub(crate) fn link_references(s: &str) -> Vec<(Range<usize>, &str)> {
+    // the position of the closing bracket of the bracketed text at `start`
+    let closing = |start: usize| -> Option<usize> {
+        let end = start + 1 + s[start+1..].find([']', '['])?;
+        (s.as_bytes()[end] == b']').then_some(end)
+    };
+    let mut references = Vec::new();
+    let mut i = 0;
+    while let Some(j) = s[i..].find('[') {
+        let start = i + j;
+        i = start + 1;
+        if s[..start].ends_with('\\') {
+            continue
+        }
+        let Some(text_end) = closing(start) else { continue };
+        if !s[text_end+1..].starts_with('[') {
+            continue
+        }
+        let Some(label_end) = closing(text_end + 1) else { continue };
+        let label = match &s[text_end+2..label_end] {
+            "" => &s[start+1..text_end],
+            label => label
+        };
+        if !label.trim().is_empty() {
+            references.push((start..label_end+1, label));
+        }
+        i = label_end + 1;
+    }
+    references
+}
+
+/// the diagnostic of the html `raw_html` at `range`, if it is a component
+/// that can't be rendered. Only the block components are rendered as errors,
+/// the inline html that is not a registered component is kept as html
+fn check_component<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    raw_html: &str,
+    range: Range<usize>,
+    block: bool
+    ) -> Option<Diagnostic>
+where 'callback: 'a
+{
+    if !can_be_custom_component(raw_html) {
+        return None
+    }
+    match (raw_html.trim().parse(), block) {
+        (Ok(CustomHtmlTag::Start(c) | CustomHtmlTag::Inline(c)), _) if cx.has_custom_component(&c.name) => None,
+        (Ok(CustomHtmlTag::Start(c) | CustomHtmlTag::Inline(c)), true) =>
+            Some(Diagnostic::error(range, format!("unknown component `{}`", c.name))),
+        (Ok(CustomHtmlTag::Start(c) | CustomHtmlTag::Inline(c)), false)
+            if c.name.starts_with(|c: char| c.is_ascii_uppercase()) =>
+            Some(Diagnostic::warning(range, format!("unknown component `{}`, rendered as html", c.name))),
+        (Ok(CustomHtmlTag::End(name)), true) =>
+            Some(Diagnostic::error(range, format!("end of component `{name}` without start"))),
+        (Err(e), true) =>
+            Some(Diagnostic::error(range, format!("invalid component: {e}"))),
+        _ => None
+    }
+}
+
+/// finds the malformed constructs in `events`, parsed from `source`:
+/// unterminated code fences, undefined link references, and components
+/// that are not registered in `cx` or can't be parsed
+pub(crate) fn diagnose<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    source: &str,
+    events: &[(Event<'a>, Range<usize>)]
+    ) -> Vec<Diagnostic>
+where 'callback: 'a
+{
+    let mut diagnostics = Vec::new();
+    let props = cx.props();
+    if let Some((format, range, error)) = crate::frontmatter::frontmatter_error(source) {
+        if crate::frontmatter_enabled(format, &props) {
+            diagnostics.push(Diagnostic::error(range, error.to_string()))
+        }
+    }
+
+    // the adjacent text events, where unresolved references are kept as text
+    let mut text: Option<Range<usize>> = None;
+    let mut in_code_block = false;
+    let mut in_html_block = false;
+
+    let check_text = |text: &mut Option<Range<usize>>, diagnostics: &mut Vec<Diagnostic>| {
+        let Some(range) = text.take() else { return };
+        let Some(s) = source.get(range.clone()) else { return };
+        diagnostics.extend(link_references(s).into_iter().map(|(r, label)| Diagnostic::warning(
+            range.start + r.start..range.start + r.end,
+            format!("undefined link reference `{label}`")
+        )));
+    };
+
+    for (event, range) in events {
+        match event {
+            Event::Text(_) if !in_code_block => {
+                match &mut text {
+                    Some(r) if r.end == range.start => r.end = range.end,
+                    _ => {
+                        check_text(&mut text, &mut diagnostics);
+                        text = Some(range.clone());
+                    }
+                }
+                continue
+            },
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+                let fenced = matches!(kind, CodeBlockKind::Fenced(_));
+                if fenced && source.get(range.clone()).is_some_and(is_unterminated_fence) {
+                    diagnostics.push(Diagnostic::warning(range.clone(), "unterminated code fence".to_string()))
+                }
+            },
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Start(Tag::HtmlBlock) => in_html_block = true,
+            Event::Html(s) if in_html_block => {
+                in_html_block = false;
+                diagnostics.extend(check_component(cx, s, range.clone(), true));
+            },
+            Event::InlineHtml(s) => diagnostics.extend(check_component(cx, s, range.clone(), false)),
+            _ => ()
+        }
+        check_text(&mut text, &mut diagnostics);
+    }
+    check_text(&mut text, &mut diagnostics);
+    diagnostics
+}
+
+/// `check_markdown(cx, source)` returns the malformed constructs of `source`,
+/// parsed like [`crate::render_markdown`] does with the props of `cx`,
+/// without rendering it. They are the diagnostics that would be
+/// reported to [`Context::report_diagnostic`] when rendering `source`
+pub fn check_markdown<'a, 'callback, F: Context<'a, 'callback>>(cx: F, source: &'a str) -> Vec<Diagnostic>
+where 'callback: 'a
+{
+    diagnose(cx, source, &crate::parse(source, &cx.props()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unterminated_fences(){
+        assert!(!is_unterminated_fence("```rust\nlet a = 1;\n```\n"));
+        assert!(!is_unterminated_fence("> ~~~~\n> a\n> ~~~~~"));
+        assert!(is_unterminated_fence("```rust\nlet a = 1;\n"));
+        assert!(is_unterminated_fence("````\na\n```"));
+        assert!(is_unterminated_fence("```"));
+        assert!(!is_unterminated_fence("    indented"));
+    }
+
+    #[test]
+    fn references(){
+        let s = "see [a][b], [c][] and \\[d][e] or [f](g)";
+        let references: Vec<_> = link_references(s).into_iter()
+            .map(|(r, label)| (&s[r], label))
+            .collect();
+        assert_eq!(references, vec![("[a][b]", "b"), ("[c][]", "c")]);
+    }
+
+    #[cfg(feature="html")]
+    #[test]
+    fn unterminated_frontmatter(){
+        use pulldown_cmark_wikilink::Options;
+        use crate::html::with_html_context;
+        use crate::MarkdownProps;
+
+        let source = "---\ntitle: hello\n";
+        assert_eq!(
+            with_html_context(MarkdownProps::default(), |cx| diagnose(cx, source, &[])),
+            vec![Diagnostic::error(0..3, "unterminated frontmatter".to_string())]
+        );
+        // without yaml metadata blocks, the `---` is a thematic break
+        let options = Options::empty();
+        let props = MarkdownProps {
+            parse_options: Some(&options),
+            ..Default::default()
+        };
+        assert_eq!(with_html_context(props, |cx| diagnose(cx, source, &[])), vec![]);
+    }
+}
@@ -0,0 +1,83 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, MathMode, CowStr};
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// the characters that make the content between dollars look like math
+const MATH_CHARS: &str = "\\^_{}=+-*/<>()[]|";
+
+/// returns true if the inline math written `written` in the source,
+/// with its dollars, and followed by `next` is more likely some prose
+/// between two prices, like `$5 and $`:
+/// - its content starts or ends with a space
+/// - its closing dollar is followed by a digit
+/// - it spans a line break
+/// - its content has several words, but no math characters
+fn is_prose(written: &str, next: Option<char>) -> bool {
+    let content = &written[1..written.len()-1];
+    content.starts_with(char::is_whitespace)
+        || content.ends_with(char::is_whitespace)
+        || next.is_some_and(|c| c.is_ascii_digit())
+        || content.contains('\n')
+        || (content.contains(char::is_whitespace) && !content.contains(|c| MATH_CHARS.contains(c)))
+}
+
+/// turns the inline math between dollars that looks like prose,
+/// like the text between the prices of `$5 and $10`, back into text.
+/// The escaped dollars, like `\$5`, are already text
+pub fn guard_dollar_math<'a>(source: &'a str, events: Events<'a>) -> Events<'a> {
+    events.into_iter()
+        .map(|(event, range)| match event {
+            Event::Math(MathMode::Inline, _) => {
+                let written = source.get(range.clone()).unwrap_or_default();
+                let dollars = written.len() >= 2 && written.starts_with('$') && written.ends_with('$')
+                    && !written.starts_with("$$");
+                let next = source[range.end..].chars().next();
+                match dollars && is_prose(written, next) {
+                    true => (Event::Text(CowStr::from(written)), range),
+                    false => (event, range)
+                }
+            },
+            _ => (event, range)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the math events of `source`, with their content, as parsed with dollars
+    fn guarded(source: &'static str, spans: &[&str]) -> Vec<Event<'static>> {
+        let mut start = 0;
+        let events = spans.iter()
+            .map(|span| {
+                let i = start + source[start..].find(span).unwrap();
+                start = i + span.len();
+                let content = span[1..span.len()-1].to_string();
+                (Event::Math(MathMode::Inline, content.into()), i..start)
+            })
+            .collect();
+        guard_dollar_math(source, events).into_iter().map(|(e, _)| e).collect()
+    }
+
+    #[test]
+    fn prices(){
+        assert_eq!(guarded("it costs $5 and $10", &["$5 and $"]), vec![Event::Text("$5 and $".into())]);
+        assert_eq!(guarded("a total of $5+$10", &["$5+$"]), vec![Event::Text("$5+$".into())]);
+        assert_eq!(guarded("from $5 to\n$10 each", &["$5 to\n$"]), vec![Event::Text("$5 to\n$".into())]);
+        assert_eq!(guarded("between $5 $6", &["$5 $"]), vec![Event::Text("$5 $".into())]);
+    }
+
+    #[test]
+    fn math_is_kept(){
+        let math = |s: &'static str| Event::Math(MathMode::Inline, s.into());
+        assert_eq!(guarded("$x$ and $a + b$ cost $5$", &["$x$", "$a + b$", "$5$"]), vec![
+            math("x"),
+            math("a + b"),
+            math("5"),
+        ]);
+        assert_eq!(guarded("so $f(x) = 2x$.", &["$f(x) = 2x$"]), vec![math("f(x) = 2x")]);
+    }
+}
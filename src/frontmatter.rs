@@ -0,0 +1,202 @@
+use core::ops::Range;
+use std::fmt;
+
+#[cfg(feature="frontmatter")]
+use serde::de::DeserializeOwned;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// the format of a frontmatter, given by its delimiters
+pub enum FrontmatterFormat {
+    /// fenced by `---` lines
+    Yaml,
+    /// fenced by `+++` lines
+    Toml,
+    /// a json object, starting with a `{` line and ending with a `}` line
+    Json,
+}
+
+impl FrontmatterFormat {
+    /// detects the format of the frontmatter at the start of `source`.
+    /// Returns None if `source` doesn't start with a frontmatter,
+    /// or if its closing delimiter is missing: the source is then plain markdown,
+    /// where a leading `---` is a thematic break
+    pub fn detect(source: &str) -> Option<Self> {
+        split_frontmatter(source).0.map(|(format, _)| format)
+    }
+
+    /// the format of a frontmatter opened by the line `line`
+    fn opened_by(line: &str) -> Option<Self> {
+        match line {
+            "---" => Some(FrontmatterFormat::Yaml),
+            "+++" => Some(FrontmatterFormat::Toml),
+            "{" => Some(FrontmatterFormat::Json),
+            _ => None
+        }
+    }
+
+    /// returns true if `line` closes a frontmatter of this format
+    fn is_closing(self, line: &str) -> bool {
+        match self {
+            FrontmatterFormat::Yaml => matches!(line, "---" | "..."),
+            FrontmatterFormat::Toml => line == "+++",
+            FrontmatterFormat::Json => line == "}",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrontmatterError {
+    /// the frontmatter has no closing delimiter
+    Unterminated,
+    /// the frontmatter could not be deserialized
+    Deserialize(String),
+}
+
+impl fmt::Display for FrontmatterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrontmatterError::Unterminated => write!(f, "unterminated frontmatter"),
+            FrontmatterError::Deserialize(e) => write!(f, "invalid frontmatter: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FrontmatterError {}
+
+/// the frontmatter of a document with its format, and the remaining markdown body
+pub(crate) type SplitFrontmatter<'s> = (Option<(FrontmatterFormat, &'s str)>, &'s str);
+
+/// splits `source` into its frontmatter and the remaining markdown body.
+/// The format of the frontmatter is given by its first line, `---`, `+++` or `{`.
+/// The braces of a json frontmatter are kept, the other delimiters are removed.
+/// Without a closing delimiter, the whole source is the body
+pub fn split_frontmatter(source: &str) -> SplitFrontmatter<'_> {
+    let format = match source.lines().next().and_then(|line| FrontmatterFormat::opened_by(line.trim_end())) {
+        Some(format) => format,
+        None => return (None, source)
+    };
+
+    let mut lines = source.split_inclusive('\n');
+    let first_line = lines.next().unwrap_or_default().len();
+    let start = if format == FrontmatterFormat::Json { 0 } else { first_line };
+
+    let mut end = first_line;
+    for line in lines {
+        if format.is_closing(line.trim_end()) {
+            let body = &source[end+line.len()..];
+            if format == FrontmatterFormat::Json {
+                end += line.len();
+            }
+            return (Some((format, &source[start..end])), body)
+        }
+        end += line.len();
+    }
+    (None, source)
+}
+
+/// finds the error of the frontmatter at the start of `source`, if any,
+/// with the format and the position of the frontmatter.
+/// The frontmatter is only deserialized with the `frontmatter` feature
+#[cfg_attr(not(feature="frontmatter"), allow(unused_variables))]
+pub(crate) fn frontmatter_error(source: &str) -> Option<(FrontmatterFormat, Range<usize>, FrontmatterError)> {
+    let first_line = source.lines().next()?;
+    let format = FrontmatterFormat::opened_by(first_line.trim_end())?;
+    let (frontmatter, body) = split_frontmatter(source);
+    let Some((_, frontmatter)) = frontmatter else {
+        return Some((format, 0..first_line.len(), FrontmatterError::Unterminated))
+    };
+    #[cfg(feature="frontmatter")]
+    if !frontmatter.trim().is_empty() {
+        if let Err(e) = deserialize::<serde::de::IgnoredAny>(format, frontmatter) {
+            return Some((format, 0..source.len() - body.len(), e))
+        }
+    }
+    None
+}
+
+/// deserializes `frontmatter` written in `format`
+#[cfg(feature="frontmatter")]
+fn deserialize<T: DeserializeOwned>(format: FrontmatterFormat, frontmatter: &str)
+    -> Result<T, FrontmatterError> {
+    let result = match format {
+        FrontmatterFormat::Yaml => serde_yaml::from_str(frontmatter).map_err(|e| e.to_string()),
+        FrontmatterFormat::Toml => toml::from_str(frontmatter).map_err(|e| e.to_string()),
+        FrontmatterFormat::Json => serde_json::from_str(frontmatter).map_err(|e| e.to_string()),
+    };
+    result.map_err(FrontmatterError::Deserialize)
+}
+
+/// parses the frontmatter of `source` into a `T`,
+/// and returns it with the remaining markdown body.
+/// A missing or empty frontmatter gives `None`,
+/// an opening delimiter without a closing one gives [`FrontmatterError::Unterminated`]
+#[cfg(feature="frontmatter")]
+pub fn parse_frontmatter<T: DeserializeOwned>(source: &str)
+    -> Result<(Option<T>, &str), FrontmatterError> {
+    let (frontmatter, body) = split_frontmatter(source);
+    let value = match frontmatter {
+        Some((format, f)) if !f.trim().is_empty() => Some(deserialize(format, f)?),
+        Some(_) => None,
+        None => match frontmatter_error(source) {
+            Some((_, _, error)) => return Err(error),
+            None => None
+        }
+    };
+    Ok((value, body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use FrontmatterFormat::*;
+
+    #[test]
+    fn split(){
+        let source = "---\ntitle: hello\n---\n# Hello";
+        assert_eq!(split_frontmatter(source), (Some((Yaml, "title: hello\n")), "# Hello"));
+    }
+
+    #[test]
+    fn split_formats(){
+        let source = "+++\ntitle = 'hello'\n+++\nbody";
+        assert_eq!(split_frontmatter(source), (Some((Toml, "title = 'hello'\n")), "body"));
+        let source = "{\n  \"title\": \"hello\"\n}\nbody";
+        assert_eq!(split_frontmatter(source), (Some((Json, "{\n  \"title\": \"hello\"\n}\n")), "body"));
+    }
+
+    #[test]
+    fn split_edge_cases(){
+        assert_eq!(split_frontmatter("# Hello"), (None, "# Hello"));
+        assert_eq!(split_frontmatter("{a} b\n}"), (None, "{a} b\n}"));
+        assert_eq!(split_frontmatter("---\n---\nbody"), (Some((Yaml, "")), "body"));
+        assert_eq!(split_frontmatter("---\r\na: 1\r\n---\r\n"), (Some((Yaml, "a: 1\r\n")), ""));
+        // without a closing delimiter, the source is plain markdown
+        assert_eq!(split_frontmatter("---\ntitle: hello\n"), (None, "---\ntitle: hello\n"));
+        assert_eq!(split_frontmatter("+++\ntitle = 1\n---\n"), (None, "+++\ntitle = 1\n---\n"));
+        assert_eq!(FrontmatterFormat::detect("---\ntext\n"), None);
+        assert_eq!(FrontmatterFormat::detect("---\ntitle: hello\n...\n"), Some(Yaml));
+    }
+
+    #[test]
+    fn unterminated(){
+        assert_eq!(frontmatter_error("---\ntitle: hello\n"), Some((Yaml, 0..3, FrontmatterError::Unterminated)));
+        assert_eq!(frontmatter_error("{\n\"a\": 1\n"), Some((Json, 0..1, FrontmatterError::Unterminated)));
+        assert_eq!(frontmatter_error("---\n---\nbody"), None);
+        assert_eq!(frontmatter_error("# Hello\n"), None);
+    }
+
+    #[cfg(feature="frontmatter")]
+    #[test]
+    fn deserialization_errors(){
+        use serde::de::IgnoredAny;
+
+        assert!(matches!(
+            frontmatter_error("---\na: [1\n---\nbody"),
+            Some((Yaml, range, FrontmatterError::Deserialize(_))) if range == (0..14)
+        ));
+        assert_eq!(frontmatter_error("---\na: 1\n---\nbody"), None);
+        assert_eq!(parse_frontmatter::<IgnoredAny>("+++\na = 1\n"), Err(FrontmatterError::Unterminated));
+        assert!(parse_frontmatter::<IgnoredAny>("+++\na = 1\n+++\n").is_ok());
+    }
+}
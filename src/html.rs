@@ -0,0 +1,694 @@
+use core::ops::Range;
+use std::cell::RefCell;
+
+use crate::{
+    render_markdown,
+    ComponentCreationError,
+    Context,
+    CowStr,
+    ElementAttributes,
+    HtmlElement,
+    ImageAttributes,
+    ImageLoading,
+    LinkDescription,
+    MarkdownProps,
+    MdComponentProps,
+    Options,
+};
+
+/// escapes the html special characters of `text`,
+/// so that it can be used as text or as an attribute value
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// the html tag of `e`, and its attributes
+fn tag(e: HtmlElement) -> (String, Vec<(String, String)>) {
+    use HtmlElement::*;
+    let attribute = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+    let (name, attributes) = match e {
+        Div => ("div", vec![]),
+        Span => ("span", vec![]),
+        Paragraph => ("p", vec![]),
+        BlockQuote => ("blockquote", vec![]),
+        Ul => ("ul", vec![]),
+        Ol(1) => ("ol", vec![]),
+        Ol(start) => ("ol", attribute("start", &start.to_string())),
+        Li => ("li", vec![]),
+        Heading(level) => return (format!("h{}", level.clamp(1, 6)), vec![]),
+        Table => ("table", vec![]),
+        Thead => ("thead", vec![]),
+        Trow => ("tr", vec![]),
+        Tcell => ("td", vec![]),
+        TheadCell => ("th", vec![]),
+        Italics => ("em", vec![]),
+        Bold => ("strong", vec![]),
+        StrikeThrough => ("s", vec![]),
+        Del => ("del", vec![]),
+        Pre => ("pre", vec![]),
+        Code => ("code", vec![]),
+        Button => ("button", vec![]),
+        Sup => ("sup", vec![]),
+        Sub => ("sub", vec![]),
+        Mark => ("mark", vec![]),
+        Ins => ("ins", vec![]),
+        Kbd => ("kbd", vec![]),
+        Figure => ("figure", vec![]),
+        Figcaption => ("figcaption", vec![]),
+        Caption => ("caption", vec![]),
+        Footer => ("footer", vec![]),
+        Cite => ("cite", vec![]),
+        Abbr => ("abbr", vec![]),
+        DefinitionList => ("dl", vec![]),
+        DefinitionTerm => ("dt", vec![]),
+        DefinitionDetails => ("dd", vec![]),
+        Details(true) => ("details", attribute("open", "")),
+        Details(false) => ("details", vec![]),
+        Summary => ("summary", vec![]),
+        Math(true) => ("math", attribute("display", "block")),
+        Math(false) => ("math", vec![]),
+    };
+    (name.to_string(), attributes)
+}
+
+/// writes `attributes` as html attributes, each one preceded by a space.
+/// Attributes with an empty value are written without value, like `disabled`
+fn write_attributes(attributes: impl IntoIterator<Item=(String, String)>) -> String {
+    attributes.into_iter()
+        .map(|(name, value)| match value.is_empty() {
+            true => format!(" {name}"),
+            false => format!(" {name}=\"{}\"", escape(&value)),
+        })
+        .collect()
+}
+
+/// the html attributes of `attributes`, preceded by `extra`
+fn element_attributes(extra: Vec<(String, String)>, attributes: ElementAttributes<()>) -> String {
+    let mut all = extra;
+    all.extend(attributes.id.map(|id| ("id".to_string(), id)));
+    if !attributes.classes.is_empty() {
+        all.push(("class".to_string(), attributes.classes.join(" ")));
+    }
+    all.extend(attributes.style.map(|style| ("style".to_string(), style.into_owned())));
+    all.extend(attributes.attributes);
+    write_attributes(all)
+}
+
+#[derive(Clone, Copy)]
+/// a [`Context`] rendering markdown to an html `String`, without a DOM,
+/// for server side rendering or tests.
+/// The handlers do nothing, and there are no custom components.
+/// The links mounted with [`Context::mount_dynamic_link_with`] are collected
+/// in `links`, see [`render_html`]
+pub struct HtmlContext<'a> {
+    /// the properties of the markdown
+    pub props: MarkdownProps<'a>,
+    /// the `<link>` elements mounted so far
+    pub links: &'a RefCell<Vec<String>>,
+}
+
+impl<'a> Context<'a, 'a> for HtmlContext<'a> {
+    type View = String;
+    type Handler<T: 'a> = ();
+    type MouseEvent = ();
+
+    fn props(self) -> MarkdownProps<'a> {
+        self.props
+    }
+
+    fn set_frontmatter(self, _frontmatter: String) {}
+
+    fn render_links(self, _link: LinkDescription<String>) -> Result<String, String> {
+        Err("no custom links".to_string())
+    }
+
+    fn call_handler<T>(_callback: &(), _input: T) {}
+
+    fn make_md_handler(self, _position: Range<usize>, _stop_propagation: bool) {}
+
+    #[cfg(feature="debug")]
+    fn send_debug_info(self, _info: Vec<String>) {}
+
+    fn el_with_attributes(self, e: HtmlElement, inside: String, attributes: ElementAttributes<()>) -> String {
+        let (name, extra) = tag(e);
+        format!("<{name}{}>{inside}</{name}>", element_attributes(extra, attributes))
+    }
+
+    fn el_span_with_inner_html(self, inner_html: String, attributes: ElementAttributes<()>) -> String {
+        self.el_with_attributes(HtmlElement::Span, inner_html, attributes)
+    }
+
+    fn el_with_inner_html(self, e: HtmlElement, inner_html: String, attributes: ElementAttributes<()>) -> String {
+        self.el_with_attributes(e, inner_html, attributes)
+    }
+
+    fn el_hr(self, attributes: ElementAttributes<()>) -> String {
+        format!("<hr{}>", element_attributes(vec![], attributes))
+    }
+
+    fn el_br(self) -> String {
+        "<br>".to_string()
+    }
+
+    fn el_fragment(self, children: Vec<String>) -> String {
+        children.concat()
+    }
+
+    fn el_a_with_attributes(self, children: String, href: String, attributes: ElementAttributes<()>) -> String {
+        let href = vec![("href".to_string(), href)];
+        format!("<a{}>{children}</a>", element_attributes(href, attributes))
+    }
+
+    fn el_img_with_attributes(self, src: String, alt: String, attributes: ImageAttributes) -> String {
+        let mut optional = Vec::new();
+        optional.extend(attributes.width.map(|width| ("width".to_string(), width.to_string())));
+        optional.extend(attributes.height.map(|height| ("height".to_string(), height.to_string())));
+        if attributes.loading == ImageLoading::Lazy {
+            optional.push(("loading".to_string(), "lazy".to_string()));
+            optional.push(("decoding".to_string(), "async".to_string()));
+        }
+        optional.extend(attributes.title.map(|title| ("title".to_string(), title)));
+        optional.extend(attributes.aria_label.map(|label| ("aria-label".to_string(), label)));
+        if attributes.presentation {
+            optional.push(("role".to_string(), "presentation".to_string()));
+        }
+        optional.extend(attributes.crossorigin.map(|c| ("crossorigin".to_string(), c.as_str().to_string())));
+        optional.extend(attributes.referrer_policy.map(|policy| ("referrerpolicy".to_string(), policy)));
+        // the alt is always written, as an empty alt marks decorative images
+        format!("<img src=\"{}\" alt=\"{}\"{}>", escape(&src), escape(&alt), write_attributes(optional))
+    }
+
+    fn el_text(self, text: CowStr<'a>) -> String {
+        escape(&text)
+    }
+
+    fn el_input_checkbox(self, checked: bool, attributes: ElementAttributes<()>) -> String {
+        let mut extra = vec![("type".to_string(), "checkbox".to_string())];
+        if checked {
+            extra.push(("checked".to_string(), String::new()));
+        }
+        format!("<input{}>", element_attributes(extra, attributes))
+    }
+
+    fn mount_dynamic_link_with(self, rel: &str, href: &str, integrity: Option<&str>, crossorigin: Option<&str>) {
+        let mut attributes = vec![
+            ("rel".to_string(), rel.to_string()),
+            ("href".to_string(), href.to_string()),
+        ];
+        attributes.extend(integrity.map(|i| ("integrity".to_string(), i.to_string())));
+        attributes.extend(crossorigin.map(|c| ("crossorigin".to_string(), c.to_string())));
+        let link = format!("<link{}>", write_attributes(attributes));
+        let mut links = self.links.borrow_mut();
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+
+    fn has_custom_component(self, _name: &str) -> bool {
+        false
+    }
+
+    fn render_custom_component(self, name: &str, _input: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+        Err(name.into())
+    }
+
+    fn has_custom_links(self) -> bool {
+        false
+    }
+}
+
+/// renders `source` to an html string with [`HtmlContext`].
+/// The links mounted while rendering, like the katex stylesheet,
+/// are placed before the document
+pub fn render_html(source: &str, props: MarkdownProps) -> String {
+    let links = RefCell::new(Vec::new());
+    let cx = HtmlContext {
+        props,
+        links: &links,
+    };
+    let body = render_markdown(cx, source);
+    links.into_inner().concat() + &body
+}
+
+/// calls `f` with an [`HtmlContext`] rendering with `props`,
+/// for the tests of the functions taking a context
+#[cfg(test)]
+pub(crate) fn with_html_context<T>(props: MarkdownProps, f: impl FnOnce(HtmlContext) -> T) -> T {
+    let links = RefCell::new(Vec::new());
+    f(HtmlContext {
+        props,
+        links: &links,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// the options of [`markdown_to_html`], a simpler and owned
+/// version of the most used [`MarkdownProps`].
+/// By default, the tables, footnotes, strikethrough, task lists and
+/// smart punctuation are enabled, and the math, wikilinks and
+/// other options are disabled
+pub struct HtmlRenderOptions {
+    /// parse the tables
+    pub tables: bool,
+    /// parse the footnotes
+    pub footnotes: bool,
+    /// parse the strikethrough, like `~~text~~`
+    pub strikethrough: bool,
+    /// parse the task lists, like `- [x] done`
+    pub task_lists: bool,
+    /// parse the math, like `$x^2$`.
+    /// Off by default, so that prices like `$5` stay text
+    pub math: bool,
+    /// turn the straight quotes and dashes into typographic ones
+    pub smart_punctuation: bool,
+    /// parse the wikilinks, like `[[Page Name]]`
+    pub wikilinks: bool,
+    /// see [`MarkdownProps::hard_line_breaks`]
+    pub hard_line_breaks: bool,
+    /// see [`MarkdownProps::sanitize_html`]
+    pub sanitize_html: bool,
+    /// see [`MarkdownProps::open_links_in_new_tab`]
+    pub open_links_in_new_tab: bool,
+    /// see [`MarkdownProps::base_url`]
+    pub base_url: Option<String>,
+}
+
+impl Default for HtmlRenderOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            task_lists: true,
+            math: false,
+            smart_punctuation: true,
+            wikilinks: false,
+            hard_line_breaks: false,
+            sanitize_html: false,
+            open_links_in_new_tab: false,
+            base_url: None,
+        }
+    }
+}
+
+impl HtmlRenderOptions {
+    /// the options of the parser, on top of all the other options
+    fn parse_options(&self) -> Options {
+        let mut options = Options::all();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.task_lists);
+        options.set(Options::ENABLE_MATH, self.math);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options
+    }
+}
+
+/// `markdown_to_html(source, options)` renders `source` to an html string,
+/// without implementing [`Context`], like with [`render_html`].
+/// Use [`render_html`] for the options missing from [`HtmlRenderOptions`]
+pub fn markdown_to_html(source: &str, options: &HtmlRenderOptions) -> String {
+    let parse_options = options.parse_options();
+    let props = MarkdownProps {
+        parse_options: Some(&parse_options),
+        wikilinks: options.wikilinks,
+        hard_line_breaks: options.hard_line_breaks,
+        sanitize_html: options.sanitize_html,
+        open_links_in_new_tab: options.open_links_in_new_tab,
+        base_url: options.base_url.as_deref(),
+        ..Default::default()
+    };
+    render_html(source, props)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CrossOrigin;
+
+    #[test]
+    fn elements(){
+        let links = RefCell::new(Vec::new());
+        let cx = HtmlContext {
+            props: MarkdownProps::default(),
+            links: &links,
+        };
+        let attributes = ElementAttributes {
+            id: Some("a\"b".to_string()),
+            classes: vec!["x".into(), "y".into()],
+            attributes: vec![("hidden".to_string(), String::new())],
+            ..Default::default()
+        };
+        assert_eq!(
+            cx.el_with_attributes(HtmlElement::Ol(3), cx.el_text("<li>".into()), attributes),
+            "<ol start=\"3\" id=\"a&quot;b\" class=\"x y\" hidden>&lt;li&gt;</ol>"
+        );
+        assert_eq!(cx.el_img("a.png".to_string(), String::new()), "<img src=\"a.png\" alt=\"\">");
+        assert_eq!(cx.el_input_checkbox(true, Default::default()), "<input type=\"checkbox\" checked>");
+        assert_eq!(
+            cx.el_table_cell("a".to_string(), true, crate::Alignment::Center, Default::default()),
+            "<th style=\"text-align: center\">a</th>"
+        );
+
+        cx.mount_dynamic_link_with("stylesheet", "s.css", None, None);
+        cx.mount_dynamic_link("stylesheet", "s.css", "", "");
+        assert_eq!(links.into_inner(), vec!["<link rel=\"stylesheet\" href=\"s.css\">"]);
+    }
+
+    #[test]
+    fn katex_stylesheet_without_integrity(){
+        let stylesheet = crate::KatexStylesheet {
+            url: "k.css".to_string(),
+            integrity: None,
+            crossorigin: Some("anonymous".to_string()),
+        };
+        let props = MarkdownProps {
+            katex_stylesheet: Some(&stylesheet),
+            ..Default::default()
+        };
+        assert!(render_html("$x$", props).starts_with(
+            "<link rel=\"stylesheet\" href=\"k.css\" crossorigin=\"anonymous\">"
+        ));
+    }
+
+    #[test]
+    fn lists(){
+        let props = MarkdownProps {
+            ordered_list_class: Some("roman"),
+            ..Default::default()
+        };
+        assert_eq!(render_html("5. a", props), "<ol start=\"5\" class=\"roman\"><li><span>a</span></li></ol>");
+        assert_eq!(render_html("1. a", props), "<ol class=\"roman\"><li><span>a</span></li></ol>");
+        assert_eq!(render_html("- a", props), "<ul><li><span>a</span></li></ul>");
+    }
+
+    #[test]
+    fn eager_images(){
+        let props = MarkdownProps {
+            image_loading: ImageLoading::Lazy,
+            eager_images: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_html("![](a.png)![](b.png)![](c.png)", props),
+            "<p><img src=\"a.png\" alt=\"\"><img src=\"b.png\" alt=\"\">\
+            <img src=\"c.png\" alt=\"\" loading=\"lazy\" decoding=\"async\"></p>"
+        );
+    }
+
+    #[test]
+    fn image_sizes_in_markdown(){
+        let props = MarkdownProps::default();
+        // the url and its size are between angle brackets
+        assert_eq!(
+            render_html("![a](<img.png =200x>)", props),
+            "<p><img src=\"img.png\" alt=\"a\" width=\"200\"></p>"
+        );
+        assert_eq!(
+            render_html("![](<img.png =200x100>)", props),
+            "<p><img src=\"img.png\" alt=\"\" width=\"200\" height=\"100\"></p>"
+        );
+        assert_eq!(
+            render_html("![](<img.png =x100>)", props),
+            "<p><img src=\"img.png\" alt=\"\" height=\"100\"></p>"
+        );
+        assert_eq!(render_html("![](<img.png =ax1>)", props), "<p><img src=\"img.png =ax1\" alt=\"\"></p>");
+        // without them, it is not an image
+        assert!(!render_html("![a](img.png =200x)", props).contains("<img"));
+    }
+
+    #[test]
+    fn table_alignment(){
+        // the parser fills the short rows with empty cells, and drops the extra cells
+        let source = "| a | b | c |\n|:--|---|--:|\n| 1 |\n| 1 | 2 | 3 | 4 |\n";
+        assert_eq!(
+            render_html(source, MarkdownProps::default()),
+            "<table><thead>\
+            <th style=\"text-align: left\" scope=\"col\"><span>a</span></th>\
+            <th scope=\"col\"><span>b</span></th>\
+            <th style=\"text-align: right\" scope=\"col\"><span>c</span></th>\
+            </thead>\
+            <tr><td style=\"text-align: left\"><span>1</span></td><td></td><td style=\"text-align: right\"></td></tr>\
+            <tr><td style=\"text-align: left\"><span>1</span></td><td><span>2</span></td>\
+            <td style=\"text-align: right\"><span>3</span></td></tr>\
+            </table>"
+        );
+    }
+
+    #[test]
+    fn merged_table_cells(){
+        let props = MarkdownProps {
+            table_spans: true,
+            ..Default::default()
+        };
+        // an empty cell merges with the cell on its left,
+        // a `^` cell with the cell above it
+        let source = "| a | b |\n|---|---|\n| 1 | |\n| 2 | 3 |\n| ^ | 4 |\n";
+        assert_eq!(
+            render_html(source, props),
+            "<table><thead>\
+            <th scope=\"col\"><span>a</span></th><th scope=\"col\"><span>b</span></th>\
+            </thead>\
+            <tr><td colspan=\"2\"><span>1</span></td></tr>\
+            <tr><td rowspan=\"2\"><span>2</span></td><td><span>3</span></td></tr>\
+            <tr><td><span>4</span></td></tr>\
+            </table>"
+        );
+    }
+
+    #[test]
+    fn aria_attributes(){
+        let props = MarkdownProps {
+            heading_anchor_links: true,
+            ..Default::default()
+        };
+        let html = render_html("## Title\n\n| a |\n|---|\n\nb[^n]\n\n[^n]: note\n", props);
+        // the anchor symbol of the heading is decorative
+        assert!(html.contains(
+            "<a href=\"#title\" class=\"heading-anchor\" aria-label=\"Link to Title\">\
+            <span aria-hidden=\"true\">#</span></a>"
+        ));
+        assert!(html.contains("<th scope=\"col\"><span>a</span></th>"));
+        assert!(html.contains("<div class=\"footnotes\" aria-label=\"Footnotes\">"));
+        assert!(html.contains(
+            "<a href=\"#fnref-n\" class=\"footnote-backref\" aria-label=\"Back to reference 1\">\
+            <span aria-hidden=\"true\">↩</span></a>"
+        ));
+    }
+
+    #[test]
+    fn definition_lists(){
+        let props = MarkdownProps {
+            definition_lists: true,
+            ..Default::default()
+        };
+        let source = "Term\nOther term\n: a definition\n  that continues\n: other definition\n";
+        assert_eq!(
+            render_html(source, props),
+            "<dl><dt><span>Term</span></dt><dt><span>Other term</span></dt>\
+            <dd><span>a definition</span> <span>that continues</span></dd>\
+            <dd><span>other definition</span></dd></dl>"
+        );
+        // a paragraph that doesn't end with a definition stays a paragraph
+        assert_eq!(
+            render_html("Term\n: definition\nnot a term\n", props),
+            "<p><span>Term</span> <span>: definition</span> <span>not a term</span></p>"
+        );
+        // an indented list belongs to the definition before it
+        assert_eq!(
+            render_html("Term\n: definition\n  - nested\n  - other\n\n- outside\n", props),
+            "<dl><dt><span>Term</span></dt>\
+            <dd><span>definition</span><ul><li><span>nested</span></li><li><span>other</span></li></ul></dd></dl>\
+            <ul><li><span>outside</span></li></ul>"
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_limited(){
+        let props = MarkdownProps {
+            max_nesting_depth: Some(32),
+            ..Default::default()
+        };
+        // each item is nested in the one before it
+        let lists: String = (0..3_000).map(|i| format!("{}- x\n", "  ".repeat(i))).collect();
+        let html = render_html(&lists, props);
+        assert_eq!(html.matches("<ul>").count(), 16);
+        assert!(html.ends_with("</ul>"));
+        let quotes = format!("{} deep\n", ">".repeat(10_000));
+        let html = render_html(&quotes, props);
+        assert_eq!(html.matches("<blockquote>").count(), 32);
+        assert!(html.contains("deep"));
+    }
+
+    #[test]
+    fn remote_images(){
+        let props = MarkdownProps {
+            image_crossorigin: Some(CrossOrigin::Anonymous),
+            image_referrer_policy: Some("no-referrer"),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_html("![](https://cdn.example.com/x.png)", props),
+            "<p><img src=\"https://cdn.example.com/x.png\" alt=\"\" crossorigin=\"anonymous\" referrerpolicy=\"no-referrer\"></p>"
+        );
+        assert_eq!(
+            render_html("![](data:image/png;base64,AA==)", props),
+            "<p><img src=\"data:image/png;base64,AA==\" alt=\"\"></p>"
+        );
+    }
+
+    #[test]
+    fn image_accessibility(){
+        let props = MarkdownProps {
+            decorative_images: true,
+            image_title_as_aria_label: true,
+            ..Default::default()
+        };
+        assert_eq!(render_html("![](x.png)", props), "<p><img src=\"x.png\" alt=\"\" role=\"presentation\"></p>");
+        assert_eq!(
+            render_html("![a](x.png \"T\")", props),
+            "<p><img src=\"x.png\" alt=\"a\" title=\"T\" aria-label=\"T\"></p>"
+        );
+    }
+
+    #[test]
+    fn tight_and_loose_lists(){
+        // the examples of the lists section of the CommonMark spec.
+        // The html has no line breaks between the tags,
+        // and the text is rendered in spans
+        let examples = [
+            // blank lines between the items
+            ("- a\n- b\n\n- c", "<ul><li><p><span>a</span></p></li><li><p><span>b</span></p></li><li><p><span>c</span></p></li></ul>"),
+            ("* a\n*\n\n* c", "<ul><li><p><span>a</span></p></li><li></li><li><p><span>c</span></p></li></ul>"),
+            // items with several blocks
+            ("- a\n- b\n\n  c\n- d", "<ul><li><p><span>a</span></p></li><li><p><span>b</span></p><p><span>c</span></p></li><li><p><span>d</span></p></li></ul>"),
+            ("- a\n- b\n\n  [ref]: /url\n- d", "<ul><li><p><span>a</span></p></li><li><p><span>b</span></p></li><li><p><span>d</span></p></li></ul>"),
+            ("* a\n  > b\n  >\n* c", "<ul><li><span>a</span><blockquote><p><span>b</span></p></blockquote></li><li><span>c</span></li></ul>"),
+            ("* foo\n  * bar\n\n  baz", "<ul><li><p><span>foo</span></p><ul><li><span>bar</span></li></ul><p><span>baz</span></p></li></ul>"),
+            // a blank line only inside a nested list
+            ("- a\n  - b\n\n    c\n- d", "<ul><li><span>a</span><ul><li><p><span>b</span></p><p><span>c</span></p></li></ul></li><li><span>d</span></li></ul>"),
+            // nested lists
+            ("- a", "<ul><li><span>a</span></li></ul>"),
+            ("- a\n  - b", "<ul><li><span>a</span><ul><li><span>b</span></li></ul></li></ul>"),
+            ("- a\n  - b\n  - c\n\n- d\n  - e\n  - f", "<ul><li><p><span>a</span></p><ul><li><span>b</span></li><li><span>c</span></li></ul></li><li><p><span>d</span></p><ul><li><span>e</span></li><li><span>f</span></li></ul></li></ul>"),
+        ];
+        for (markdown, html) in examples {
+            assert_eq!(markdown_to_html(markdown, &HtmlRenderOptions::default()), html, "{markdown:?}");
+        }
+    }
+
+    #[test]
+    fn task_list_items(){
+        let props = MarkdownProps::default();
+        let html = render_html("- [x] a\n- c", props);
+        assert!(html.starts_with("<ul><li class=\"task-list-item checked\">"));
+        assert!(html.ends_with("<li><span>c</span></li></ul>"));
+        // in a loose list, the marker is in the paragraph of the item
+        assert!(render_html("- [ ] b\n\n- c", props).contains("<li class=\"task-list-item unchecked\">"));
+    }
+
+    #[test]
+    fn source_lines(){
+        let props = MarkdownProps {
+            source_line_attributes: true,
+            ..Default::default()
+        };
+        // only the top-level elements have a line
+        assert_eq!(
+            render_html("a\n\n> b\n\n```\nc\n```\n\n---\n\n<div>d</div>\n", props),
+            "<p data-source-line=\"1\"><span>a</span></p>\
+            <blockquote data-source-line=\"3\"><p><span>b</span></p></blockquote>\
+            <pre class=\"code-block\" data-source-line=\"5\"><code>c</code></pre>\
+            <hr data-source-line=\"9\">\
+            <span data-source-line=\"11\"><div>d</div>\n</span>"
+        );
+        #[cfg(feature="maths")]
+        assert!(render_html("$$x$$", props).contains("data-source-line=\"1\""));
+    }
+
+    #[test]
+    fn break_markers(){
+        let props = MarkdownProps {
+            show_break_markers: true,
+            ..Default::default()
+        };
+        // the markers are not read by screen readers
+        assert_eq!(
+            render_html("a\nb  \nc", props),
+            "<p><span>a</span>\
+            <span class=\"break-marker soft-break-marker\" aria-hidden=\"true\">↵</span> <span>b</span>\
+            <span class=\"break-marker hard-break-marker\" aria-hidden=\"true\">¶</span><br><span>c</span></p>"
+        );
+    }
+
+    #[test]
+    fn inline_footnote_numbers(){
+        let props = MarkdownProps {
+            inline_footnotes: true,
+            ..Default::default()
+        };
+        let source = "a[^x] b^[inline] c[^1]\n\n[^x]: X\n\n[^1]: user";
+        let html = render_html(source, props);
+        // the inline footnotes are numbered with the other ones, in order
+        let references = [
+            "<a href=\"#fn-x\">1</a>",
+            "<a href=\"#inline-fn-1\">2</a>",
+            "<a href=\"#fn-1\">3</a>",
+        ];
+        let positions: Vec<_> = references.iter().map(|r| html.find(r).expect(r)).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert!(html.contains("<a href=\"#inline-fnref-1\" class=\"footnote-backref\" aria-label=\"Back to reference 2\">"));
+        assert!(!html.contains('^'));
+        assert!(html.contains("<span>inline</span>"));
+        assert!(html.contains("<span>user</span>"));
+    }
+
+    #[test]
+    fn thematic_breaks(){
+        let props = MarkdownProps {
+            hr_class: Some("divider"),
+            ..Default::default()
+        };
+        let html = render_html("a\n\n---\n\n***\n\n___\n\n  - - -\n", props);
+        assert_eq!(html, format!("<p><span>a</span></p>{}", "<hr class=\"divider\">".repeat(4)));
+    }
+
+    #[test]
+    fn render_options(){
+        let options = HtmlRenderOptions::default().parse_options();
+        assert!(!options.contains(Options::ENABLE_MATH));
+        assert!(options.contains(Options::ENABLE_TABLES));
+        let options = HtmlRenderOptions {
+            math: true,
+            ..Default::default()
+        };
+        assert_eq!(options.parse_options(), Options::all());
+    }
+
+    #[test]
+    fn entities_are_decoded_once(){
+        // the parser decodes the entities of the text, an escaped one stays literal
+        let html = render_html("&shy; &amp;copy;", MarkdownProps::default());
+        assert!(html.contains('\u{ad}'));
+        assert!(html.contains("&amp;copy;"));
+        assert!(!html.contains('©'));
+    }
+
+    #[test]
+    fn markdown_to_html_without_math(){
+        let html = markdown_to_html("from $5 to $10", &HtmlRenderOptions::default());
+        assert_eq!(html, "<p><span>from $5 to $10</span></p>");
+    }
+}
@@ -0,0 +1,203 @@
+use core::ops::Range;
+use std::collections::VecDeque;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CowStr};
+
+use crate::utils::locate;
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// the label of the `n`-th inline footnote of a document.
+/// It starts with `^`, so that it can't be the label of a footnote
+/// written like `[^label]`, and so that it gets ids apart from them
+/// (see [`crate::utils::footnote_id`])
+fn inline_label(n: usize) -> String {
+    format!("^{n}")
+}
+
+/// the position of the bracket closing the ones opened before `s`,
+/// `depth` being the number of opened brackets.
+/// If they are not closed in `s`, `depth` is updated for the next text
+fn closing_bracket(s: &str, depth: &mut usize) -> Option<usize> {
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => *depth += 1,
+            ']' if *depth == 1 => return Some(i),
+            ']' => *depth -= 1,
+            _ => ()
+        }
+    }
+    None
+}
+
+/// a text event with the content of `s`, located at `part`
+/// of the text event at `range` if its text is written as is in the source
+fn text_part<'a>(source: &str, s: &str, range: &Range<usize>, part: Range<usize>) -> (Event<'a>, Range<usize>) {
+    let range = locate(source, s, range.clone(), part.clone());
+    (Event::Text(CowStr::from(s[part].to_string())), range)
+}
+
+/// turns the inline footnotes, like `^[some note]`, into references
+/// to footnotes numbered like the other ones, whose definitions
+/// are added at the end of `events`.
+/// The note can contain formatting, like emphasis or links,
+/// but not another inline footnote, and ends with its paragraph.
+/// The text of the code blocks is skipped
+pub fn convert_inline_footnotes<'a>(source: &str, events: Events<'a>) -> Events<'a> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut definitions = Vec::new();
+    let mut queue: VecDeque<_> = events.into();
+    let mut in_code_block = false;
+
+    while let Some((event, range)) = queue.pop_front() {
+        let s = match &event {
+            Event::Text(s) if !in_code_block => s.to_string(),
+            _ => {
+                match &event {
+                    Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => in_code_block = true,
+                    Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => in_code_block = false,
+                    _ => ()
+                }
+                result.push((event, range));
+                continue
+            }
+        };
+        // the marker can be split between two text events, like `^` and `[note]`
+        if let Some((Event::Text(next), next_range)) = queue.front() {
+            if s.ends_with('^') && next.starts_with('[') && next_range.start == range.end {
+                let merged = (Event::Text(format!("{s}{next}").into()), range.start..next_range.end);
+                queue[0] = merged;
+                continue
+            }
+        }
+        let Some(start) = s.find("^[") else {
+            result.push((event, range));
+            continue
+        };
+
+        // the content of the note, until its closing bracket
+        let mut depth = 1;
+        let mut note = Vec::new();
+        let mut consumed = Vec::new();
+        let mut closing = None;
+        match closing_bracket(&s[start+2..], &mut depth) {
+            Some(end) => closing = Some((s.clone(), range.clone(), start + 2 + end)),
+            None => {
+                note.push(text_part(source, &s, &range, start+2..s.len()));
+                // the depth of the elements of the note
+                let mut nesting = 0usize;
+                while let Some((event, r)) = queue.pop_front() {
+                    consumed.push((event.clone(), r.clone()));
+                    match &event {
+                        Event::Text(t) if nesting == 0 => {
+                            if let Some(end) = closing_bracket(t, &mut depth) {
+                                closing = Some((t.to_string(), r, end));
+                                break
+                            }
+                        },
+                        Event::Start(_) => nesting += 1,
+                        Event::End(_) if nesting == 0 => break,
+                        Event::End(_) => nesting -= 1,
+                        _ => ()
+                    }
+                    note.push((event, r));
+                }
+            }
+        }
+
+        let Some((text, text_range, end)) = closing else {
+            // the note is not closed: the marker is kept as text
+            result.push(text_part(source, &s, &range, 0..start+2));
+            queue.push_front(text_part(source, &s, &range, start+2..s.len()));
+            while let Some(event) = consumed.pop() {
+                queue.insert(1, event);
+            }
+            continue
+        };
+        let last = match consumed.is_empty() {
+            true => start+2..end,
+            false => 0..end,
+        };
+        if !last.is_empty() {
+            note.push(text_part(source, &text, &text_range, last));
+        }
+        note.retain(|(e, _)| !matches!(e, Event::Text(t) if t.is_empty()));
+
+        if start > 0 {
+            result.push(text_part(source, &s, &range, 0..start));
+        }
+        let label = inline_label(definitions.len() + 1);
+        let reference_range = range.start..text_range.end;
+        result.push((Event::FootnoteReference(label.clone().into()), reference_range.clone()));
+        if end + 1 < text.len() {
+            queue.push_front(text_part(source, &text, &text_range, end+1..text.len()));
+        }
+
+        definitions.push((Event::Start(Tag::FootnoteDefinition(label.into())), reference_range.clone()));
+        definitions.push((Event::Start(Tag::Paragraph), reference_range.clone()));
+        definitions.extend(note);
+        definitions.push((Event::End(TagEnd::Paragraph), reference_range.clone()));
+        definitions.push((Event::End(TagEnd::FootnoteDefinition), reference_range));
+    }
+    result.extend(definitions);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn events(events: Vec<Event<'static>>) -> Events<'static> {
+        events.into_iter().map(|e| (e, 0..0)).collect()
+    }
+
+    #[test]
+    fn inline_notes(){
+        let converted = convert_inline_footnotes("", events(vec![
+            Event::Text("a^[note ".into()),
+            Event::Start(Tag::Strong),
+            Event::Text("bold]".into()),
+            Event::End(TagEnd::Strong),
+            Event::Text(" [x]] b".into()),
+            Event::FootnoteReference("c".into()),
+        ]));
+        let label = || CowStr::from("^1");
+        assert_eq!(converted.into_iter().map(|(e, _)| e).collect::<Vec<_>>(), vec![
+            Event::Text("a".into()),
+            Event::FootnoteReference(label()),
+            Event::Text(" b".into()),
+            Event::FootnoteReference("c".into()),
+            Event::Start(Tag::FootnoteDefinition(label())),
+            Event::Start(Tag::Paragraph),
+            Event::Text("note ".into()),
+            Event::Start(Tag::Strong),
+            Event::Text("bold]".into()),
+            Event::End(TagEnd::Strong),
+            Event::Text(" [x]".into()),
+            Event::End(TagEnd::Paragraph),
+            Event::End(TagEnd::FootnoteDefinition),
+        ]);
+    }
+
+    #[test]
+    fn unclosed_notes(){
+        let original = events(vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text("a ^[b".into()),
+            Event::End(TagEnd::Paragraph),
+            Event::Start(Tag::Paragraph),
+            Event::Text("c]".into()),
+            Event::End(TagEnd::Paragraph),
+        ]);
+        let converted: Vec<_> = convert_inline_footnotes("", original).into_iter().map(|(e, _)| e).collect();
+        assert_eq!(converted, vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text("a ^[".into()),
+            Event::Text("b".into()),
+            Event::End(TagEnd::Paragraph),
+            Event::Start(Tag::Paragraph),
+            Event::Text("c]".into()),
+            Event::End(TagEnd::Paragraph),
+        ]);
+    }
+}
@@ -0,0 +1,176 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, MathMode};
+
+use crate::utils::is_verbatim;
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// the opening and closing delimiters of the latex math
+const DELIMITERS: [(&str, &str, MathMode); 2] = [
+    ("\\(", "\\)", MathMode::Inline),
+    ("\\[", "\\]", MathMode::Display),
+];
+
+/// returns true if the character at `i` in `s` is escaped by a backslash
+fn is_escaped(s: &str, i: usize) -> bool {
+    s[..i].bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
+/// finds the math written with latex delimiters in the raw markdown `s`,
+/// like `\(x^2\)` or `\[x^2\]`.
+/// Returns the position of each one with its delimiters, its content and its mode.
+/// The delimiters preceded by an escaped backslash, like `\\(`, are plain text
+fn find_math(s: &str) -> Vec<(Range<usize>, &str, MathMode)> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while let Some(j) = s[i..].find('\\') {
+        let start = i + j;
+        i = start + 1;
+        if is_escaped(s, start) {
+            continue
+        }
+        let Some((open, close, mode)) = DELIMITERS.iter().find(|(open, _, _)| s[start..].starts_with(open)) else {
+            continue
+        };
+        let content_start = start + open.len();
+        let end = s[content_start..].match_indices(close)
+            .map(|(k, _)| content_start + k)
+            .find(|&k| !is_escaped(s, k));
+        if let Some(end) = end {
+            let content = &s[content_start..end];
+            if !content.trim().is_empty() {
+                found.push((start..end + close.len(), content, *mode));
+            }
+            i = end + close.len();
+        }
+    }
+    found
+}
+
+/// turns the math written with latex delimiters, like `\(x^2\)` for inline math
+/// and `\[x^2\]` for display math, into math events.
+/// Markdown escapes the delimiters, so they are found in the `source`
+/// of the adjacent text, line breaks and math, outside of code blocks.
+/// The dollar math parsed inside of them is part of their content,
+/// but they can't contain other markup, like emphasis or code
+pub fn convert_latex_delimiters<'a>(source: &'a str, events: Events<'a>) -> Events<'a> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut run: Events<'a> = Vec::new();
+    let mut in_code_block = false;
+
+    for (event, range) in events {
+        match &event {
+            Event::Text(_) | Event::SoftBreak | Event::Math(..) if !in_code_block => {
+                run.push((event, range));
+                continue
+            },
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            _ => ()
+        }
+        push_run(source, std::mem::take(&mut run), &mut result);
+        result.push((event, range));
+    }
+    push_run(source, run, &mut result);
+    result
+}
+
+/// pushes the text, line breaks and math of `run` into `events`,
+/// with the math they contain
+fn push_run<'a>(source: &'a str, run: Events<'a>, events: &mut Events<'a>) {
+    let (Some((_, first)), Some((_, last))) = (run.first(), run.last()) else { return };
+    // the text of an escape starts after its backslash
+    let offset = match source[..first.start].ends_with('\\') {
+        true => first.start - 1,
+        false => first.start
+    };
+    let math = source.get(offset..last.end).map(find_math).unwrap_or_default();
+    if math.is_empty() {
+        events.extend(run);
+        return
+    }
+    let math: Vec<_> = math.into_iter()
+        .map(|(r, content, mode)| (offset + r.start..offset + r.end, content, mode))
+        .collect();
+
+    let mut next_math = math.iter().peekable();
+    for (event, range) in run {
+        // the math starting before this event
+        while let Some((r, content, mode)) = next_math.next_if(|(r, _, _)| r.start < range.start) {
+            events.push((Event::Math(*mode, (*content).into()), r.clone()));
+        }
+        let outside = |r: &Range<usize>| !math.iter().any(|(m, _, _)| m.start < r.end && r.start < m.end);
+        match event {
+            // the text can be cut where the math starts and ends
+            // if it is written as is in the source
+            Event::Text(s) if is_verbatim(source, &s, &range) => {
+                let mut start = range.start;
+                for (m, content, mode) in &math {
+                    if m.end <= start || range.end <= m.start {
+                        continue
+                    }
+                    if start < m.start {
+                        events.push((Event::Text(source[start..m.start].into()), start..m.start));
+                    }
+                    if range.start <= m.start {
+                        // the math starts inside of this text
+                        next_math.next_if(|(r, _, _)| r == m);
+                        events.push((Event::Math(*mode, (*content).into()), m.clone()));
+                    }
+                    start = m.end;
+                }
+                if start < range.end {
+                    events.push((Event::Text(source[start..range.end].into()), start..range.end));
+                }
+            },
+            event if outside(&range) => events.push((event, range)),
+            _ => ()
+        }
+    }
+    events.extend(next_math.map(|(r, content, mode)| (Event::Math(*mode, (*content).into()), r.clone())));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delimiters(){
+        let s = r"a \(x^2\) b \[\$y\$\] c \\(z\\) and \( unclosed";
+        let math: Vec<_> = find_math(s).into_iter()
+            .map(|(r, content, mode)| (&s[r], content, mode))
+            .collect();
+        assert_eq!(math, vec![
+            (r"\(x^2\)", "x^2", MathMode::Inline),
+            (r"\[\$y\$\]", r"\$y\$", MathMode::Display),
+        ]);
+    }
+
+    #[test]
+    fn events(){
+        let source = "a \\(x\\) b";
+        // the escapes of the delimiters start new text events
+        let events = vec![
+            (Event::Text("a ".into()), 0..2),
+            (Event::Text("(x".into()), 3..5),
+            (Event::Text(") b".into()), 6..9),
+        ];
+        assert_eq!(convert_latex_delimiters(source, events), vec![
+            (Event::Text("a ".into()), 0..2),
+            (Event::Math(MathMode::Inline, "x".into()), 2..7),
+            (Event::Text(" b".into()), 7..9),
+        ]);
+
+        // the dollar math inside of the delimiters is part of the math
+        let source = r"\[a $b$\]";
+        let events = vec![
+            (Event::Text("[a ".into()), 1..4),
+            (Event::Math(MathMode::Inline, "b".into()), 4..7),
+            (Event::Text("]".into()), 8..9),
+        ];
+        assert_eq!(convert_latex_delimiters(source, events), vec![
+            (Event::Math(MathMode::Display, "a $b$".into()), 0..9),
+        ]);
+    }
+}
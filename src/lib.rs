@@ -1,39 +1,107 @@
-use pulldown_cmark_wikilink::{ParserOffsetIter, LinkType, Event};
-pub use pulldown_cmark_wikilink::{Options, CowStr};
+use pulldown_cmark_wikilink::{ParserOffsetIter, LinkType, Tag, TagEnd};
+pub use pulldown_cmark_wikilink::{Alignment, Options, CowStr, Event};
 
 use core::ops::Range;
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 mod render;
-use render::Renderer;
+pub use render::EventRenderer;
+#[cfg(feature="syntect")]
+pub use render::code_theme_css;
 
 mod utils;
+pub use utils::strip_tracking_params;
 
 mod component;
 
+mod toc;
+pub use toc::{document_outline, extract_toc, OutlineNode, TocEntry};
 
+mod plain_text;
+pub use plain_text::to_plain_text;
+
+mod stats;
+pub use stats::{document_stats, DocumentStats, StatsOptions};
+
+mod code_fence;
+pub use code_fence::CodeFence;
+
+mod sanitize;
+mod linkify;
+
+mod latex_delimiters;
+
+mod dollar_math;
+
+mod broken_links;
+
+mod inline_footnotes;
+
+mod diagnostics;
+pub use diagnostics::{check_markdown, Diagnostic, Severity};
+
+mod cache;
+pub use cache::{RenderCache, IncrementalRender};
+
+mod streaming;
+pub use streaming::{StreamingRenderer, StreamingUpdate};
+
+#[cfg(feature="html")]
+mod html;
+#[cfg(feature="html")]
+pub use html::{markdown_to_html, render_html, HtmlContext, HtmlRenderOptions};
+
+mod frontmatter;
+pub use frontmatter::{split_frontmatter, FrontmatterError, FrontmatterFormat};
+#[cfg(feature="frontmatter")]
+pub use frontmatter::parse_frontmatter;
+
+
+/// the html attributes of an element.
+/// Every [`Context`] must render all of them onto the element
 pub struct ElementAttributes<H> {
-    pub classes: Vec<String>,
-    pub style: Option<String>,
-    pub on_click: Option<H>
+    /// the `id` attribute, used by heading anchors and footnotes
+    pub id: Option<String>,
+    /// the `class` attribute.
+    /// The fixed class names are borrowed, without allocation
+    pub classes: Vec<Cow<'static, str>>,
+    /// the `style` attribute
+    pub style: Option<Cow<'static, str>>,
+    /// the handler of the click events on the element
+    pub on_click: Option<H>,
+    /// the handler of the `mouseenter` events on the element
+    pub on_mouse_enter: Option<H>,
+    /// the handler of the `mouseleave` events on the element
+    pub on_mouse_leave: Option<H>,
+    /// other attributes, like `data-*` or `aria-label`,
+    /// applied after the ones above
+    pub attributes: Vec<(String, String)>,
 }
 
 impl<H> Default for ElementAttributes<H> {
     fn default() -> Self {
         Self {
+            id: None,
             style: None,
             classes: vec![],
-            on_click: None
+            on_click: None,
+            on_mouse_enter: None,
+            on_mouse_leave: None,
+            attributes: vec![],
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HtmlElement {
     Div,
     Span,
     Paragraph,
     BlockQuote,
     Ul,
+    /// an ordered list, starting at this number.
+    /// It must be rendered as the `start` attribute when it is not 1
     Ol(i32),
     Li,
     Heading(u8),
@@ -41,18 +109,86 @@ pub enum HtmlElement {
     Thead,
     Trow,
     Tcell,
+    /// a header cell, `th`
+    TheadCell,
     Italics,
     Bold,
+    /// an `s` element, for text that is no longer accurate
     StrikeThrough,
+    /// a `del` element, for deleted text
+    Del,
     Pre,
-    Code
+    Code,
+    Button,
+    Sup,
+    Sub,
+    Mark,
+    /// an `ins` element, for inserted text
+    Ins,
+    /// a `kbd` element, for keyboard keys
+    Kbd,
+    /// a `figure` element, around an image and its caption
+    Figure,
+    /// a `figcaption` element, the caption of a `figure`
+    Figcaption,
+    /// a `caption` element, the caption of a table,
+    /// rendered as the first child of the `table`
+    Caption,
+    /// a `footer` element, the attribution of a blockquote
+    Footer,
+    /// a `cite` element, the author or the source of a quote
+    Cite,
+    /// an `abbr` element, with its title in the attributes
+    Abbr,
+    DefinitionList,
+    DefinitionTerm,
+    DefinitionDetails,
+    /// a `details` element.
+    /// `Details(true)` is open by default
+    Details(bool),
+    Summary,
+    /// a MathML `math` element.
+    /// `Math(true)` is displayed as a block, `Math(false)` inline
+    Math(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// the kind of markdown element that was clicked.
+/// Text is described by the innermost element containing it,
+/// or by `Text` outside of any element
+pub enum MarkdownTag {
+    Text,
+    Paragraph,
+    Heading(u8),
+    BlockQuote,
+    ListItem,
+    TableCell,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    /// a link, with its resolved url
+    Link(String),
+    Image,
+    FootnoteDefinition,
+    FootnoteReference,
+    Code,
+    CodeBlock,
+    Math,
+    Html,
+    Rule,
+    TaskListMarker,
 }
 
 pub trait Context<'a, 'callback>: Copy + 'a
 where 'callback: 'a
 {
+    /// the rendered elements of the framework
     type View: Clone + 'callback;
+    /// a callback taking a `T`, like the click handlers
     type Handler<T: 'callback>: 'callback;
+    /// the event received by the mouse handlers,
+    /// like `web_sys::MouseEvent` in the browser, or `()` without a DOM.
+    /// The crate doesn't depend on any DOM binding itself
     type MouseEvent: 'static;
 
     /// get all the properties from the context
@@ -62,6 +198,15 @@ where 'callback: 'a
     /// present at the top of the markdown source
     fn set_frontmatter(self, frontmatter: String);
 
+    /// write the frontmatter present at the top of the markdown source,
+    /// with its `format`.
+    /// With the `frontmatter` feature, `parse_frontmatter` deserializes it before rendering.
+    /// By default, the format is dropped and `set_frontmatter` is called
+    fn set_frontmatter_with_format(self, format: FrontmatterFormat, frontmatter: String) {
+        let _ = format;
+        self.set_frontmatter(frontmatter)
+    }
+
     fn render_links(self, link: LinkDescription<Self::View>) 
         -> Result<Self::View, String>;
 
@@ -71,9 +216,78 @@ where 'callback: 'a
     /// creates a callback that will fire when the user clicks on markdown
     fn make_md_handler(self, position: Range<usize>, stop_propagation: bool) -> Self::Handler<Self::MouseEvent>;
 
+    /// creates a callback that will fire when the user clicks on markdown.
+    /// `tag` is the kind of element at `position`.
+    /// The text of a link is described by [`MarkdownTag::Link`] with its url,
+    /// which can be used to intercept the navigation.
+    /// By default, the tag is dropped and `make_md_handler` is called
+    fn make_md_tag_handler(self, position: Range<usize>, tag: MarkdownTag, stop_propagation: bool) -> Self::Handler<Self::MouseEvent> {
+        let _ = tag;
+        self.make_md_handler(position, stop_propagation)
+    }
+
+    /// creates a callback that will fire when the mouse enters
+    /// (if `enter` is true) or leaves a block of markdown,
+    /// like a paragraph or a heading.
+    /// By default, there is no such callback
+    fn make_md_hover_handler(self, position: Range<usize>, tag: MarkdownTag, enter: bool) -> Option<Self::Handler<Self::MouseEvent>> {
+        let _ = (position, tag, enter);
+        None
+    }
+
+    /// creates a callback that will fire when the user clicks on
+    /// the checkbox of a task list item, to toggle it.
+    /// `toggle` gives the new state of the checkbox and the position
+    /// of its marker, like `[ ]`, in the source.
+    /// By default, there is no such callback,
+    /// and the clicks are handled by [`Context::make_md_tag_handler`]
+    fn make_task_toggle_handler(self, toggle: TaskToggle) -> Option<Self::Handler<Self::MouseEvent>> {
+        let _ = toggle;
+        None
+    }
+
+    /// creates a callback for the links to `#id` when `anchor_scroll`
+    /// is set in the props, that scrolls to the element with the id `id`
+    /// as described by `scroll`, like with `scrollIntoView`.
+    /// It should prevent the default navigation.
+    /// By default, there is no such callback, and the browser jumps to the element
+    fn make_anchor_scroll_handler(self, id: String, scroll: AnchorScroll) -> Option<Self::Handler<Self::MouseEvent>> {
+        let _ = (id, scroll);
+        None
+    }
+
+    /// creates a callback that copies `text` to the clipboard when clicked.
+    /// By default, there is no such callback, and the code blocks
+    /// have no copy button even with `code_copy_button`
+    fn make_copy_handler(self, text: String) -> Option<Self::Handler<Self::MouseEvent>> {
+        let _ = text;
+        None
+    }
+
     #[cfg(feature="debug")]
     fn send_debug_info(self, info: Vec<String>);
 
+    /// returns true if the diagnostics of the rendered documents are
+    /// looked for, and given to [`Context::report_diagnostic`].
+    /// By default, only with the `debug` feature, since finding them
+    /// takes another pass over the document on each render
+    fn has_diagnostic_reporter(self) -> bool {
+        cfg!(feature="debug")
+    }
+
+    /// called before rendering a document, for each malformed construct
+    /// found in it, like an unterminated code fence or an unknown component,
+    /// if [`Context::has_diagnostic_reporter`] returns true.
+    /// See [`check_markdown`] to get them without rendering.
+    /// By default, they are sent to [`Context::send_debug_info`]
+    /// with the `debug` feature
+    fn report_diagnostic(self, diagnostic: Diagnostic) {
+        #[cfg(feature="debug")]
+        self.send_debug_info(vec![diagnostic.to_string()]);
+        #[cfg(not(feature="debug"))]
+        let _ = diagnostic;
+    }
+
 
     /// creates a html element
     /// `attributes` contains the html attributes for this element
@@ -87,6 +301,12 @@ where 'callback: 'a
     /// renders raw html, inside a span
     fn el_span_with_inner_html(self, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
+    /// renders raw html inside the element `e`.
+    /// By default, the html is rendered in a span inside `e`
+    fn el_with_inner_html(self, e: HtmlElement, inner_html: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        self.el_with_attributes(e, self.el_span_with_inner_html(inner_html, Default::default()), attributes)
+    }
+
     /// renders a `hr` element, with attributes
     fn el_hr(self, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
@@ -96,11 +316,52 @@ where 'callback: 'a
     /// takes a vector of views and return a view
     fn el_fragment(self, children: Vec<Self::View>) -> Self::View;
 
-    /// renders a link
-    fn el_a(self, children: Self::View, href: String) -> Self::View;
+    /// takes the top-level elements of a document, each one with a key
+    /// derived from its position in the source, and returns a view.
+    /// The frameworks with keyed children can use it to update
+    /// only the elements that changed.
+    /// By default, the keys are ignored and the elements are put
+    /// together with [`Context::el_fragment`]
+    fn el_keyed_fragment(self, children: Vec<(String, Self::View)>) -> Self::View {
+        self.el_fragment(children.into_iter().map(|(_, child)| child).collect())
+    }
 
-    /// renders an image
-    fn el_img(self, src: String, alt: String) -> Self::View;
+    /// renders a link.
+    /// By default, it calls `el_a_with_attributes` without attributes
+    fn el_a(self, children: Self::View, href: String) -> Self::View {
+        self.el_a_with_attributes(children, href, Default::default())
+    }
+
+    /// renders a link, with attributes, like the click handler
+    /// of the links to a section or the `target` of the links
+    /// opening in a new tab
+    fn el_a_with_attributes(self, children: Self::View, href: String, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
+
+    /// renders a link that opens in a new tab.
+    /// By default, it calls `el_a_with_attributes`
+    /// with `target="_blank"` and `rel="noopener noreferrer"`
+    fn el_a_new_tab(self, children: Self::View, href: String) -> Self::View {
+        let attributes = ElementAttributes {
+            attributes: vec![
+                ("target".to_string(), "_blank".to_string()),
+                ("rel".to_string(), "noopener noreferrer".to_string()),
+            ],
+            ..Default::default()
+        };
+        self.el_a_with_attributes(children, href, attributes)
+    }
+
+    /// renders an image.
+    /// By default, it calls `el_img_with_attributes` without optional attributes
+    fn el_img(self, src: String, alt: String) -> Self::View {
+        self.el_img_with_attributes(src, alt, Default::default())
+    }
+
+    /// renders an image, with the optional attributes `attributes`.
+    /// All of them must be rendered: the lazy loading, the size,
+    /// the title and the accessibility attributes of the images
+    /// are only set through it
+    fn el_img_with_attributes(self, src: String, alt: String, attributes: ImageAttributes) -> Self::View;
 
     /// renders an empty view
     fn el_empty(self) -> Self::View {
@@ -111,35 +372,87 @@ where 'callback: 'a
     fn el_text(self, text: CowStr<'a>) -> Self::View;
 
 
-    // renders a checkbox with attributes
+    /// renders a checkbox with attributes.
+    /// Read-only checkboxes have a `disabled` attribute in `attributes.attributes`
     fn el_input_checkbox(self, checked: bool, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View;
 
 
-    /// add a styleshit to the markdown component
-    fn mount_dynamic_link(self, rel: &str, href: &str, integrity: &str, crossorigin: &str);
+    /// add a styleshit to the markdown component.
+    /// By default, it calls `mount_dynamic_link_with`,
+    /// without the attributes that are empty
+    fn mount_dynamic_link(self, rel: &str, href: &str, integrity: &str, crossorigin: &str) {
+        self.mount_dynamic_link_with(
+            rel,
+            href,
+            Some(integrity).filter(|s| !s.is_empty()),
+            Some(crossorigin).filter(|s| !s.is_empty()),
+        )
+    }
+
+    /// add a styleshit to the markdown component.
+    /// When `integrity` or `crossorigin` is None,
+    /// the corresponding attribute must be omitted from the link.
+    /// It must be idempotent: if a link with the same `href` is already mounted,
+    /// it must not add another one.
+    /// In the browser, the crate also avoids calling it twice for the same `href`,
+    /// until [`forget_mounted_links`] is called
+    fn mount_dynamic_link_with(self, rel: &str, href: &str, integrity: Option<&str>, crossorigin: Option<&str>);
 
     fn has_custom_component(self, name: &str) -> bool;
     fn render_custom_component(self, name: &str, input: MdComponentProps<Self::View>) -> Result<Self::View, ComponentCreationError>;
 
+    /// renders a custom component that failed, in place of the component,
+    /// so that the rest of the document is still rendered.
+    /// Panics are only caught when they unwind, which is not the
+    /// case on `wasm32-unknown-unknown`.
+    /// By default, the error is rendered in a span with the `markdown-error` class
+    fn render_component_error(self, error: ComponentError) -> Self::View {
+        render::render_error(self, error.to_string())
+    }
+
     fn render_tasklist_marker(self, m: bool, position: Range<usize>) -> Self::View {
+        let toggle = TaskToggle {
+            checked: !m,
+            position: position.clone(),
+        };
+        let toggle_handler = self.make_task_toggle_handler(toggle);
+        // the checkboxes are read-only, unless they can be toggled
+        let disabled = toggle_handler.is_none() && !self.props().editable_task_lists;
+        let on_click = toggle_handler
+            .unwrap_or_else(|| self.make_md_tag_handler(position, MarkdownTag::TaskListMarker, true));
         let attributes = ElementAttributes {
-            on_click: Some(self.make_md_handler(position, true)),
+            on_click: Some(on_click),
+            attributes: disabled
+                .then(|| ("disabled".to_string(), String::new()))
+                .into_iter()
+                .collect(),
             ..Default::default()
         };
         self.el_input_checkbox(m, attributes)
     }
 
+    /// renders a thematic break, at the `range` of its characters,
+    /// like `---`, without its indentation and line ending
     fn render_rule(self, range: Range<usize>) -> Self::View {
-        let attributes = ElementAttributes{
-            on_click: Some(self.make_md_handler(range, false)),
-            ..Default::default()
-        };
-        self.el_hr(attributes)
+        render::render_rule(self, range, None)
     }
 
+    /// renders a thematic break starting at the line `source_line` of the source,
+    /// set for the top-level ones with [`MarkdownProps::source_line_attributes`].
+    /// By default, it is rendered with `render_rule` without a line
+    fn render_rule_on_line(self, range: Range<usize>, source_line: Option<usize>) -> Self::View {
+        match source_line {
+            None => self.render_rule(range),
+            Some(_) => render::render_rule(self, range, source_line),
+        }
+    }
 
+
+    /// renders inline code.
+    /// The code is always rendered as text with [`Context::el_text`],
+    /// never as inner html
     fn render_code(self, s: CowStr<'a>, range: Range<usize>) -> Self::View {
-        let callback = self.make_md_handler(range.clone(), false);
+        let callback = self.make_md_tag_handler(range, MarkdownTag::Code, false);
         let attributes = ElementAttributes{
             on_click: Some(callback),
             ..Default::default()
@@ -148,6 +461,69 @@ where 'callback: 'a
     }
 
 
+    /// renders a cell of a table, a `th` if it is in the `header` row,
+    /// or a `td` otherwise.
+    /// `align` is the alignment of its column, from the delimiter row
+    /// of the table, like `:--:` for [`Alignment::Center`].
+    /// It is not in `attributes`, which contain the other attributes
+    /// of the cell, like `scope`, `colspan` or `rowspan`.
+    /// By default, it is rendered with a `text-align` style
+    fn el_table_cell(self, inside: Self::View, header: bool, align: Alignment, attributes: ElementAttributes<Self::Handler<Self::MouseEvent>>) -> Self::View {
+        render::render_table_cell(self, inside, header, align, attributes)
+    }
+
+
+    /// renders a code block.
+    /// When the `syntect` feature is enabled and `lang` is known,
+    /// the code is split into `Span`s with the classes of their tokens,
+    /// colored by the stylesheet of `code_theme_css(MarkdownProps::theme)`.
+    /// Otherwise, it renders as plain `Pre`/`Code` elements.
+    /// In both cases, the code is rendered as text, never as inner html
+    fn render_code_block(self, lang: Option<&str>, source: &str, range: Range<usize>) -> Self::View {
+        let fence = CodeFence {
+            info: lang.unwrap_or_default().to_string(),
+            lang: lang.map(str::to_string),
+            ..Default::default()
+        };
+        render::render_code_block(self, &fence, source, range)
+    }
+
+    /// renders a code block, with the whole information string of its `fence`.
+    /// By default, it is rendered with `render_code_block`, unless the fence
+    /// has lines to highlight or a source line, which `render_code_block` can't show
+    fn render_code_fence(self, fence: &CodeFence, source: &str, range: Range<usize>) -> Self::View {
+        match fence.highlighted_lines.is_empty() && fence.source_line.is_none() {
+            true => self.render_code_block(fence.lang.as_deref(), source, range),
+            false => render::render_code_block(self, fence, source, range)
+        }
+    }
+
+
+    /// renders the marker placed at the end of a document
+    /// cut by `max_events`
+    fn render_truncation_marker(self) -> Self::View {
+        self.el_with_attributes(
+            HtmlElement::Div,
+            self.el_text("Content truncated".into()),
+            ElementAttributes {
+                classes: vec!["content-truncated".into()],
+                ..Default::default()
+            }
+        )
+    }
+
+
+    /// renders a heading, for all the levels.
+    /// Override it to add a custom anchor, or to collect the headings
+    /// while they are rendered. By default, it is a [`HtmlElement::Heading`]
+    /// with the slug as id
+    fn render_heading(self, heading: HeadingDescription<Self::View>) -> Self::View {
+        render::render_heading(self, heading)
+    }
+
+
+    /// renders a text, with a click handler from `make_md_handler`.
+    /// The renderer calls [`Context::render_text_in`] instead
     fn render_text(self, s: CowStr<'a>, range: Range<usize>) -> Self::View{
         let callback = self.make_md_handler(range, false);
         let attributes = ElementAttributes{
@@ -157,10 +533,148 @@ where 'callback: 'a
         self.el_with_attributes(HtmlElement::Span, self.el_text(s), attributes)
     }
 
+    /// renders a text, inside the element described by `tag`,
+    /// in a span with a click handler from [`Context::make_md_tag_handler`]
+    fn render_text_in(self, s: CowStr<'a>, tag: MarkdownTag, range: Range<usize>) -> Self::View{
+        let callback = self.make_md_tag_handler(range, tag, false);
+        let attributes = ElementAttributes{
+            on_click: Some(callback),
+            ..Default::default()
+        };
+        self.el_with_attributes(HtmlElement::Span, self.el_text(s), attributes)
+    }
+
+
+    /// renders a reference to the footnote `label`.
+    /// `number` is the number displayed for this footnote,
+    /// and `occurrence` counts the references to it, starting at 1
+    fn render_footnote_reference(self, label: &str, number: usize, occurrence: usize, range: Range<usize>) -> Self::View {
+        let attributes = ElementAttributes {
+            id: Some(utils::footnote_reference_id(label, occurrence)),
+            classes: vec!["footnote-reference".into()],
+            on_click: Some(self.make_md_tag_handler(range, MarkdownTag::FootnoteReference, false)),
+            ..Default::default()
+        };
+        let link = self.el_a(
+            self.el_text(number.to_string().into()),
+            format!("#{}", utils::footnote_id(label))
+        );
+        self.el_with_attributes(HtmlElement::Sup, link, attributes)
+    }
+
+    /// renders the definition of the footnote `label` in the footnote section,
+    /// with a link back to each of its `references`.
+    /// `number` is the number displayed for this footnote.
+    /// When there are several references, the links are numbered, like `↩1` and `↩2`
+    fn render_footnote_definition(self, label: &str, number: usize, content: Self::View, references: usize) -> Self::View {
+        let mut children = vec![content];
+        for occurrence in 1..=references {
+            let arrow = self.el_with_attributes(
+                HtmlElement::Span,
+                self.el_text("↩".into()),
+                ElementAttributes {
+                    attributes: vec![("aria-hidden".to_string(), "true".to_string())],
+                    ..Default::default()
+                }
+            );
+            let (arrow, aria_label) = match references {
+                1 => (arrow, format!("Back to reference {number}")),
+                _ => (
+                    self.el_fragment(vec![arrow, self.el(HtmlElement::Sup, self.el_text(occurrence.to_string().into()))]),
+                    format!("Back to reference {number} ({occurrence})")
+                ),
+            };
+            children.push(self.el_a_with_attributes(
+                    arrow,
+                    format!("#{}", utils::footnote_reference_id(label, occurrence)),
+                    ElementAttributes {
+                        classes: vec!["footnote-backref".into()],
+                        attributes: vec![("aria-label".to_string(), aria_label)],
+                        ..Default::default()
+                    }
+            ));
+        }
+        let attributes = ElementAttributes {
+            id: Some(utils::footnote_id(label)),
+            ..Default::default()
+        };
+        self.el_with_attributes(HtmlElement::Li, self.el_fragment(children), attributes)
+    }
+
+
+    /// resolves the target of a wikilink, like `Page Name` in `[[Page Name|alias]]`,
+    /// into a url.
+    /// Returns None if the target doesn't exist: the link is then
+    /// rendered as a span with the `broken-link` class.
+    /// By default, the target is used as the url
+    fn resolve_wikilink(self, target: &str) -> Option<String> {
+        Some(target.to_string())
+    }
+
 
     fn has_custom_links(self) -> bool;
 
+    /// returns true if the code blocks in the language `lang`
+    /// are rendered with [`Context::render_custom_code_block`],
+    /// like `mermaid` blocks rendered as diagrams
+    fn has_custom_code_block(self, lang: &str) -> bool {
+        let _ = lang;
+        false
+    }
+
+    /// renders a code block with a custom renderer
+    fn render_custom_code_block(self, code: CodeBlockProps) -> Result<Self::View, String> {
+        let _ = code;
+        Err("no custom code block renderer".to_string())
+    }
+
+    /// returns true if the images are rendered with [`Context::render_images`]
+    /// instead of [`Context::el_img_with_attributes`].
+    /// Custom images take precedence over custom links
+    fn has_custom_images(self) -> bool {
+        false
+    }
+
+    /// renders an image with a custom renderer
+    fn render_images(self, image: ImageDescription) -> Result<Self::View, String> {
+        let _ = image;
+        Err("no custom image renderer".to_string())
+    }
+
+    /// the name of the provider of the media at `url`, like `youtube`,
+    /// if it can be embedded with [`Context::render_embed`].
+    /// By default, the YouTube and Vimeo videos are recognized:
+    /// override it to recognize other providers
+    fn embed_provider(self, url: &str) -> Option<String> {
+        utils::embed_provider(url).map(str::to_string)
+    }
+
+    /// renders the media of a link alone in its paragraph,
+    /// like a video player in an `iframe`, when `auto_embed` is enabled.
+    /// Returns None to render the link as usual, which is the default
+    fn render_embed(self, embed: EmbedDescription) -> Option<Self::View> {
+        let _ = embed;
+        None
+    }
+
+    /// returns true if the references to undefined links, like `[text][label]`
+    /// without a `[label]: url` definition, are rendered with
+    /// [`Context::render_broken_link`]. Otherwise, they are rendered as text
+    fn has_custom_broken_links(self) -> bool {
+        false
+    }
+
+    /// renders a reference to an undefined link, for example as a warning,
+    /// or as a link resolved from another table of references
+    fn render_broken_link(self, link: BrokenLink) -> Result<Self::View, String> {
+        let _ = link;
+        Err("no custom broken link renderer".to_string())
+    }
+
 
+    /// renders a link, with [`Context::render_links`] if `has_custom_links` is true.
+    /// The images only go through it with custom links: otherwise they are
+    /// rendered with [`Context::el_img_with_attributes`] before reaching it
     fn render_link(self, link: LinkDescription<Self::View>) 
         -> Result<Self::View, String>
     {
@@ -169,8 +683,17 @@ where 'callback: 'a
         }
         else {
             Ok(
-                if link.image {
-                    self.el_img(link.url, link.title)
+                if self.props().open_links_in_new_tab && utils::is_external_url(&link.url, self.props().base_url) {
+                    self.el_a_new_tab(link.content, link.url)
+                }
+                else if let Some(handler) = link.url.strip_prefix('#')
+                    .zip(self.props().anchor_scroll)
+                    .and_then(|(id, scroll)| self.make_anchor_scroll_handler(id.to_string(), scroll)) {
+                    let attributes = ElementAttributes {
+                        on_click: Some(handler),
+                        ..Default::default()
+                    };
+                    self.el_a_with_attributes(link.content, link.url, attributes)
                 }
                 else {
                     self.el_a(link.content, link.url)
@@ -182,6 +705,146 @@ where 'callback: 'a
 
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// when the browser loads the images of the document
+pub enum ImageLoading {
+    /// load the images immediately, the browser default
+    #[default]
+    Eager,
+    /// set `loading="lazy"` and `decoding="async"` on the images,
+    /// so that they load when they get close to the viewport.
+    /// The first `MarkdownProps::eager_images` images, likely above the fold,
+    /// are still loaded immediately
+    Lazy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// the `crossorigin` attribute of the images
+pub enum CrossOrigin {
+    /// `crossorigin="anonymous"`, without credentials
+    Anonymous,
+    /// `crossorigin="use-credentials"`
+    UseCredentials,
+}
+
+impl CrossOrigin {
+    /// the value of the `crossorigin` attribute
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CrossOrigin::Anonymous => "anonymous",
+            CrossOrigin::UseCredentials => "use-credentials",
+        }
+    }
+}
+
+#[derive(Default)]
+/// the optional attributes of an `img` element
+pub struct ImageAttributes {
+    /// when to load the image
+    pub loading: ImageLoading,
+    /// the width of the image, in pixels, see [`ImageDescription::width`]
+    pub width: Option<u32>,
+    /// the height of the image, in pixels
+    pub height: Option<u32>,
+    /// the title of the image, like `Title` in `![alt](x.png "Title")`
+    pub title: Option<String>,
+    /// the accessible name of the image, in an `aria-label` attribute
+    pub aria_label: Option<String>,
+    /// the image is decorative, with an empty alt:
+    /// it is hidden from screen readers with `role="presentation"`
+    pub presentation: bool,
+    /// the `crossorigin` attribute of the image
+    pub crossorigin: Option<CrossOrigin>,
+    /// the `referrerpolicy` attribute of the image, like `no-referrer`
+    pub referrer_policy: Option<String>,
+}
+
+/// the description of a code block, used to render it with
+/// [`Context::render_custom_code_block`]
+pub struct CodeBlockProps {
+    /// the information string of the code block,
+    /// with its language and its raw text
+    pub fence: CodeFence,
+
+    /// the code inside the block
+    pub source: String,
+
+    /// the position of the code block in the markdown source
+    pub range: Range<usize>,
+}
+
+/// the description of an image, used to render it with
+/// [`Context::render_images`]
+pub struct ImageDescription {
+    /// the url of the image
+    pub src: String,
+
+    /// the alternative text of the image
+    pub alt: String,
+
+    /// the title of the image, often empty
+    pub title: String,
+
+    /// the width of the image, in pixels.
+    /// The size is written after the url, like `=200x100`, `=200x` or `=x100`.
+    /// Since a url can't contain a space, the url and its size
+    /// must be written between angle brackets: `![alt](<img.png =200x100>)`
+    pub width: Option<u32>,
+
+    /// the height of the image, in pixels
+    pub height: Option<u32>,
+
+    /// the position of the image in the markdown source
+    pub range: Range<usize>,
+}
+
+/// the description of a media to embed, used to render it with
+/// [`Context::render_embed`]
+pub struct EmbedDescription {
+    /// the url of the link to the media
+    pub url: String,
+
+    /// the provider of the media, from [`Context::embed_provider`]
+    pub provider: String,
+
+    /// the position of the paragraph of the link in the markdown source
+    pub range: Range<usize>,
+}
+
+/// the description of a reference to an undefined link,
+/// used to render it with [`Context::render_broken_link`]
+pub struct BrokenLink {
+    /// the label of the reference, like `label` in `[text][label]`
+    pub reference: String,
+
+    /// the markdown of the reference, like `[text][label]`
+    pub source: String,
+
+    /// the position of the reference in the markdown source
+    pub range: Range<usize>,
+}
+
+/// the description of a heading, used to render it with
+/// [`Context::render_heading`]
+pub struct HeadingDescription<V> {
+    /// the level of the heading, from 1 to 6,
+    /// with [`MarkdownProps::heading_offset`] added
+    pub level: u8,
+
+    /// the rendered content of the heading
+    pub content: V,
+
+    /// the id of the heading, unique in the document
+    pub slug: String,
+
+    /// the position of the heading in the markdown source
+    pub range: Range<usize>,
+
+    /// the line of the heading in the markdown source, starting at 1,
+    /// for the top-level headings with [`MarkdownProps::source_line_attributes`]
+    pub source_line: Option<usize>,
+}
+
 /// the description of a link, used to render it with a custom callback.
 /// See [pulldown_cmark::Tag::Link] for documentation
 pub struct LinkDescription<V> {
@@ -208,6 +871,7 @@ pub enum HtmlError {
     Link(String),
     Syntax(String),
     CustomComponent{name: String, msg: String},
+    CustomCodeBlock{lang: String, msg: String},
     Math,
 }
 
@@ -232,6 +896,11 @@ pub enum HtmlError {
 ///     children: ... // html view of **hey**
 /// }
 /// ```
+/// The children are the markdown between the start and the end tags,
+/// rendered like the rest of the document: they can contain
+/// other components, including components with the same name.
+/// Components can also be used inline, like `<MyBox>**hey !**</MyBox>`,
+/// and `<MyBox/>` has no children
 pub struct MdComponentProps<V> {
     pub attributes: BTreeMap<String, String>,
     pub children: V
@@ -286,18 +955,711 @@ impl<T: std::fmt::Debug> From<T> for ComponentCreationError {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// how the links inside the document, like `[see](#section)`,
+/// scroll to their target
+pub struct AnchorScroll {
+    /// scroll smoothly, instead of jumping to the target
+    pub smooth: bool,
+    /// the space left above the target, in pixels,
+    /// like the height of a sticky header
+    pub offset: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// the urls of the references to issues, like `#123`,
+/// and to users, like `@name`, linked by [`MarkdownProps::reference_templates`]
+pub struct ReferenceTemplates {
+    /// the url of the issues, where `{n}` is replaced by the number,
+    /// like `/issues/{n}`
+    pub issue: Option<String>,
+    /// the url of the users, where `{name}` is replaced by the name,
+    /// like `/users/{name}`
+    pub user: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// the toggling of the checkbox of a task list item
+pub struct TaskToggle {
+    /// the state of the checkbox after the click
+    pub checked: bool,
+    /// the position of the marker in the source, like `[ ]` or `[x]`
+    pub position: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// a custom component that could not be rendered,
+/// because it is not registered, or because its callback
+/// returned an error or panicked
+pub struct ComponentError {
+    /// the name of the component
+    pub name: String,
+    /// the reason of the failure
+    pub message: String,
+}
+
+impl std::fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Custom component `{}` failed: `{}`", self.name, self.message)
+    }
+}
+
+
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// the library used to render math
+pub enum MathBackend {
+    /// render math with katex.
+    /// Requires the `maths` feature, and loads the katex stylesheet
+    #[default]
+    Katex,
+    /// render math as native MathML, which is accessible
+    /// and does not require any stylesheet.
+    /// Requires the `mathml` feature
+    MathMl,
+}
+
+/// the location of the katex stylesheet,
+/// mounted when the document contains math
+pub struct KatexStylesheet {
+    /// the url of the css file
+    pub url: String,
+
+    /// the subresource integrity hash of the css file.
+    /// If None, the link has no `integrity` attribute
+    pub integrity: Option<String>,
+
+    /// the `crossorigin` attribute of the link.
+    /// If None, the link has no `crossorigin` attribute
+    pub crossorigin: Option<String>,
+}
 
+impl Default for KatexStylesheet {
+    fn default() -> Self {
+        Self {
+            url: "https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css".to_string(),
+            integrity: Some("sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI".to_string()),
+            crossorigin: Some("anonymous".to_string()),
+        }
+    }
+}
 
+/// a transformation of the markdown events, with their position in the source
+pub type EventPreprocessor = fn(&mut Vec<(Event, Range<usize>)>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// how the line breaks inside a paragraph are rendered
+pub enum SoftBreak {
+    /// as a space, like html does
+    #[default]
+    Space,
+    /// as a `br` element
+    LineBreak,
+    /// as nothing, for languages without spaces between words
+    None,
+}
 
+#[derive(Clone, Copy, Default)]
 pub struct MarkdownProps<'a>
 {
+    /// render the line breaks inside a paragraph as `br` elements.
+    /// Same as `soft_break: SoftBreak::LineBreak`, which it overrides
     pub hard_line_breaks: bool,
 
+    /// how the line breaks inside a paragraph are rendered
+    pub soft_break: SoftBreak,
+
+    /// show a marker before each line break inside a paragraph,
+    /// like the formatting marks of a word processor.
+    /// The markers are spans with the `break-marker` class,
+    /// hidden from the screen readers
+    pub show_break_markers: bool,
+
     pub wikilinks: bool,
 
+    /// the options of the markdown parser. Defaults to all the options.
+    /// The toggles of single options, like `smart_punctuation`,
+    /// are applied on top of them and take precedence
     pub parse_options: Option<&'a pulldown_cmark_wikilink::Options>,
 
+    /// turn the straight quotes into curly quotes, and `--` and `---`
+    /// into en and em dashes, whatever the `parse_options`.
+    /// When None, the setting of the `parse_options` is kept
+    pub smart_punctuation: Option<bool>,
+
+    /// the name of the syntect theme used to highlight code blocks.
+    /// Defaults to `base16-ocean.light`.
+    /// Its stylesheet is given by `code_theme_css`
     pub theme: Option<&'a str>,
+
+    /// wrap the document in a `div` with this class, like `markdown-body`.
+    /// The `theme` is set on it as a `data-theme` attribute.
+    /// Without it, the document is a fragment
+    pub container_class: Option<&'a str>,
+
+    /// render the math written with latex delimiters, `\(x\)` for inline
+    /// math and `\[x\]` for display math, like the math between dollars.
+    /// The delimiters preceded by an escaped backslash are plain text
+    pub latex_math_delimiters: bool,
+
+    /// keep as text the inline math between dollars that looks like prose,
+    /// like `$5 and $10`: when its content starts or ends with a space,
+    /// spans a line break, or has several words without any math character,
+    /// or when its closing dollar is followed by a digit
+    pub strict_dollar_math: bool,
+
+    /// the color of the math expressions that can't be compiled,
+    /// like `#cc0000`. They are shown as their source,
+    /// with the `math-error` class
+    pub math_error_color: Option<&'a str>,
+
+    /// the class of the unordered lists, to style their bullets with css
+    pub unordered_list_class: Option<&'a str>,
+
+    /// the class of the ordered lists, to style their markers with css,
+    /// like `list-style-type: lower-roman`
+    pub ordered_list_class: Option<&'a str>,
+
+    /// the class of the thematic breaks, like `---`, to style them with css
+    pub hr_class: Option<&'a str>,
+
+    /// where to load the katex stylesheet from.
+    /// Defaults to the jsdelivr cdn
+    pub katex_stylesheet: Option<&'a KatexStylesheet>,
+
+    /// the katex macros, like `\RR` for `\mathbb{R}`, available
+    /// in all the math of the document. They are given to katex for each
+    /// expression, and the macros defined in an expression with `\gdef`
+    /// are not kept for the next ones, nor for the next renders
+    pub katex_macros: Option<&'a HashMap<String, String>>,
+
+    /// how to render math
+    pub math_backend: MathBackend,
+
+    /// computes the id of a heading from its plain text,
+    /// for example to transliterate non-latin scripts.
+    /// Defaults to github-style slugs: lowercase, without punctuation,
+    /// and with spaces replaced by `-`.
+    /// The ids are de-duplicated after this function runs: a repeated id
+    /// gets a numeric suffix, like `intro-1`, and an empty id becomes `section`.
+    /// Explicit ids, like `# Title {#id}`, don't go through this function
+    pub slugify: Option<fn(&str) -> String>,
+
+    /// render `^text^` as superscript and `~text~` as subscript.
+    /// The marked text must not contain any formatting.
+    /// `~~strikethrough~~` is not affected
+    pub superscript_subscript: bool,
+
+    /// render `==text==` as marked (highlighted) text.
+    /// The marked text must not contain any formatting
+    pub mark: bool,
+
+    /// render `++text++` as inserted text, in an `ins` element.
+    /// The inserted text must not contain any formatting
+    pub insert: bool,
+
+    /// render the strikethrough text, like `~~text~~`, as deleted text,
+    /// in a `del` element, instead of an `s` element
+    pub strikethrough_as_del: bool,
+
+    /// render the text surrounded by this marker, like `||Ctrl||`,
+    /// as a keyboard key, in a `kbd` element.
+    /// The text must not contain any formatting
+    pub kbd_marker: Option<&'a str>,
+
+    /// open the links to other websites (`http` and `https` urls
+    /// on another host than `base_url`) in a new tab
+    pub open_links_in_new_tab: bool,
+
+    /// add a link to itself at the end of each heading, with the
+    /// `heading-anchor` class, which copies the link when clicked.
+    /// Its `#` symbol is hidden from screen readers,
+    /// and is not part of the text of the heading, like in its slug
+    pub heading_anchor_links: bool,
+
+    /// added to the level of each heading, up to 6, when the document
+    /// is a section of a page with its own headings.
+    /// With 1, `# Title` is rendered as a `h2` element
+    pub heading_offset: u8,
+
+    /// set the line where each top-level block, like a paragraph, a heading,
+    /// a list, a code block or a thematic break, starts in the source, from 1,
+    /// as a `data-source-line` attribute.
+    /// It lets an editor scroll its preview along with the source
+    pub source_line_attributes: bool,
+
+    /// the maximum number of markdown events rendered, to protect against
+    /// huge documents. The document is cut after its last top-level block
+    /// that fits, like a paragraph or a list, and ends with
+    /// [`Context::render_truncation_marker`]
+    pub max_events: Option<usize>,
+
+    /// render a document made of a single paragraph, like a chat message,
+    /// as its inline content, without the paragraph element around it.
+    /// The documents with several blocks are rendered as usual
+    pub tight: bool,
+
+    /// render the links alone in their paragraph, like a bare url on its
+    /// own line, with [`Context::render_embed`] when their provider
+    /// is recognized by [`Context::embed_provider`], like a YouTube video
+    pub auto_embed: bool,
+
+    /// give the images with an empty alt, like `![](x.png)`,
+    /// a `role="presentation"` attribute, as they are decorative
+    pub decorative_images: bool,
+
+    /// give the images with a title, like `![alt](x.png "Title")`,
+    /// an `aria-label` attribute with their title
+    pub image_title_as_aria_label: bool,
+
+    /// scroll to the target of the links inside the document,
+    /// like `[see](#section)`, with [`Context::make_anchor_scroll_handler`]
+    pub anchor_scroll: Option<AnchorScroll>,
+
+    /// the url of the document.
+    /// Relative links and images are resolved against it
+    pub base_url: Option<&'a str>,
+
+    /// rewrites the urls of the links and images, after their resolution
+    /// against `base_url`, for example with [`strip_tracking_params`].
+    /// The sanitization, if enabled, applies to the rewritten urls
+    pub url_rewriter: Option<fn(&str) -> String>,
+
+    /// remove the unsafe parts of the raw html (`<script>`, `onclick=...`)
+    /// and of the urls of the links and images (`javascript:...`).
+    /// Enable it when rendering untrusted content
+    pub sanitize_html: bool,
+
+    /// keep the html comments, like `<!-- note -->`, in the rendered html,
+    /// to debug it. By default, they are removed.
+    /// The sanitized html never contains comments
+    pub preserve_comments: bool,
+
+    /// show the line numbers of the code blocks.
+    /// Each line is rendered in a span with the `code-line` class,
+    /// starting with its number in a span with the `line-number` class
+    pub code_line_numbers: bool,
+
+    /// add a button to copy the code of the code blocks,
+    /// if the context has a [`Context::make_copy_handler`]
+    pub code_copy_button: bool,
+
+    /// show the language of the code blocks, as written in their fence
+    /// like `rust` or `ts`, in a span with the `code-language-label` class.
+    /// Like the copy button, it is placed before the `pre` element,
+    /// and before the copy button if both are enabled
+    pub code_language_label: bool,
+
+    /// when to load the images of the document
+    pub image_loading: ImageLoading,
+
+    /// the number of images at the start of the document
+    /// that load immediately when `image_loading` is `Lazy`
+    pub eager_images: usize,
+
+    /// the `crossorigin` attribute of the remote images,
+    /// for the images from a CDN with CORS requirements.
+    /// It is not set on the `data:` urls
+    pub image_crossorigin: Option<CrossOrigin>,
+
+    /// the `referrerpolicy` attribute of the remote images,
+    /// like `no-referrer`. It is not set on the `data:` urls
+    pub image_referrer_policy: Option<&'a str>,
+
+    /// the maximum number of nested block containers, like lists,
+    /// list items and blockquotes. The content of deeper containers is
+    /// rendered as plain text, to protect against stack overflows on
+    /// untrusted input. Inline formatting is not counted.
+    /// Real documents rarely need more than a few dozen levels
+    pub max_nesting_depth: Option<usize>,
+
+    /// render the images alone in their paragraph and with a title,
+    /// like `![alt](image.png "Caption")`, in a `figure`
+    /// with their title as `figcaption`.
+    /// The images inside of a text stay inline
+    pub figure_for_titled_images: bool,
+
+    /// render a paragraph starting with this prefix, like `Table:`,
+    /// right before or after a table as the caption of the table,
+    /// without the prefix
+    pub table_caption_prefix: Option<&'a str>,
+
+    /// merge the table cells containing only `^` into the cell above them,
+    /// and the empty cells into the cell on their left, with the
+    /// `rowspan` and `colspan` attributes.
+    /// The cells are not merged across the head and the body of the table
+    pub table_spans: bool,
+
+    /// render the inline footnotes, like `^[some note]`, as footnotes
+    /// without label, numbered with the other footnotes in the order
+    /// of their references. The footnotes must be enabled
+    /// in the parsing options, which is the default
+    pub inline_footnotes: bool,
+
+    /// replace shortcodes like `:smile:` by their emoji.
+    /// Unknown shortcodes are left untouched.
+    /// Requires the `emoji` feature
+    pub emoji_shortcodes: bool,
+
+    /// render blockquotes starting with a marker like `[!NOTE]`
+    /// as github alerts, with the `markdown-alert` class
+    /// and a class for their kind, like `markdown-alert-note`.
+    /// The kinds are `NOTE`, `TIP`, `IMPORTANT`, `WARNING` and `CAUTION`,
+    /// and a custom title can follow the marker
+    pub github_alerts: bool,
+
+    /// render the last line of a blockquote starting with `— `,
+    /// like `> — Some author`, as its attribution, in a `footer` element
+    /// with the author in a `cite` element
+    pub blockquote_attribution: bool,
+
+    /// render collapsible details blocks, written as
+    /// ```md
+    /// ::: details Some title
+    ///
+    /// Some content
+    ///
+    /// :::
+    /// ```
+    /// The closing `:::` must be a paragraph on its own,
+    /// and the blocks can be nested
+    pub details_blocks: bool,
+
+    /// open the details blocks by default
+    pub details_open: bool,
+
+    /// turn the bare urls, like `https://example.com` or `www.example.com`,
+    /// and the email addresses of the text into links.
+    /// The text of links and code is left untouched
+    pub linkify: bool,
+
+    /// with `linkify`, turn the mentions like `@name` into links to
+    /// this url, where `{}` is replaced by the name,
+    /// like `https://github.com/{}`
+    pub mention_url: Option<&'a str>,
+
+    /// turn the email addresses of the text, like `me@example.com`,
+    /// into `mailto:` links, without the bare urls.
+    /// It is already done by `linkify`
+    pub autolink_emails: bool,
+
+    /// turn the references to issues, like `#123`, and to users, like `@name`,
+    /// into links built from these templates.
+    /// The text of links and code is left untouched
+    pub reference_templates: Option<&'a ReferenceTemplates>,
+
+    /// render the checkboxes of the task lists without the `disabled` attribute.
+    /// They are also enabled when [`Context::make_task_toggle_handler`]
+    /// returns a callback
+    pub editable_task_lists: bool,
+
+    /// wrap the abbreviations defined in the document,
+    /// like `*[HTML]: HyperText Markup Language`, in an `abbr` element
+    /// with their definition as title.
+    /// The definitions are removed from the document, and the abbreviations
+    /// are matched as whole words, case-sensitively, outside of code and links
+    pub abbreviations: bool,
+
+    /// render paragraphs made of terms followed by
+    /// definitions starting with `: ` as definition lists.
+    /// The lists indented under the last definition are nested in it
+    pub definition_lists: bool,
+
+    /// transforms the markdown events after parsing, before they are rendered.
+    /// The ranges of the events are their positions in the source,
+    /// so that the click handlers of new events can point to it
+    pub event_preprocessor: Option<EventPreprocessor>,
+
+    /// the title of the footnote section at the end of the document.
+    /// Defaults to `Footnotes`, an empty string removes it
+    pub footnotes_heading: Option<&'a str>,
+}
+
+/// moves the lists right after a paragraph that is a definition list,
+/// and indented further than its first term, into the paragraph:
+/// they are nested in its last definition, like
+/// ```md
+/// Term
+/// : definition
+///   - nested item
+/// ```
+fn nest_definition_lists(source: &str, events: &mut [(Event, Range<usize>)]) {
+    let is_nested_list = |event: Option<&(Event, Range<usize>)>, indent: usize| matches!(event,
+        Some((Event::Start(Tag::List(_)), range)) if render::line_indent(source, range.start) > indent);
+    let mut i = 0;
+    while i < events.len() {
+        let Some(end) = events[i..].iter()
+            .position(|(e, _)| *e == Event::End(TagEnd::Paragraph))
+            .map(|n| i + n)
+            .filter(|_| events[i].0 == Event::Start(Tag::Paragraph))
+            else {
+                i += 1;
+                continue
+            };
+        let indent = render::line_indent(source, events[i].1.start);
+        if !is_nested_list(events.get(end + 1), indent)
+            || render::definition_list_lines(source, events[i+1..end].to_vec()).is_err() {
+            i = end + 1;
+            continue
+        }
+        let mut next = end + 1;
+        while is_nested_list(events.get(next), indent) {
+            let mut depth = 0;
+            for (event, _) in &events[next..] {
+                next += 1;
+                match event {
+                    Event::Start(_) => depth += 1,
+                    Event::End(_) => depth -= 1,
+                    _ => ()
+                }
+                if depth == 0 {
+                    break
+                }
+            }
+        }
+        // the end of the paragraph goes after the lists
+        events[end..next].rotate_left(1);
+        i = next;
+    }
+}
+
+/// splits the frontmatter at the start of `source`, if its format
+/// is enabled by the parsing options of `props`.
+/// A json frontmatter is enabled with any of the metadata block options
+fn frontmatter<'s>(source: &'s str, props: &MarkdownProps) -> frontmatter::SplitFrontmatter<'s> {
+    match FrontmatterFormat::detect(source) {
+        Some(format) if frontmatter_enabled(format, props) => split_frontmatter(source),
+        _ => (None, source)
+    }
+}
+
+/// returns true if the frontmatters written in `format`
+/// are enabled by the parsing options of `props`
+fn frontmatter_enabled(format: FrontmatterFormat, props: &MarkdownProps) -> bool {
+    let options = props.parse_options.copied().unwrap_or(Options::all());
+    let yaml = options.contains(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+    let toml = options.contains(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+    match format {
+        FrontmatterFormat::Yaml => yaml,
+        FrontmatterFormat::Toml => toml,
+        FrontmatterFormat::Json => yaml || toml,
+    }
+}
+
+/// the options of the parser: the `parse_options` of `props`,
+/// with the toggles of single options applied on top of them
+fn parse_options(props: &MarkdownProps) -> Options {
+    let mut options = props.parse_options.copied().unwrap_or(Options::all());
+    if let Some(smart_punctuation) = props.smart_punctuation {
+        options.set(Options::ENABLE_SMART_PUNCTUATION, smart_punctuation);
+    }
+    options
+}
+
+/// parses `source` with the parsing options of `props`,
+/// and applies the `event_preprocessor` of `props`.
+/// The frontmatter is skipped, but the ranges are still relative to `source`
+fn parse<'a>(source: &'a str, props: &MarkdownProps) -> Vec<(Event<'a>, Range<usize>)> {
+    let (_, body) = frontmatter(source, props);
+    parse_from(source, source.len() - body.len(), props)
+}
+
+/// parses `source` after `offset` like [`parse`], without looking for a frontmatter.
+/// The ranges are still relative to `source`
+fn parse_from<'a>(source: &'a str, offset: usize, props: &MarkdownProps) -> Vec<(Event<'a>, Range<usize>)> {
+    let body = &source[offset..];
+    let mut events = ParserOffsetIter::new_ext(body, parse_options(props), props.wikilinks)
+        .map(|(e, r)| (e, r.start+offset..r.end+offset))
+        .collect();
+    if let Some(preprocess) = props.event_preprocessor {
+        preprocess(&mut events)
+    }
+    events
+}
+
+#[cfg_attr(not(feature="emoji"), allow(unused_variables))]
+/// applies the text transformations enabled in `props` to `s`
+fn transform_text(props: &MarkdownProps, s: &mut CowStr) {
+    #[cfg(feature="emoji")]
+    if props.emoji_shortcodes {
+        if let Some(t) = utils::replace_shortcodes(s) {
+            *s = t.into()
+        }
+    }
+}
+
+/// removes the paragraphs made of abbreviation definitions from `events`,
+/// and returns the title of each abbreviation
+fn take_abbreviations(source: &str, events: &mut Vec<(Event, Range<usize>)>) -> BTreeMap<String, String> {
+    let mut abbreviations = BTreeMap::new();
+    let mut kept = Vec::with_capacity(events.len());
+    let mut drained = std::mem::take(events).into_iter();
+    while let Some((event, range)) = drained.next() {
+        let definitions: Option<Vec<_>> = match &event {
+            Event::Start(Tag::Paragraph) => source[range.clone()].lines()
+                .map(utils::parse_abbreviation)
+                .collect(),
+            _ => None
+        };
+        match definitions {
+            Some(definitions) => {
+                abbreviations.extend(definitions.into_iter()
+                    .map(|(abbreviation, title)| (abbreviation.to_string(), title.to_string())));
+                drained.by_ref().find(|(e, _)| *e == Event::End(TagEnd::Paragraph));
+            },
+            None => kept.push((event, range))
+        }
+    }
+    *events = kept;
+    abbreviations
+}
+
+/// removes the top-level blocks of `events` after the first `max` events,
+/// keeping only the blocks that fit entirely.
+/// Returns true if some events were removed
+fn truncate_events(events: &mut Vec<(Event, Range<usize>)>, max: usize) -> bool {
+    if events.len() <= max {
+        return false
+    }
+    let mut depth = 0usize;
+    let mut boundary = 0;
+    for (i, (event, _)) in events.iter().enumerate().take(max) {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => ()
+        }
+        if depth == 0 {
+            boundary = i + 1;
+        }
+    }
+    events.truncate(boundary);
+    true
+}
+
+/// removes the start and the end of the paragraph of `events`
+/// if it is its only top-level block
+fn unwrap_single_paragraph(events: &mut Vec<(Event, Range<usize>)>) {
+    let is_single = matches!(events.first(), Some((Event::Start(Tag::Paragraph), _)))
+        && events.iter().position(|(e, _)| *e == Event::End(TagEnd::Paragraph)) == Some(events.len() - 1);
+    if is_single {
+        events.pop();
+        events.remove(0);
+    }
+}
+
+/// moves the paragraphs starting with `prefix`, like `Table: Some caption`,
+/// right before or after a table into the table, as its first child,
+/// and removes the prefix. The renderer turns them into captions.
+/// A paragraph between two tables is the caption of the second one
+fn move_table_captions(events: &mut Vec<(Event, Range<usize>)>, prefix: &str) {
+    let is_caption = |events: &[(Event, Range<usize>)], i: usize| matches!(
+        events.get(i..i+2),
+        Some([(Event::Start(Tag::Paragraph), _), (Event::Text(s), _)]) if s.starts_with(prefix)
+    );
+    let paragraph_end = |events: &[(Event, Range<usize>)], start: usize| events[start..].iter()
+        .position(|(e, _)| *e == Event::End(TagEnd::Paragraph))
+        .map(|i| start + i + 1);
+
+    // the index of the start of each table with a caption,
+    // and the indices of the events of its caption
+    let mut captions: BTreeMap<usize, Range<usize>> = BTreeMap::new();
+    let mut paragraph_start = 0;
+    let mut last_paragraph: Option<Range<usize>> = None;
+    let mut table_start = 0;
+    for (i, (event, _)) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) => paragraph_start = i,
+            Event::End(TagEnd::Paragraph) => last_paragraph = Some(paragraph_start..i+1),
+            Event::Start(Tag::Table(_)) => {
+                table_start = i;
+                let before = last_paragraph.clone()
+                    .filter(|p| p.end == i && is_caption(events, p.start));
+                captions.extend(before.map(|p| (i, p)));
+            },
+            Event::End(TagEnd::Table) if !captions.contains_key(&table_start) => {
+                let after = Some(i + 1)
+                    .filter(|&start| is_caption(events, start))
+                    .and_then(|start| Some(start..paragraph_end(events, start)?))
+                    .filter(|p| !matches!(events.get(p.end), Some((Event::Start(Tag::Table(_)), _))));
+                captions.extend(after.map(|p| (table_start, p)));
+            },
+            _ => ()
+        }
+    }
+    if captions.is_empty() {
+        return
+    }
+
+    let mut old: Vec<_> = std::mem::take(events).into_iter().map(Some).collect();
+    for i in 0..old.len() {
+        if captions.values().any(|c| c.contains(&i)) {
+            continue
+        }
+        events.extend(old[i].take());
+        if let Some(caption) = captions.get(&i) {
+            for (j, (event, range)) in old[caption.clone()].iter_mut().filter_map(Option::take).enumerate() {
+                match event {
+                    Event::Text(s) if j == 1 => {
+                        let text = s[prefix.len()..].trim_start();
+                        if !text.is_empty() {
+                            events.push((Event::Text(text.to_string().into()), range))
+                        }
+                    },
+                    event => events.push((event, range))
+                }
+            }
+        }
+    }
+}
+
+/// renders a stream of markdown `events` parsed from `source`,
+/// one top-level element at a time.
+/// Unlike [`render_markdown`], the events are not preprocessed
+/// (emoji shortcodes, ...) and the katex stylesheet is not mounted
+pub fn render_events<'a, 'callback, F, I>(cx: F, source: &'a str, events: I)
+    -> EventRenderer<'a, 'callback, I::IntoIter, F>
+where I: IntoIterator<Item=(Event<'a>, Range<usize>)>,
+      'callback: 'a,
+      F: Context<'a, 'callback>,
+{
+    EventRenderer::new(cx, source, events.into_iter())
+}
+
+#[cfg(target_arch="wasm32")]
+thread_local! {
+    /// the links mounted in the page so far
+    static MOUNTED_LINKS: std::cell::RefCell<std::collections::HashSet<String>> = Default::default();
+}
+
+/// forgets the links mounted so far, so that the next documents mount them again.
+/// Call it when the links mounted by the crate were removed from the page,
+/// like after replacing its `head` when navigating in a single page application.
+/// Outside of the browser, the links are mounted for every document and it does nothing
+pub fn forget_mounted_links() {
+    #[cfg(target_arch="wasm32")]
+    MOUNTED_LINKS.with(|links| links.borrow_mut().clear());
+}
+
+/// calls [`Context::mount_dynamic_link_with`] if `href` was not mounted before.
+/// The links stay mounted until [`forget_mounted_links`] is called.
+/// The page is shared in the browser only: elsewhere, like when rendering
+/// on a server, the link is mounted for every document
+fn mount_link_once<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    rel: &str,
+    href: &str,
+    integrity: Option<&str>,
+    crossorigin: Option<&str>
+    )
+where 'callback: 'a
+{
+    #[cfg(target_arch="wasm32")]
+    if !MOUNTED_LINKS.with(|links| links.borrow_mut().insert(href.to_string())) {
+        return
+    }
+    cx.mount_dynamic_link_with(rel, href, integrity, crossorigin)
 }
 
 pub fn render_markdown<'a, 'callback, F: Context<'a, 'callback>>(
@@ -305,11 +1667,82 @@ pub fn render_markdown<'a, 'callback, F: Context<'a, 'callback>>(
     source: &'a str, 
     ) -> F::View 
 {
+    render_parsed(cx, source, parse(source, &cx.props()))
+}
+
+/// renders `source` like [`render_markdown`], without parsing it again
+/// if it was the last document rendered with `cache`, with the same parsing options
+pub fn render_markdown_cached<'a, 'callback, F: Context<'a, 'callback>, V>(
+    cx: F,
+    source: &'a str,
+    cache: &mut RenderCache<V>,
+    ) -> F::View
+{
+    let stream = cache.parse(source, &cx.props());
+    render_parsed(cx, source, stream)
+}
+
+/// renders the events of `source`, as returned by [`parse`]
+fn render_parsed<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    source: &'a str,
+    stream: Vec<(Event<'a>, Range<usize>)>,
+    ) -> F::View
+{
+    let prepared = prepare(cx, source, stream);
+
+    let mut elements = Vec::new();
+    let mut renderer = render_events(cx, source, prepared.stream).with_abbreviations(prepared.abbreviations);
+    while let Some(view) = renderer.next() {
+        elements.push((renderer.key(), view));
+    }
+
+    finish(cx, elements, prepared.has_math, prepared.truncated)
+}
+
+/// the events of a document, ready to be rendered
+struct Prepared<'a> {
+    stream: Vec<(Event<'a>, Range<usize>)>,
+    /// true if the document contains math
+    has_math: bool,
+    /// the title of each abbreviation of the document
+    abbreviations: BTreeMap<String, String>,
+    /// true if the end of the document was cut by `max_events`
+    truncated: bool,
+}
+
+/// sets the frontmatter, reports the diagnostics, and applies
+/// the transformations enabled in the props to the events of `source`
+fn prepare<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    source: &'a str,
+    stream: Vec<(Event<'a>, Range<usize>)>,
+    ) -> Prepared<'a>
+where 'callback: 'a
+{
+
+    if let (Some((format, raw)), _) = frontmatter(source, &cx.props()) {
+        cx.set_frontmatter_with_format(format, raw.to_string());
+    }
+
+    if cx.has_diagnostic_reporter() {
+        for diagnostic in diagnostics::diagnose(cx, source, &stream) {
+            cx.report_diagnostic(diagnostic)
+        }
+    }
+
+    transform(cx, source, stream)
+}
 
-    let parse_options_default = Options::all();
-    let options = cx.props().parse_options.unwrap_or(&parse_options_default);
-    let mut stream: Vec<_>
-        = ParserOffsetIter::new_ext(source, *options, cx.props().wikilinks).collect();
+/// applies the transformations enabled in the props to the events of `source`,
+/// without reporting anything about the document
+fn transform<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    source: &'a str,
+    stream: Vec<(Event<'a>, Range<usize>)>,
+    ) -> Prepared<'a>
+where 'callback: 'a
+{
 
     #[cfg(feature="debug")]
     {
@@ -317,24 +1750,139 @@ pub fn render_markdown<'a, 'callback, F: Context<'a, 'callback>>(
         cx.send_debug_info(debug_info)
     }
 
-    if cx.props().hard_line_breaks {
-        for (r, _) in &mut stream {
-            if *r == Event::SoftBreak {
-                *r = Event::HardBreak
-            }
+    transform_events(&cx.props(), source, stream, cx.has_custom_broken_links())
+}
+
+/// the transformations of [`transform`], which only depend on the props.
+/// The broken links are marked if `mark_broken_links` is set
+fn transform_events<'a>(
+    props: &MarkdownProps,
+    source: &'a str,
+    mut stream: Vec<(Event<'a>, Range<usize>)>,
+    mark_broken_links: bool,
+    ) -> Prepared<'a>
+{
+    let truncated = props.max_events
+        .is_some_and(|max| truncate_events(&mut stream, max));
+
+    if props.strict_dollar_math {
+        stream = dollar_math::guard_dollar_math(source, stream);
+    }
+
+    if props.latex_math_delimiters {
+        stream = latex_delimiters::convert_latex_delimiters(source, stream);
+    }
+
+    if props.inline_footnotes && parse_options(props).contains(Options::ENABLE_FOOTNOTES) {
+        stream = inline_footnotes::convert_inline_footnotes(source, stream);
+    }
+
+    if mark_broken_links {
+        stream = broken_links::mark_broken_links(source, stream);
+    }
+
+    // the katex stylesheet is only needed if there is some math
+    let mut has_math = false;
+    // text transformations don't apply to the content of code blocks
+    let mut in_code_block = false;
+    for (r, _) in &mut stream {
+        match r {
+            Event::Math(..) => has_math = true,
+            Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => in_code_block = false,
+            Event::Text(s) if !in_code_block => transform_text(props, s),
+            _ => ()
         }
     }
 
-    let elements = Renderer::new(cx, &mut stream.into_iter())
-        .collect::<Vec<_>>();
+    if props.linkify {
+        let mention_url = props.mention_url;
+        stream = linkify::linkify(source, stream, |text| linkify::find_links(text, mention_url));
+    }
+    else if props.autolink_emails {
+        stream = linkify::linkify(source, stream, linkify::find_emails);
+    }
+    if let Some(templates) = props.reference_templates {
+        stream = linkify::linkify(source, stream, |text| linkify::find_references(text, templates));
+    }
 
+    let abbreviations = match props.abbreviations {
+        true => take_abbreviations(source, &mut stream),
+        false => BTreeMap::new(),
+    };
 
-    cx.mount_dynamic_link(
-        "stylesheet",
-        "https://cdn.jsdelivr.net/npm/katex@0.16.7/dist/katex.min.css",
-        "sha384-3UiQGuEI4TTMaFmGIZumfRPtfKQ3trwQE2JgosJxCnGmQpL/lJdjpcHkaaFwHlcI",
-        "anonymous"
-    );
+    if let Some(prefix) = props.table_caption_prefix {
+        move_table_captions(&mut stream, prefix);
+    }
 
-    cx.el_fragment(elements)
+    if props.tight {
+        unwrap_single_paragraph(&mut stream);
+    }
+
+    if props.definition_lists {
+        nest_definition_lists(source, &mut stream);
+    }
+
+    Prepared {
+        stream,
+        has_math,
+        abbreviations,
+        truncated,
+    }
+}
+
+/// mounts the katex stylesheet, if the math is rendered with katex
+fn mount_math_stylesheet<'a, 'callback, F: Context<'a, 'callback>>(cx: F)
+where 'callback: 'a
+{
+    if cx.props().math_backend == MathBackend::Katex {
+        let default_stylesheet = KatexStylesheet::default();
+        let stylesheet = cx.props().katex_stylesheet.unwrap_or(&default_stylesheet);
+        mount_link_once(
+            cx,
+            "stylesheet",
+            &stylesheet.url,
+            stylesheet.integrity.as_deref(),
+            stylesheet.crossorigin.as_deref(),
+        );
+    }
+}
+
+/// the key of the element added after the ones of the document,
+/// for [`Context::el_keyed_fragment`]
+const TRUNCATION_MARKER_KEY: &str = "truncated";
+
+/// mounts the stylesheets needed by the document,
+/// and puts its rendered `elements` together with their keys,
+/// followed by the truncation marker if the document was `truncated`
+fn finish<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    mut elements: Vec<(String, F::View)>,
+    has_math: bool,
+    truncated: bool,
+    ) -> F::View
+{
+    if has_math {
+        mount_math_stylesheet(cx);
+    }
+
+    if truncated {
+        elements.push((TRUNCATION_MARKER_KEY.to_string(), cx.render_truncation_marker()));
+    }
+
+    match cx.props().container_class {
+        Some(class) => cx.el_with_attributes(
+            HtmlElement::Div,
+            cx.el_keyed_fragment(elements),
+            ElementAttributes {
+                classes: vec![class.to_string().into()],
+                attributes: cx.props().theme
+                    .map(|theme| ("data-theme".to_string(), theme.to_string()))
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            }
+        ),
+        None => cx.el_keyed_fragment(elements)
+    }
 }
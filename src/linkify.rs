@@ -0,0 +1,342 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, LinkType, CowStr};
+
+use crate::ReferenceTemplates;
+use crate::utils;
+
+/// the prefixes starting a bare url
+const URL_PREFIXES: &[(&str, &str)] = &[
+    ("https://", ""),
+    ("http://", ""),
+    ("www.", "https://"),
+];
+
+#[derive(Debug, PartialEq)]
+/// a link found in a text by [`find_links`]
+pub struct FoundLink {
+    /// the position of the link in the text
+    pub range: Range<usize>,
+    /// the url of the link
+    pub url: String,
+    pub link_type: LinkType,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_email_local_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-')
+}
+
+fn is_domain_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-')
+}
+
+fn is_mention_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-')
+}
+
+/// the length of the url at the start of `s`, without
+/// its trailing punctuation and unbalanced closing parentheses
+fn url_len(s: &str) -> usize {
+    let mut len = s.find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+        .unwrap_or(s.len());
+    loop {
+        let url = &s[..len];
+        let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '*', '_', '~']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(t) if trimmed.matches('(').count() < trimmed.matches(')').count() => t,
+            _ => trimmed
+        };
+        if trimmed.len() == len {
+            return len
+        }
+        len = trimmed.len();
+    }
+}
+
+/// the length of the domain at the start of `s`,
+/// if it is a valid domain with a dot
+fn domain_len(s: &str) -> Option<usize> {
+    let len = s.find(|c| !is_domain_char(c)).unwrap_or(s.len());
+    let domain = s[..len].trim_end_matches(['.', '-']);
+    let valid = domain.contains('.')
+        && !domain.starts_with(['.', '-'])
+        && !domain.contains("..");
+    valid.then_some(domain.len())
+}
+
+/// finds the bare urls, like `https://example.com` or `www.example.com`,
+/// and the email addresses in `text`.
+/// If `mention_url` is set, the mentions like `@name` are found too,
+/// with `mention_url` as url where `{}` is replaced by the name.
+/// The trailing punctuation is not part of the links
+pub fn find_links(text: &str, mention_url: Option<&str>) -> Vec<FoundLink> {
+    let mut links = Vec::new();
+    // the end of the last link
+    let mut end = 0;
+    let mut previous: Option<char> = None;
+
+    for (i, c) in text.char_indices() {
+        if i < end {
+            previous = Some(c);
+            continue
+        }
+        let rest = &text[i..];
+        let at_word_start = !previous.is_some_and(is_word_char);
+
+        let url = URL_PREFIXES.iter()
+            .filter(|_| at_word_start)
+            .find(|(prefix, _)| rest.starts_with(prefix))
+            .and_then(|(prefix, scheme)| {
+                let len = url_len(rest);
+                (len > prefix.len()).then(|| (len, format!("{scheme}{}", &rest[..len])))
+            });
+        if let Some((len, url)) = url {
+            links.push(FoundLink { range: i..i+len, url, link_type: LinkType::Autolink });
+            end = i + len;
+        }
+        else if c == '@' {
+            let local_start = text[end..i]
+                .rfind(|c| !is_email_local_char(c))
+                .map_or(end, |j| end + j + 1);
+            let local = &text[local_start..i];
+            let domain = domain_len(&text[i+1..]);
+            match (local.is_empty(), domain, mention_url) {
+                (false, Some(len), _) if !local.starts_with('.') => {
+                    let range = local_start..i+1+len;
+                    let url = format!("mailto:{}", &text[range.clone()]);
+                    links.push(FoundLink { range: range.clone(), url, link_type: LinkType::Email });
+                    end = range.end;
+                },
+                (true, _, Some(mention_url)) if at_word_start => {
+                    let len = text[i+1..].find(|c| !is_mention_char(c)).unwrap_or(text.len() - i - 1);
+                    let name = text[i+1..i+1+len].trim_end_matches('-');
+                    if !name.is_empty() {
+                        let url = mention_url.replace("{}", name);
+                        links.push(FoundLink { range: i..i+1+name.len(), url, link_type: LinkType::Shortcut });
+                        end = i + 1 + name.len();
+                    }
+                },
+                _ => ()
+            }
+        }
+        previous = Some(c);
+    }
+    links
+}
+
+/// finds the email addresses in `text`, like [`find_links`] but without the urls.
+/// The addresses inside of a url, like `https://example.com/me@example.com`, are part of it
+pub fn find_emails(text: &str) -> Vec<FoundLink> {
+    find_links(text, None).into_iter()
+        .filter(|link| link.link_type == LinkType::Email)
+        .collect()
+}
+
+/// finds the references to issues, like `#123`, and to users, like `@name`,
+/// in `text`, for the templates set in `templates`.
+/// The `#` must be followed by digits only, so `#fff` or `#12ab` are not references
+pub fn find_references(text: &str, templates: &ReferenceTemplates) -> Vec<FoundLink> {
+    let mut links = Vec::new();
+    // the end of the last reference
+    let mut end = 0;
+    let mut previous: Option<char> = None;
+
+    for (i, c) in text.char_indices() {
+        let at_word_start = !previous.is_some_and(|p| is_word_char(p) || matches!(p, '&' | '#' | '@'));
+        previous = Some(c);
+        if i < end || !at_word_start {
+            continue
+        }
+        let rest = &text[i+1..];
+        let found = match (c, &templates.issue, &templates.user) {
+            ('#', Some(template), _) => {
+                let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                let number = &rest[..len];
+                let valid = !number.is_empty() && !rest[len..].starts_with(is_word_char);
+                valid.then(|| (len, template.replace("{n}", number)))
+            },
+            ('@', _, Some(template)) => {
+                let len = rest.find(|c| !is_mention_char(c)).unwrap_or(rest.len());
+                let name = rest[..len].trim_end_matches('-');
+                (!name.is_empty()).then(|| (name.len(), template.replace("{name}", name)))
+            },
+            _ => None
+        };
+        if let Some((len, url)) = found {
+            links.push(FoundLink { range: i..i+1+len, url, link_type: LinkType::Shortcut });
+            end = i + 1 + len;
+        }
+    }
+    links
+}
+
+/// wraps the links found by `find` in the text events of `events`,
+/// like [`find_links`], into link events.
+/// The text of links, code and metadata blocks is skipped.
+/// Adjacent text events are merged before looking for links
+pub fn linkify<'a>(source: &str, events: Vec<(Event<'a>, Range<usize>)>,
+    find: impl Fn(&str) -> Vec<FoundLink>) -> Vec<(Event<'a>, Range<usize>)> {
+    let mut result: Vec<(Event<'a>, Range<usize>)> = Vec::with_capacity(events.len());
+    // the depth of the links and images, and of the code and metadata blocks
+    let mut skipped = 0;
+    let mut text: Option<(String, Range<usize>)> = None;
+
+    let flush = |text: &mut Option<(String, Range<usize>)>, result: &mut Vec<_>| {
+        if let Some((s, range)) = text.take() {
+            push_linkified(source, s, range, &find, result)
+        }
+    };
+
+    for (event, range) in events {
+        match &event {
+            Event::Text(s) if skipped == 0 => {
+                match &mut text {
+                    Some((t, r)) if r.end == range.start => {
+                        t.push_str(s);
+                        r.end = range.end;
+                    },
+                    _ => {
+                        flush(&mut text, &mut result);
+                        text = Some((s.to_string(), range));
+                    }
+                }
+                continue
+            },
+            Event::Start(Tag::Link{..} | Tag::Image{..} | Tag::CodeBlock(_) | Tag::MetadataBlock(_)) =>
+                skipped += 1,
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) =>
+                skipped -= 1,
+            _ => ()
+        }
+        flush(&mut text, &mut result);
+        result.push((event, range));
+    }
+    flush(&mut text, &mut result);
+    result
+}
+
+/// pushes the text `s` at `range` into `events`, with its links
+fn push_linkified<'a>(source: &str, s: String, range: Range<usize>, find: &impl Fn(&str) -> Vec<FoundLink>,
+    events: &mut Vec<(Event<'a>, Range<usize>)>) {
+    let links = find(&s);
+    if links.is_empty() {
+        events.push((Event::Text(s.into()), range));
+        return
+    }
+
+    let locate = |r: Range<usize>| utils::locate(source, &s, range.clone(), r);
+
+    let mut start = 0;
+    for link in links {
+        if start < link.range.start {
+            let text = CowStr::from(s[start..link.range.start].to_string());
+            events.push((Event::Text(text), locate(start..link.range.start)));
+        }
+        let r = locate(link.range.clone());
+        let tag = Tag::Link {
+            link_type: link.link_type,
+            dest_url: link.url.into(),
+            title: "".into(),
+            id: "".into(),
+        };
+        events.push((Event::Start(tag), r.clone()));
+        events.push((Event::Text(s[link.range.clone()].to_string().into()), r.clone()));
+        events.push((Event::End(TagEnd::Link), r));
+        start = link.range.end;
+    }
+    if start < s.len() {
+        events.push((Event::Text(s[start..].to_string().into()), locate(start..s.len())));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<(&str, String)> {
+        find_links(text, Some("/users/{}"))
+            .into_iter()
+            .map(|link| (&text[link.range], link.url))
+            .collect()
+    }
+
+    #[test]
+    fn bare_urls(){
+        assert_eq!(urls("see https://example.com/a_(b), or www.example.org."), vec![
+            ("https://example.com/a_(b)", "https://example.com/a_(b)".to_string()),
+            ("www.example.org", "https://www.example.org".to_string()),
+        ]);
+        assert_eq!(urls("(https://example.com/page)"), vec![
+            ("https://example.com/page", "https://example.com/page".to_string()),
+        ]);
+        assert_eq!(urls("https:// and xhttps://a.b"), vec![]);
+    }
+
+    #[test]
+    fn emails_and_mentions(){
+        assert_eq!(urls("mail me.too@example.com, @someone."), vec![
+            ("me.too@example.com", "mailto:me.too@example.com".to_string()),
+            ("@someone", "/users/someone".to_string()),
+        ]);
+        assert_eq!(urls("a@b and @"), vec![]);
+        assert!(find_links("@someone", None).is_empty());
+    }
+
+    #[test]
+    fn emails(){
+        let emails = |text| find_emails(text)
+            .into_iter()
+            .map(|link| (&text[link.range], link.url))
+            .collect::<Vec<_>>();
+        assert_eq!(emails("write to me+tag@mail.example.co.uk."), vec![
+            ("me+tag@mail.example.co.uk", "mailto:me+tag@mail.example.co.uk".to_string()),
+        ]);
+        assert_eq!(emails("(me@x.com), <you@y.org>;"), vec![
+            ("me@x.com", "mailto:me@x.com".to_string()),
+            ("you@y.org", "mailto:you@y.org".to_string()),
+        ]);
+        assert_eq!(emails("me@localhost, @me, https://example.com/me@example.com"), vec![]);
+    }
+
+    #[test]
+    fn references(){
+        let templates = ReferenceTemplates {
+            issue: Some("/issues/{n}".to_string()),
+            user: Some("/users/{name}".to_string()),
+        };
+        let references = |text| find_references(text, &templates)
+            .into_iter()
+            .map(|link| (&text[link.range], link.url))
+            .collect::<Vec<_>>();
+        assert_eq!(references("fixes #12, thanks @alice-."), vec![
+            ("#12", "/issues/12".to_string()),
+            ("@alice", "/users/alice".to_string()),
+        ]);
+        assert_eq!(references("#fff #12ab a#1 me@example.com &#35; ##1"), vec![]);
+
+        let events = vec![
+            (Event::Text("see #1 ".into()), 0..7),
+            (Event::Code("#2".into()), 7..11),
+            (Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: "/".into(),
+                title: "".into(),
+                id: "".into(),
+            }), 11..18),
+            (Event::Text("#3".into()), 12..14),
+            (Event::End(TagEnd::Link), 11..18),
+        ];
+        let linked = linkify("see #1 `#2`[#3](/)", events, |text| find_references(text, &templates));
+        let texts: Vec<_> = linked.iter()
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Link{dest_url, ..}) => Some(dest_url.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["/issues/1", "/"]);
+    }
+}
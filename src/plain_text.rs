@@ -0,0 +1,103 @@
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd};
+
+use crate::MarkdownProps;
+
+/// returns true if `tag` ends a block of text
+fn is_block_end(tag: &TagEnd) -> bool {
+    matches!(tag,
+        TagEnd::Paragraph
+        | TagEnd::Heading(_)
+        | TagEnd::BlockQuote
+        | TagEnd::CodeBlock
+        | TagEnd::HtmlBlock
+        | TagEnd::List(_)
+        | TagEnd::Item
+        | TagEnd::FootnoteDefinition
+        | TagEnd::Table
+        | TagEnd::TableHead
+        | TagEnd::TableRow
+    )
+}
+
+/// the text of a list of events, without formatting, images, or html.
+/// Soft breaks become spaces, table cells are separated by spaces,
+/// and blocks are separated by empty lines
+fn plain_text<'e, 'a: 'e>(events: impl IntoIterator<Item=&'e Event<'a>>) -> String {
+    let mut text = String::new();
+    // the separator to insert before the next text
+    let mut separator = "";
+    // the depth of the images we are in
+    let mut images = 0;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Image{..}) => images += 1,
+            Event::End(TagEnd::Image) => images -= 1,
+            Event::End(t) if is_block_end(t) => separator = "\n\n",
+            Event::End(TagEnd::TableCell) if separator.is_empty() => separator = " ",
+            Event::Text(s) | Event::Code(s) if images == 0 => {
+                if !text.is_empty() {
+                    text.push_str(separator);
+                }
+                separator = "";
+                text.push_str(s);
+            },
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            _ => ()
+        }
+    }
+    text.trim_end().to_string()
+}
+
+/// `to_plain_text(source, props)` extracts the text of `source`,
+/// for previews or search indexing.
+/// The formatting, images, html and urls are dropped,
+/// but the text of the links is kept.
+/// The document is parsed like [`crate::render_markdown`] does with the same `props`
+pub fn to_plain_text(source: &str, props: &MarkdownProps) -> String {
+    let events = crate::parse(source, props);
+    plain_text(events.iter().map(|(e, _)| e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pulldown_cmark_wikilink::{CowStr, LinkType};
+
+    #[test]
+    fn blocks_and_links(){
+        let link = Tag::Link {
+            link_type: LinkType::Inline,
+            dest_url: CowStr::from("https://example.com"),
+            title: CowStr::from(""),
+            id: CowStr::from(""),
+        };
+        let image = Tag::Image {
+            link_type: LinkType::Inline,
+            dest_url: CowStr::from("image.png"),
+            title: CowStr::from(""),
+            id: CowStr::from(""),
+        };
+        let events = vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text("some ".into()),
+            Event::Start(Tag::Strong),
+            Event::Text("bold".into()),
+            Event::End(TagEnd::Strong),
+            Event::SoftBreak,
+            Event::Start(link),
+            Event::Text("link".into()),
+            Event::End(TagEnd::Link),
+            Event::Start(image),
+            Event::Text("alt".into()),
+            Event::End(TagEnd::Image),
+            Event::InlineHtml("<br>".into()),
+            Event::End(TagEnd::Paragraph),
+            Event::Start(Tag::Paragraph),
+            Event::Code("code".into()),
+            Event::End(TagEnd::Paragraph),
+        ];
+        assert_eq!(plain_text(&events), "some bold link\n\ncode");
+    }
+}
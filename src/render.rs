@@ -1,31 +1,78 @@
 use core::ops::Range;
 
 use core::marker::PhantomData;
+use core::iter::Peekable;
 
-use syntect::parsing::SyntaxSet;
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::panic::{self, AssertUnwindSafe};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+#[cfg(feature="syntect")]
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature="syntect")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature="syntect")]
+use syntect::parsing::{SyntaxSet, ParseState, ScopeStack};
+#[cfg(feature="syntect")]
 use syntect::highlighting::ThemeSet;
+#[cfg(feature="syntect")]
+use syntect::html::{css_for_theme_with_class_style, ClassStyle};
+#[cfg(feature="syntect")]
+use syntect::util::LinesWithEndings;
 
-use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CodeBlockKind, Alignment};
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd, CodeBlockKind, Alignment, LinkType, MetadataBlockKind};
 
-#[cfg(features="maths")]
 use pulldown_cmark_wikilink::MathMode;
 
-#[cfg(features="maths")]
-use katex;
-
-use crate::utils::as_closing_tag;
+use crate::utils::{
+    as_closing_tag,
+    cell_spans,
+    events_text,
+    locate,
+    parse_alert_marker,
+    parse_details_marker,
+    parse_image_size,
+    resolve_url,
+    split_abbreviations,
+    split_markers,
+    CellMerge,
+    LineIndex,
+    MarkedText,
+    Slugs,
+};
 use super::{
+    CowStr,
+    HtmlElement,
     Context,
     LinkDescription,
+    ImageDescription,
+    HeadingDescription,
+    BrokenLink,
+    EmbedDescription,
+    CodeBlockProps,
     MdComponentProps,
+    ComponentError,
     ElementAttributes,
-    HtmlError
+    HtmlError,
+    ImageAttributes,
+    ImageLoading,
+    CodeFence,
+    FrontmatterFormat,
+    MarkdownTag,
 };
 
+#[cfg(any(feature="maths", feature="mathml"))]
+use super::MathBackend;
+
 use super::HtmlElement::*;
 
 use crate::component::{ComponentCall, CustomHtmlTag};
+use crate::sanitize::{is_safe_url, sanitize_html, strip_html_comments};
 
+#[cfg(feature="syntect")]
 // load the default syntect options to highlight code
 lazy_static::lazy_static!{
     static ref SYNTAX_SET: SyntaxSet = {
@@ -34,8 +81,27 @@ lazy_static::lazy_static!{
     static ref THEME_SET: ThemeSet = {
         ThemeSet::load_defaults()
     };
+    /// the code blocks that were already highlighted,
+    /// so that re-rendering a document doesn't tokenize them again
+    static ref HIGHLIGHT_CACHE: Mutex<HighlightCache> = {
+        Mutex::new(HighlightCache::default())
+    };
 }
 
+#[cfg(feature="syntect")]
+/// the theme used when `MarkdownProps::theme` is not set
+const DEFAULT_THEME: &str = "base16-ocean.light";
+
+#[cfg(feature="syntect")]
+/// the number of highlighted blocks kept in `HIGHLIGHT_CACHE`.
+/// Past it, the oldest block is dropped
+const HIGHLIGHT_CACHE_CAPACITY: usize = 256;
+
+#[cfg(feature="syntect")]
+/// the prefix of the classes given to the highlighted tokens,
+/// like `hl-keyword`
+const HIGHLIGHT_CLASS_PREFIX: &str = "hl-";
+
 
 
 impl HtmlError {
@@ -61,6 +127,8 @@ impl ToString for HtmlError {
                 format!("`{s}`: not implemented"),
             HtmlError::CustomComponent{name, msg} =>
                 format!("Custom component `{name}` failed: `{msg}`"),
+            HtmlError::CustomCodeBlock{lang, msg} =>
+                format!("Custom code block `{lang}` failed: `{msg}`"),
             HtmlError::Syntax(s) =>
                 format!("syntax error: {s}"),
             HtmlError::Link(s) =>
@@ -72,99 +140,854 @@ impl ToString for HtmlError {
 
 
 
-/// `highlight_code(content, ss, ts)` render the content `content`
-/// with syntax highlighting
-fn highlight_code(theme_name: Option<&str>, content: &str, kind: &CodeBlockKind) -> Option<String> {
-    let lang = match kind {
-        CodeBlockKind::Fenced(x) => x,
-        CodeBlockKind::Indented => return None
-    };
+#[cfg(feature="syntect")]
+/// a code block, once highlighted
+struct HighlightedCode {
+    /// the tokens of each line of the code, with their classes
+    lines: Vec<Vec<(Vec<String>, String)>>,
+}
 
-    let theme_name = theme_name
-        .clone()
-        .unwrap_or("base16-ocean.light");
-    let theme = THEME_SET.themes.get(theme_name)
-        .expect("unknown theme")
-        .clone();
+#[cfg(feature="syntect")]
+/// the language, the theme and the source of a highlighted code block
+type HighlightKey = (String, String, String);
 
-    Some(
-        syntect::html::highlighted_html_for_string(
-            content,
-            &SYNTAX_SET,
-            SYNTAX_SET.find_syntax_by_token(lang)?,
-            &theme
-            ).ok()?
+#[cfg(feature="syntect")]
+#[derive(Default)]
+/// the highlighted code blocks, with the order in which they were added
+struct HighlightCache {
+    blocks: HashMap<HighlightKey, Arc<HighlightedCode>>,
+    order: VecDeque<HighlightKey>,
+}
+
+#[cfg(feature="syntect")]
+impl HighlightCache {
+    fn get(&self, key: &HighlightKey) -> Option<Arc<HighlightedCode>> {
+        self.blocks.get(key).cloned()
+    }
+
+    /// adds a highlighted block, dropping the oldest one
+    /// when the cache is full
+    fn insert(&mut self, key: HighlightKey, code: Arc<HighlightedCode>) {
+        if self.blocks.contains_key(&key) {
+            return
+        }
+        if self.order.len() >= HIGHLIGHT_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.blocks.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.blocks.insert(key, code);
+    }
+}
+
+#[cfg(feature="syntect")]
+/// `scope_classes(stack)` gives the classes of a token
+/// inside the scopes of `stack`, like `hl-keyword hl-control`
+fn scope_classes(stack: &ScopeStack) -> Vec<String> {
+    let mut classes: Vec<String> = Vec::new();
+    for scope in stack.as_slice() {
+        for atom in scope.build_string().split('.') {
+            let class = format!("{HIGHLIGHT_CLASS_PREFIX}{atom}");
+            if !classes.contains(&class) {
+                classes.push(class)
+            }
+        }
+    }
+    classes
+}
+
+#[cfg(feature="syntect")]
+/// `highlight_code(theme_name, content, lang)` splits `content`
+/// into tokens, with the classes of their scopes.
+/// The colors come from the stylesheet given by `code_theme_css(theme_name)`.
+/// Returns None if the theme or the language is unknown.
+fn highlight_code(theme_name: Option<&str>, content: &str, lang: &str) -> Option<Arc<HighlightedCode>> {
+    let theme_name = theme_name.unwrap_or(DEFAULT_THEME);
+    let key = (lang.to_string(), theme_name.to_string(), content.to_string());
+
+    if let Some(x) = HIGHLIGHT_CACHE.lock().ok()?.get(&key) {
+        return Some(x)
+    }
+
+    THEME_SET.themes.get(theme_name)?;
+    let syntax = SYNTAX_SET.find_syntax_by_token(lang)?;
+    let mut parser = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(content) {
+        let mut tokens: Vec<(Vec<String>, String)> = Vec::new();
+        let mut push_token = |classes: Vec<String>, text: &str| {
+            if text.is_empty() {
+                return
+            }
+            // merge consecutive tokens with the same classes,
+            // to keep the number of nodes low
+            match tokens.last_mut() {
+                Some((c, t)) if *c == classes => t.push_str(text),
+                _ => tokens.push((classes, text.to_string()))
+            }
+        };
+
+        let mut start = 0;
+        for (end, op) in parser.parse_line(line, &SYNTAX_SET).ok()? {
+            push_token(scope_classes(&stack), &line[start..end]);
+            stack.apply(&op).ok()?;
+            start = end;
+        }
+        push_token(scope_classes(&stack), &line[start..]);
+        lines.push(tokens);
+    }
+
+    let highlighted = Arc::new(HighlightedCode { lines });
+    HIGHLIGHT_CACHE.lock().ok()?.insert(key, highlighted.clone());
+
+    Some(highlighted)
+}
+
+#[cfg(feature="syntect")]
+/// `code_theme_css(theme)` gives the stylesheet that colors the code blocks
+/// highlighted with the syntect theme `theme`, or with `base16-ocean.light`
+/// when it is None. The tokens only get classes, so this stylesheet
+/// must be included in the page for the code to be colored.
+/// Returns None if the theme is unknown
+pub fn code_theme_css(theme: Option<&str>) -> Option<String> {
+    let theme = THEME_SET.themes.get(theme.unwrap_or(DEFAULT_THEME))?;
+    css_for_theme_with_class_style(theme, ClassStyle::SpacedPrefixed { prefix: HIGHLIGHT_CLASS_PREFIX }).ok()
+}
+
+/// renders a heading, with its slug as id
+pub(crate) fn render_heading<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    heading: HeadingDescription<F::View>,
+    ) -> F::View {
+    cx.el_with_attributes(
+        Heading(heading.level),
+        heading.content,
+        ElementAttributes {
+            id: Some(heading.slug),
+            attributes: heading.source_line.into_iter().map(source_line_attribute).collect(),
+            ..block_attributes(cx, &heading.range, MarkdownTag::Heading(heading.level))
+        }
+    )
+}
+
+/// the attributes of a block of markdown at `range`,
+/// with the hover handlers of the context
+fn block_attributes<'a, 'callback, F: Context<'a, 'callback>>(cx: F, range: &Range<usize>, tag: MarkdownTag)
+    -> ElementAttributes<F::Handler<F::MouseEvent>> {
+    ElementAttributes {
+        on_mouse_enter: cx.make_md_hover_handler(range.clone(), tag.clone(), true),
+        on_mouse_leave: cx.make_md_hover_handler(range.clone(), tag, false),
+        ..Default::default()
+    }
+}
+
+/// the `data-source-line` attribute of a block starting at the line `line`
+fn source_line_attribute(line: usize) -> (String, String) {
+    ("data-source-line".to_string(), line.to_string())
+}
+
+/// renders an error message, in a span with the `markdown-error` class
+pub(crate) fn render_error<'a, 'callback, F: Context<'a, 'callback>>(cx: F, message: String)
+    -> F::View {
+    cx.el_with_attributes(
+        Span,
+        cx.el_fragment(vec![
+            cx.el_text(message.into()),
+            cx.el_br(),
+        ]),
+        ElementAttributes {
+            classes: vec!["markdown-error".into()],
+            on_click: None,
+            ..Default::default()
+        }
     )
 }
 
 /// renders a source code in a code block, with syntax highlighting if possible.
 /// `cx`: the current markdown context
-/// `source`: the source to render 
+/// `fence`: the language of the code and the lines to highlight
+/// `source`: the source to render
 /// `range`: the position of the code in the original source
-fn render_code_block<'a, 'callback, F: Context<'a, 'callback>>(
+pub(crate) fn render_code_block<'a, 'callback, F: Context<'a, 'callback>>(
     cx: F,
-    source: String,
-    k: &CodeBlockKind,
+    fence: &CodeFence,
+    source: &str,
     range: Range<usize>
     ) -> F::View {
 
-    let code_attributes = ElementAttributes{
-        on_click: Some(cx.make_md_handler(range, true)),
+    let label = fence.lang.as_ref()
+        .filter(|_| cx.props().code_language_label)
+        .map(|lang| cx.el_with_attributes(
+            Span,
+            cx.el_text(lang.clone().into()),
+            ElementAttributes {
+                classes: vec!["code-language-label".into()],
+                ..Default::default()
+            }
+        ));
+    let button = cx.props().code_copy_button
+        .then(|| cx.make_copy_handler(source.to_string()))
+        .flatten()
+        .map(|handler| cx.el_with_attributes(
+            Button,
+            cx.el_text("Copy".into()),
+            ElementAttributes {
+                classes: vec!["copy-button".into()],
+                on_click: Some(handler),
+                ..Default::default()
+            }
+        ));
+    // the line is set on the outermost element
+    if label.is_none() && button.is_none() {
+        return code_pre(cx, fence, source, range, fence.source_line)
+    }
+
+    let pre = code_pre(cx, fence, source, range, None);
+    let children = label.into_iter().chain(button).chain([pre]).collect();
+    cx.el_with_attributes(
+        Div,
+        cx.el_fragment(children),
+        ElementAttributes {
+            classes: vec!["code-block-container".into()],
+            attributes: fence.source_line.into_iter().map(source_line_attribute).collect(),
+            ..Default::default()
+        }
+    )
+}
+
+/// renders a thematic break at `range`, starting at the line `source_line`
+pub(crate) fn render_rule<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    range: Range<usize>,
+    source_line: Option<usize>,
+    ) -> F::View {
+    let attributes = ElementAttributes{
+        classes: cx.props().hr_class.map(|c| c.to_string().into()).into_iter().collect(),
+        attributes: source_line.into_iter().map(source_line_attribute).collect(),
+        on_click: Some(cx.make_md_tag_handler(range, MarkdownTag::Rule, false)),
         ..Default::default()
     };
+    cx.el_hr(attributes)
+}
+
+#[cfg(feature="syntect")]
+/// `highlighted_lines(cx, lang, source)` renders each line of `source`
+/// as highlighted tokens.
+/// Returns None if the code can't be highlighted
+fn highlighted_lines<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    lang: Option<&str>,
+    source: &str,
+    ) -> Option<Vec<Vec<F::View>>> {
+
+    let lang = lang.filter(|_| !source.is_empty())?;
+    let code = highlight_code(cx.props().theme, source, lang)?;
+    let lines = code.lines.iter()
+        .map(|tokens| tokens.iter()
+            .map(|(classes, text)| cx.el_with_attributes(
+                    Span,
+                    cx.el_text(text.clone().into()),
+                    ElementAttributes {
+                        classes: classes.iter().map(|c| c.clone().into()).collect(),
+                        ..Default::default()
+                    }
+            ))
+            .collect()
+        )
+        .collect();
+    Some(lines)
+}
+
+#[cfg(not(feature="syntect"))]
+/// without the `syntect` feature, code is never highlighted
+fn highlighted_lines<'a, 'callback, F: Context<'a, 'callback>>(
+    _cx: F,
+    _lang: Option<&str>,
+    _source: &str,
+    ) -> Option<Vec<Vec<F::View>>> {
+    None
+}
+
+/// renders the `pre` element of a code block
+fn code_pre<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    fence: &CodeFence,
+    source: &str,
+    range: Range<usize>,
+    source_line: Option<usize>,
+    ) -> F::View {
+
+    let lang = fence.lang.as_deref();
+    let mut classes: Vec<Cow<str>> = vec!["code-block".into()];
+    if let Some(lang) = lang {
+        classes.push(format!("language-{lang}").into());
+    }
+
+    // the last line ends with a newline, which would show as an empty line
+    let source = source.strip_suffix('\n')
+        .map_or(source, |s| s.strip_suffix('\r').unwrap_or(s));
+
+    let lines = match highlighted_lines(cx, lang, source) {
+        Some(lines) => {
+            // the background of the theme
+            #[cfg(feature="syntect")]
+            classes.push(format!("{HIGHLIGHT_CLASS_PREFIX}code").into());
+            lines
+        },
+        None => source.split_inclusive('\n')
+            .map(|line| vec![cx.el_text(line.to_string().into())])
+            .collect()
+    };
+
+    let line_numbers = cx.props().code_line_numbers;
+    if line_numbers {
+        classes.push("line-numbers".into());
+    }
+
+    let content: Vec<_> = if line_numbers || !fence.highlighted_lines.is_empty() {
+        lines.into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let mut children = Vec::new();
+                if line_numbers {
+                    children.push(cx.el_with_attributes(
+                        Span,
+                        cx.el_text((i+1).to_string().into()),
+                        ElementAttributes {
+                            classes: vec!["line-number".into()],
+                            ..Default::default()
+                        }
+                    ));
+                }
+                children.extend(line);
 
-    match highlight_code(cx.props().theme, &source, &k) {
-        None => cx.el_with_attributes(
-            Code,
-            cx.el(Code, cx.el_text(source.into())),
-            code_attributes
-        ),
-        Some(x) => cx.el_span_with_inner_html(x, code_attributes)
+                let mut line_classes: Vec<Cow<str>> = vec!["code-line".into()];
+                if fence.highlighted_lines.contains(&(i+1)) {
+                    line_classes.push("highlighted-line".into());
+                }
+                cx.el_with_attributes(
+                    Span,
+                    cx.el_fragment(children),
+                    ElementAttributes {
+                        classes: line_classes,
+                        ..Default::default()
+                    }
+                )
+            })
+            .collect()
     }
+    else {
+        lines.into_iter().flatten().collect()
+    };
+
+    let attributes = ElementAttributes {
+        classes,
+        attributes: source_line.into_iter().map(source_line_attribute).collect(),
+        on_click: Some(cx.make_md_tag_handler(range.clone(), MarkdownTag::CodeBlock, true)),
+        ..block_attributes(cx, &range, MarkdownTag::CodeBlock)
+    };
+    cx.el_with_attributes(Pre, cx.el(Code, cx.el_fragment(content)), attributes)
 }
 
-#[cfg(features="maths")]
+#[cfg_attr(not(any(feature="maths", feature="mathml")), allow(unused_variables))]
 /// `render_maths(content)` returns a html node
-/// with the latex content `content` compiled inside
-fn render_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, content: &str, display_mode: &MathMode, range: Range<usize>) 
+/// with the latex content `content` compiled inside,
+/// using the math backend of the props
+fn render_maths<'a, 'callback, F: Context<'a, 'callback>>(cx: F, content: &str, display_mode: &MathMode, range: Range<usize>, source_line: Option<usize>)
     -> Result<F::View, HtmlError>{
-    let opts = katex::Opts::builder()
-        .display_mode(*display_mode == MathMode::Display)
-        .build()
-        .unwrap();
-
     let class_name = match display_mode {
         MathMode::Inline => "math-inline",
         MathMode::Display => "math-flow",
     };
 
-    let callback = cx.make_md_handler(range, true);
+    let callback = cx.make_md_tag_handler(range, MarkdownTag::Math, true);
 
     let attributes = ElementAttributes{
-            classes: vec![class_name.to_string()],
+            classes: vec![class_name.into()],
+            attributes: source_line.into_iter().map(source_line_attribute).collect(),
             on_click: Some(callback),
             ..Default::default()
     };
 
-    match katex::render_with_opts(content, opts){
-        Ok(x) => Ok(cx.el_span_with_inner_html(x, attributes)),
-        Err(_) => Err(HtmlError::Math),
+    // an expression that can't be compiled is shown as its source,
+    // without failing the rest of the document
+    let math_error = |attributes: ElementAttributes<F::Handler<F::MouseEvent>>| {
+        let mut classes = attributes.classes;
+        classes.push("math-error".into());
+        cx.el_with_attributes(Span, cx.el_text(content.to_string().into()), ElementAttributes {
+            classes,
+            style: cx.props().math_error_color.map(|color| format!("color: {color}").into()),
+            ..attributes
+        })
+    };
+
+    match cx.props().math_backend {
+        #[cfg(feature="maths")]
+        MathBackend::Katex => {
+            let mut builder = katex::Opts::builder();
+            builder.display_mode(*display_mode == MathMode::Display);
+            for (name, definition) in cx.props().katex_macros.into_iter().flatten() {
+                builder = builder.add_macro(name.clone(), definition.clone());
+            }
+            let opts = builder.build().unwrap();
+
+            // display math is a block on its own, inline math flows with the text
+            match (katex::render_with_opts(content, opts), display_mode) {
+                (Ok(x), MathMode::Display) => Ok(cx.el_with_inner_html(Div, x, attributes)),
+                (Ok(x), MathMode::Inline) => Ok(cx.el_span_with_inner_html(x, attributes)),
+                (Err(_), _) => Ok(math_error(attributes)),
+            }
+        },
+        #[cfg(feature="mathml")]
+        MathBackend::MathMl => {
+            let style = match display_mode {
+                MathMode::Inline => latex2mathml::DisplayStyle::Inline,
+                MathMode::Display => latex2mathml::DisplayStyle::Block,
+            };
+            let mathml = latex2mathml::latex_to_mathml(content, style).ok();
+            match mathml.as_deref().and_then(mathml_inner) {
+                Some(inner) => Ok(cx.el_with_inner_html(
+                        Math(*display_mode == MathMode::Display),
+                        inner.to_string(),
+                        attributes
+                )),
+                None => Ok(math_error(attributes)),
+            }
+        },
+        #[allow(unreachable_patterns)]
+        backend => Err(HtmlError::not_implemented(
+                format!("{backend:?} math backend (the corresponding feature is disabled)")
+        ))
     }
 }
 
+#[cfg(feature="mathml")]
+/// `mathml_inner(mathml)` strips the outer `<math>` tag of `mathml`,
+/// so that the backend can create the `math` element itself
+fn mathml_inner(mathml: &str) -> Option<&str> {
+    let start = mathml.find('>')? + 1;
+    let end = mathml.rfind("</math>")?;
+    mathml.get(start..end)
+}
+
+
+/// renders a table cell with a `text-align` style for `align`
+pub(crate) fn render_table_cell<'a, 'callback, F: Context<'a, 'callback>>(
+    cx: F,
+    inside: F::View,
+    header: bool,
+    align: Alignment,
+    attributes: ElementAttributes<F::Handler<F::MouseEvent>>,
+    ) -> F::View
+where 'callback: 'a
+{
+    let element = if header {TheadCell} else {Tcell};
+    let attributes = ElementAttributes {
+        style: attributes.style.or(align_string(align).map(Cow::from)),
+        ..attributes
+    };
+    cx.el_with_attributes(element, inside, attributes)
+}
 
 /// `align_string(align)` gives the css string
-/// that is used to align text according to `align`
-fn align_string(align: Alignment) -> &'static str {
+/// that is used to align text according to `align`,
+/// or None if the text is not aligned
+fn align_string(align: Alignment) -> Option<&'static str> {
     match align {
-        Alignment::Left => "text-align: left",
-        Alignment::Right => "text-align: right",
-        Alignment::Center => "text-align: center",
-        Alignment::None => "",
+        Alignment::Left => Some("text-align: left"),
+        Alignment::Right => Some("text-align: right"),
+        Alignment::Center => Some("text-align: center"),
+        Alignment::None => None,
+    }
+}
+
+
+
+
+/// a buffered list of markdown events, with their position in the source
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// `definition_list_lines(source, events)` splits the events of a paragraph
+/// into the lines of a definition list, like
+/// ```md
+/// Term
+/// Other term
+/// : definition
+///   continued on an indented line
+/// : other definition
+/// ```
+/// Each line is returned with `true` if it is a definition, `false` if it is a term.
+/// A line indented further than the first term continues the line before it.
+/// The paragraph must start with a term and end with a definition,
+/// otherwise the events are given back unchanged.
+/// The lists nested in the last definition are moved into the paragraph
+/// by `nest_definition_lists`.
+///
+/// The definition lists are found in the text of the paragraphs because
+/// `pulldown-cmark-wikilink` is a fork of pulldown-cmark 0.9,
+/// which has no definition list events: they were added in pulldown-cmark 0.12
+pub(crate) fn definition_list_lines<'a>(source: &str, events: Events<'a>) -> Result<Vec<(bool, Events<'a>)>, Events<'a>> {
+    // the column where the first event of a line starts in the source
+    let column = |line: &Events| line.first().map_or(0, |(_, range)| {
+        let start = range.start.min(source.len());
+        start - source[..start].rfind('\n').map_or(0, |i| i + 1)
+    });
+
+    let mut lines: Vec<Events> = vec![vec![]];
+    // the line breaks between the lines
+    let mut breaks = Vec::new();
+    let mut depth = 0;
+    for (event, range) in &events {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+        match event {
+            Event::SoftBreak | Event::HardBreak if depth == 0 => {
+                breaks.push((event.clone(), range.clone()));
+                lines.push(vec![]);
+            },
+            _ => lines.last_mut().unwrap().push((event.clone(), range.clone()))
+        }
+    }
+
+    let indent = column(&lines[0]);
+    let mut lines = lines.into_iter();
+    let mut merged: Vec<Events> = lines.next().into_iter().collect();
+    for (line, line_break) in lines.zip(breaks) {
+        match merged.last_mut() {
+            Some(previous) if !previous.is_empty() && column(&line) > indent => {
+                previous.push(line_break);
+                previous.extend(line);
+            },
+            _ => merged.push(line)
+        }
+    }
+    let lines = merged;
+
+    let is_definition = |line: &Events| matches!(line.first(), 
+        Some((Event::Text(s), _)) if s.starts_with(": "));
+
+    let valid = lines.len() >= 2
+        && !is_definition(&lines[0])
+        && is_definition(&lines[lines.len()-1])
+        && lines.iter().all(|l| !l.is_empty());
+    if !valid {
+        return Err(events)
+    }
+
+    Ok(lines.into_iter()
+        .map(|mut line| {
+            let definition = is_definition(&line);
+            if definition {
+                if let (Event::Text(s), range) = &mut line[0] {
+                    let text = s[1..].trim_start().to_string();
+                    range.start += s.len() - text.len();
+                    *s = text.into();
+                }
+            }
+            (definition, line)
+        })
+        .collect()
+    )
+}
+
+/// the number of spaces and tabs at the start of the line containing `pos` in `source`
+pub(crate) fn line_indent(source: &str, pos: usize) -> usize {
+    let pos = pos.min(source.len());
+    let line = &source[source[..pos].rfind('\n').map_or(0, |i| i + 1)..];
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+
+/// the end of the line starting at `start` in `source`
+fn line_end(source: &str, start: usize) -> usize {
+    source[start..].find('\n').map_or(source.len(), |i| start + i)
+}
+
+/// removes the events of the first line of the paragraph starting at `events[0]`,
+/// and the paragraph itself if nothing remains.
+/// Returns false, leaving the events unchanged,
+/// if the first line contains something else than text
+fn strip_first_line(source: &str, events: &mut Events) -> bool {
+    let start = match events.first() {
+        Some((Event::Start(Tag::Paragraph), range)) => range.start,
+        _ => return false
+    };
+    let line_end = line_end(source, start);
+
+    // the events of the first line, after the start of the paragraph
+    let first_line = 1 + events[1..].iter()
+        .take_while(|(e, range)| range.start <= line_end && !matches!(e, Event::End(_)))
+        .count();
+    let only_text = events[1..first_line].iter()
+        .all(|(e, _)| matches!(e, Event::Text(_) | Event::SoftBreak));
+    if !only_text {
+        return false
+    }
+    events.drain(1..first_line);
+
+    // the paragraph was made of the first line only
+    if matches!(events.get(1), Some((Event::End(TagEnd::Paragraph), _))) {
+        events.drain(0..2);
+    }
+    true
+}
+
+/// `split_alert(source, events)` detects a github alert in the events of a blockquote,
+/// like
+/// ```md
+/// > [!NOTE]
+/// > Some note
+/// ```
+/// It returns the kind and the title of the alert,
+/// and the events without the first line.
+/// Otherwise, the events are given back unchanged
+fn split_alert<'a>(source: &str, mut events: Events<'a>)
+    -> Result<(&'static str, String, Events<'a>), Events<'a>> {
+    let start = match events.first() {
+        Some((Event::Start(Tag::Paragraph), range)) => range.start,
+        _ => return Err(events)
+    };
+    let (kind, title) = match parse_alert_marker(&source[start..line_end(source, start)]) {
+        Some(alert) => alert,
+        None => return Err(events)
+    };
+    if !strip_first_line(source, &mut events) {
+        return Err(events)
+    }
+    Ok((kind, title, events))
+}
+
+/// the markers shown before the line breaks, with `show_break_markers`
+const SOFT_BREAK_MARKER: &str = "↵";
+const HARD_BREAK_MARKER: &str = "¶";
+
+/// the marker starting the attribution of a blockquote
+const ATTRIBUTION_MARKER: &str = "— ";
+
+/// `split_attribution(events)` detects the attribution of a blockquote,
+/// the last line of its last paragraph when it starts with `— `, like
+/// ```md
+/// > Some quote
+/// > — Some author
+/// ```
+/// It returns the events without the attribution,
+/// and the events of the attribution without its marker.
+/// Otherwise, the events are given back unchanged
+fn split_attribution(mut events: Events) -> Result<(Events, Events), Events> {
+    if !matches!(events.last(), Some((Event::End(TagEnd::Paragraph), _))) {
+        return Err(events)
+    }
+    // the start of the last paragraph
+    let mut depth = 0;
+    let start = events.iter().rposition(|(e, _)| {
+        match e {
+            Event::End(_) => depth += 1,
+            Event::Start(_) => depth -= 1,
+            _ => ()
+        }
+        depth == 0
+    });
+    let Some(start) = start else { return Err(events) };
+
+    // the last line of the paragraph starting with the marker
+    let paragraph = &events[start+1..events.len()-1];
+    let mut depth = 0;
+    let mut line = None;
+    for (i, (event, _)) in paragraph.iter().enumerate() {
+        let line_start = i == 0
+            || (depth == 0 && matches!(paragraph[i-1].0, Event::SoftBreak | Event::HardBreak));
+        let marked = matches!(event, Event::Text(s)
+            if s.starts_with(ATTRIBUTION_MARKER) && !s[ATTRIBUTION_MARKER.len()..].trim().is_empty());
+        if line_start && marked {
+            line = Some(start + 1 + i);
+        }
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => ()
+        }
+    }
+    let Some(line) = line else { return Err(events) };
+
+    let end = events.len() - 1;
+    let mut attribution: Events = events.drain(line..end).collect();
+    if let Some((Event::Text(s), range)) = attribution.first_mut() {
+        *s = s[ATTRIBUTION_MARKER.len()..].to_string().into();
+        range.start += ATTRIBUTION_MARKER.len();
+    }
+    match line == start + 1 {
+        // the paragraph was made of the attribution only
+        true => events.truncate(start),
+        // the line break before the attribution
+        false => { events.remove(line - 1); },
+    }
+    Ok((events, attribution))
+}
+
+#[derive(Clone)]
+/// the state of a document,
+/// shared by all the renderers of this document
+pub(crate) struct DocumentState<V> {
+    /// the footnotes found so far in the document
+    footnotes: Footnotes<V>,
+    /// the heading ids used so far in the document
+    slugs: Slugs,
+    /// the title of each abbreviation of the document
+    abbreviations: BTreeMap<String, String>,
+    /// the number of images rendered so far in the document
+    images: usize,
+    /// the changes made since they were last taken, when they are recorded
+    changes: Option<Vec<StateChange<V>>>,
+}
+
+#[derive(Clone)]
+/// a change made by an element to the state of its document,
+/// with the value the element read from the state.
+/// Replaying the changes of the elements gives the state after them
+pub(crate) enum StateChange<V> {
+    /// a reference to a footnote, with its number and its occurrence
+    FootnoteReference { label: String, number: usize, occurrence: usize },
+    /// the definition of a footnote
+    FootnoteDefinition { label: String, content: V },
+    /// the id of a heading, made unique from its slug
+    Slug { slug: String, id: String },
+    /// an image, with its number in the document
+    Image(usize),
+}
+
+impl<V> DocumentState<V> {
+    fn new() -> Self {
+        Self {
+            footnotes: Footnotes::new(),
+            slugs: Slugs::default(),
+            abbreviations: BTreeMap::new(),
+            images: 0,
+            changes: None,
+        }
+    }
+}
+
+impl<V: Clone> DocumentState<V> {
+    fn record(&mut self, change: impl FnOnce() -> StateChange<V>) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(change())
+        }
+    }
+
+    /// adds a reference to the footnote `label`,
+    /// returns the number of the footnote and the occurrence of this reference
+    fn reference_footnote(&mut self, label: &str) -> (usize, usize) {
+        let (number, occurrence) = self.footnotes.reference(label);
+        self.record(|| StateChange::FootnoteReference { label: label.to_string(), number, occurrence });
+        (number, occurrence)
+    }
+
+    /// defines the footnote `label`
+    fn define_footnote(&mut self, label: &str, content: V) {
+        self.record(|| StateChange::FootnoteDefinition { label: label.to_string(), content: content.clone() });
+        self.footnotes.define(label, content)
+    }
+
+    /// the unique id of a heading with the slug `slug`
+    fn unique_slug(&mut self, slug: String) -> String {
+        let id = self.slugs.unique(slug.clone());
+        self.record(|| StateChange::Slug { slug, id: id.clone() });
+        id
+    }
+
+    /// the number of the next image of the document, starting at 1
+    fn next_image(&mut self) -> usize {
+        self.images += 1;
+        let images = self.images;
+        self.record(|| StateChange::Image(images));
+        images
+    }
+
+    /// applies a change made by an element rendered earlier, without recording it.
+    /// Returns false if the element would read another value from the state now,
+    /// like another number for a footnote
+    fn replay(&mut self, change: &StateChange<V>) -> bool {
+        match change {
+            StateChange::FootnoteReference { label, number, occurrence } =>
+                self.footnotes.reference(label) == (*number, *occurrence),
+            StateChange::FootnoteDefinition { label, content } => {
+                self.footnotes.define(label, content.clone());
+                true
+            },
+            StateChange::Slug { slug, id } => self.slugs.unique(slug.clone()) == *id,
+            StateChange::Image(n) => {
+                self.images += 1;
+                self.images == *n
+            },
+        }
     }
 }
 
+#[derive(Clone)]
+/// the footnotes of a document.
+/// They are numbered in the order of their first reference,
+/// wherever they are defined
+struct Footnotes<V> {
+    /// the labels of the referenced footnotes, in the order they are numbered
+    labels: Vec<String>,
+    /// the number of references to each footnote
+    references: BTreeMap<String, usize>,
+    /// the rendered content of each footnote definition, in document order
+    definitions: Vec<(String, V)>,
+}
+
+impl<V> Footnotes<V> {
+    fn new() -> Self {
+        Self {
+            labels: Vec::new(),
+            references: BTreeMap::new(),
+            definitions: Vec::new(),
+        }
+    }
+
+    /// returns the number of the footnote `label`,
+    /// numbering it if it is new
+    fn number(&mut self, label: &str) -> usize {
+        match self.labels.iter().position(|l| l == label) {
+            Some(i) => i + 1,
+            None => {
+                self.labels.push(label.to_string());
+                self.labels.len()
+            }
+        }
+    }
+
+    /// adds a reference to the footnote `label`,
+    /// returns the number of the footnote and the occurrence of this reference
+    fn reference(&mut self, label: &str) -> (usize, usize) {
+        let number = self.number(label);
+        let occurrence = self.references.entry(label.to_string()).or_insert(0);
+        *occurrence += 1;
+        (number, *occurrence)
+    }
+
+    /// defines the footnote `label`, replacing its previous definition
+    fn define(&mut self, label: &str, content: V) {
+        self.definitions.retain(|(l, _)| l != label);
+        self.definitions.push((label.to_string(), content));
+    }
 
+    /// takes the definitions, the referenced ones first in the order
+    /// of their number, then the others in document order
+    fn take_definitions(&mut self) -> Vec<(String, V)> {
+        let mut definitions = std::mem::take(&mut self.definitions);
+        let position = |label: &str| self.labels.iter().position(|l| l == label);
+        // the sort is stable, so the unreferenced definitions keep their order
+        definitions.sort_by_key(|(label, _)| position(label).unwrap_or(usize::MAX));
+        definitions
+    }
+}
 
 
 /// Manage the creation of a [`F::View`]
@@ -177,6 +1000,8 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     __marker : PhantomData<&'callback ()>,
     /// the markdown context
     cx: F,
+    /// the markdown source of the document
+    source: &'a str,
     /// the stream of markdown [`Event`]s
     stream: &'c mut I,
     /// the alignment settings inside the current table
@@ -186,33 +1011,314 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     cell_index: usize,
     /// the root tag that this renderer is rendering
     end_tag: Option<TagEnd>,
-    /// the current component we are inside of.
-    /// custom components doesn't allow nesting.
-    current_component: Option<String>
+    /// the innermost element containing the text of this renderer
+    parent: MarkdownTag,
+    /// true inside of a link or an image
+    in_link: bool,
+    /// the number of block containers, like lists and blockquotes,
+    /// around the events of this renderer
+    depth: usize,
+    /// an event taken from the stream, to be rendered next
+    pending: Option<(Event<'a>, Range<usize>)>,
+    /// the state of the whole document
+    document: Rc<RefCell<DocumentState<F::View>>>,
+    /// the lines of the source, to set the line of the top-level elements.
+    /// It is only set for the top-level renderers
+    lines: Option<Rc<LineIndex>>,
 }
 
-/// returns true if `raw_html`:
-/// - starts with '<'
-/// - ends with '>'
-/// - does not have any '<' or '>' in between
-fn can_be_custom_component(raw_html: &str) -> bool {
-    let chars: Vec<_> = raw_html.trim().chars().collect();
-    let len = chars.len();
-    if len==0 {return false};
-    let (fst, middle, last) = (chars[0], &chars[1..len-1], chars[len-1]);
-    fst == '<' && last == '>' && middle.into_iter().all(|c| c!=&'<' && c!=&'>')
+/// returns true if `tag` is a block that can contain other blocks
+fn is_block_container(tag: &Tag) -> bool {
+    matches!(tag, Tag::BlockQuote | Tag::List(_) | Tag::Item | Tag::FootnoteDefinition(_))
 }
 
-impl<'a, 'callback, 'c, I, F> Iterator for Renderer<'a, 'callback, 'c, I, F> 
+/// the link of a heading to itself, with `heading_anchor_links`.
+/// Clicking it copies the link, resolved against the `base_url`
+fn heading_anchor<'a, 'callback, F: Context<'a, 'callback>>(cx: F, slug: &str, text: &str) -> F::View {
+    let href = format!("#{slug}");
+    let link = match cx.props().base_url {
+        Some(base) => resolve_url(base, &href),
+        None => href.clone()
+    };
+    let symbol = cx.el_with_attributes(
+        Span,
+        cx.el_text("#".into()),
+        ElementAttributes {
+            attributes: vec![("aria-hidden".to_string(), "true".to_string())],
+            ..Default::default()
+        }
+    );
+    cx.el_a_with_attributes(symbol, href, ElementAttributes {
+        classes: vec!["heading-anchor".into()],
+        attributes: vec![("aria-label".to_string(), format!("Link to {text}"))],
+        on_click: cx.make_copy_handler(link),
+        ..Default::default()
+    })
+}
+
+/// how a table cell with the content `events` is merged with its neighbours,
+/// with `table_spans`
+fn cell_merge(events: &Events) -> CellMerge {
+    let text_only = events.iter().all(|(e, _)| matches!(e, Event::Text(_)));
+    match events_text(events.iter().map(|(e, _)| e)).trim() {
+        "" if text_only => CellMerge::Left,
+        "^" if text_only => CellMerge::Up,
+        _ => CellMerge::None
+    }
+}
+
+/// the title of the image making up the content of a paragraph,
+/// if it is the only content and its title is not empty
+fn standalone_image_title(events: &Events) -> Option<String> {
+    let title = match events.first() {
+        Some((Event::Start(Tag::Image{title, ..}), _)) if !title.is_empty() => title.to_string(),
+        _ => return None
+    };
+    let images = events.iter()
+        .filter(|(e, _)| matches!(e, Event::Start(Tag::Image{..})))
+        .count();
+    let ends_with_image = matches!(events.last(), Some((Event::End(TagEnd::Image), _)));
+    (images == 1 && ends_with_image).then_some(title)
+}
+
+/// the state of the checkbox of a list item with the `events`,
+/// if it is a task. The marker comes first, or inside its first paragraph
+fn task_state(events: &Events) -> Option<bool> {
+    events.iter()
+        .take(2)
+        .find_map(|(e, _)| match e {
+            Event::TaskListMarker(checked) => Some(*checked),
+            _ => None
+        })
+}
+
+/// the position of the characters of a thematic break, like `---`,
+/// from the `range` of its event, which can include
+/// the indentation and the end of the line
+fn rule_range(source: &str, range: Range<usize>) -> Range<usize> {
+    let Some(rule) = source.get(range.clone()) else { return range };
+    let start = range.start + (rule.len() - rule.trim_start().len());
+    let end = range.start + rule.trim_end().len();
+    match start < end {
+        true => start..end,
+        false => range
+    }
+}
+
+/// the position of the marker of a task list item, like `[ ]` or `[x]`,
+/// from the `range` of its event, which can start before the marker,
+/// at the start of its item.
+/// Only the list markers, like `-` or `1.`, can come before it
+fn task_marker_range(source: &str, range: Range<usize>) -> Range<usize> {
+    let line = source.get(range.start..line_end(source, range.start)).unwrap_or_default();
+    let is_list_marker = |c: char| c.is_whitespace() || c.is_ascii_digit() || "-*+.)>".contains(c);
+    let prefix = line.find(|c| !is_list_marker(c)).unwrap_or(line.len());
+    match line.get(prefix..prefix+3) {
+        Some("[ ]" | "[x]" | "[X]") => range.start + prefix..range.start + prefix + 3,
+        _ => range
+    }
+}
+
+/// the url of the link making up the content of a paragraph,
+/// if it is the only content
+fn standalone_link_url(events: &Events) -> Option<String> {
+    let url = match events.first() {
+        Some((Event::Start(Tag::Link{dest_url, ..}), _)) => dest_url.to_string(),
+        _ => return None
+    };
+    let links = events.iter()
+        .filter(|(e, _)| matches!(e, Event::Start(Tag::Link{..})))
+        .count();
+    let ends_with_link = matches!(events.last(), Some((Event::End(TagEnd::Link), _)));
+    (links == 1 && ends_with_link).then_some(url)
+}
+
+/// the kind of element described by `tag`,
+/// if its text should be described by it
+fn markdown_tag(tag: &Tag) -> Option<MarkdownTag> {
+    Some(match tag {
+        Tag::Paragraph => MarkdownTag::Paragraph,
+        Tag::Heading{level, ..} => MarkdownTag::Heading(*level as u8),
+        Tag::BlockQuote => MarkdownTag::BlockQuote,
+        Tag::Item => MarkdownTag::ListItem,
+        Tag::TableCell => MarkdownTag::TableCell,
+        Tag::Emphasis => MarkdownTag::Emphasis,
+        Tag::Strong => MarkdownTag::Strong,
+        Tag::Strikethrough => MarkdownTag::Strikethrough,
+        Tag::Link{dest_url, ..} => MarkdownTag::Link(dest_url.to_string()),
+        Tag::Image{..} => MarkdownTag::Image,
+        Tag::FootnoteDefinition(_) => MarkdownTag::FootnoteDefinition,
+        _ => return None
+    })
+}
+
+/// the key of the footnote section, for [`Context::el_keyed_fragment`]
+pub(crate) const FOOTNOTES_KEY: &str = "footnotes";
+
+/// the key of the top-level element at `range`,
+/// for [`Context::el_keyed_fragment`]
+pub(crate) fn element_key(range: &Range<usize>) -> String {
+    format!("{}-{}", range.start, range.end)
+}
+
+/// An iterator over the views of a stream of markdown events,
+/// rendering one top-level element at a time.
+/// The footnote section comes last, if there are footnotes
+pub struct EventRenderer<'a, 'callback, I, F>
 where I: Iterator<Item=(Event<'a>, Range<usize>)>,
       'callback: 'a,
       F: Context<'a, 'callback>,
 {
-    type Item = F::View;
+    __marker : PhantomData<&'callback ()>,
+    /// the markdown context
+    cx: F,
+    /// the markdown source of the document
+    source: &'a str,
+    /// the stream of markdown [`Event`]s
+    events: Peekable<I>,
+    /// the position of the last rendered element in the source
+    range: Option<Range<usize>>,
+    /// the state of the whole document
+    document: Rc<RefCell<DocumentState<F::View>>>,
+    /// the lines of the source, with [`MarkdownProps::source_line_attributes`]
+    lines: Option<Rc<LineIndex>>,
+    /// true once the footnote section was rendered
+    finished: bool,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        use Event::*;
-        let (item, range): (Event<'a>, Range<usize>) = self.stream.next()? ;
+impl<'a, 'callback, I, F> EventRenderer<'a, 'callback, I, F>
+where I: Iterator<Item=(Event<'a>, Range<usize>)>,
+      'callback: 'a,
+      F: Context<'a, 'callback>,
+{
+    pub fn new(cx: F, source: &'a str, events: I) -> Self {
+        Self {
+            __marker: PhantomData,
+            cx,
+            source,
+            events: events.peekable(),
+            range: None,
+            document: Rc::new(RefCell::new(DocumentState::new())),
+            lines: cx.props().source_line_attributes.then(|| Rc::new(LineIndex::new(source))),
+            finished: false,
+        }
+    }
+
+    /// the key of the last rendered element, see [`element_key`]
+    pub(crate) fn key(&self) -> String {
+        match (&self.range, self.finished) {
+            (Some(range), false) => element_key(range),
+            _ => FOOTNOTES_KEY.to_string(),
+        }
+    }
+
+    /// a copy of the state of the document,
+    /// after the elements rendered so far
+    pub(crate) fn state(&self) -> DocumentState<F::View> {
+        self.document.borrow().clone()
+    }
+
+    /// continues the rendering of a document in the state `state`
+    pub(crate) fn with_state(self, state: DocumentState<F::View>) -> Self {
+        *self.document.borrow_mut() = state;
+        self
+    }
+
+    /// puts the document back in `state`, taken with [`EventRenderer::state`]
+    pub(crate) fn restore_state(&self, state: DocumentState<F::View>) {
+        *self.document.borrow_mut() = state;
+    }
+
+    /// records the changes made to the state of the document by the elements,
+    /// see [`EventRenderer::take_changes`]
+    pub(crate) fn recording_changes(self) -> Self {
+        self.document.borrow_mut().changes = Some(Vec::new());
+        self
+    }
+
+    /// the changes made to the state of the document since the last call,
+    /// by the last rendered elements
+    pub(crate) fn take_changes(&self) -> Vec<StateChange<F::View>> {
+        self.document.borrow_mut().changes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// applies the `changes` made by elements rendered earlier, as if they were rendered again.
+    /// Returns false if one of the elements would read another value from the state now:
+    /// the state is then left partially changed
+    pub(crate) fn replay(&self, changes: &[StateChange<F::View>]) -> bool {
+        let mut document = self.document.borrow_mut();
+        changes.iter().all(|change| document.replay(change))
+    }
+
+    /// renders the footnote section without rendering the remaining events,
+    /// which ends the document
+    pub(crate) fn footnote_section(&mut self) -> Option<F::View> {
+        self.finished = true;
+        let mut renderer = Renderer::new(self.cx, self.source, &mut self.events);
+        renderer.document = self.document.clone();
+        renderer.footnote_section()
+    }
+
+    /// wraps the whole-word occurrences of the keys of `abbreviations`
+    /// outside of code and links in an `abbr`, with their value as title
+    pub(crate) fn with_abbreviations(self, abbreviations: BTreeMap<String, String>) -> Self {
+        self.document.borrow_mut().abbreviations = abbreviations;
+        self
+    }
+}
+
+impl<'a, 'callback, I, F> Iterator for EventRenderer<'a, 'callback, I, F>
+where I: Iterator<Item=(Event<'a>, Range<usize>)>,
+      'callback: 'a,
+      F: Context<'a, 'callback>,
+{
+    type Item = F::View;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None
+        }
+        self.range = self.events.peek().map(|(_, range)| range.clone());
+        let mut renderer = Renderer::new(self.cx, self.source, &mut self.events);
+        renderer.document = self.document.clone();
+        renderer.lines = self.lines.clone();
+        match renderer.next() {
+            Some(view) => Some(view),
+            None => {
+                self.finished = true;
+                renderer.footnote_section()
+            }
+        }
+    }
+}
+
+/// returns true if `raw_html`:
+/// - starts with '<'
+/// - ends with '>'
+/// - does not have any '<' or '>' in between
+/// - is not a comment or a declaration, starting with `<!`
+pub(crate) fn can_be_custom_component(raw_html: &str) -> bool {
+    let chars: Vec<_> = raw_html.trim().chars().collect();
+    let len = chars.len();
+    if len==0 {return false};
+    let (fst, middle, last) = (chars[0], &chars[1..len-1], chars[len-1]);
+    fst == '<' && last == '>' && middle.first() != Some(&'!')
+        && middle.into_iter().all(|c| c!=&'<' && c!=&'>')
+}
+
+impl<'a, 'callback, 'c, I, F> Iterator for Renderer<'a, 'callback, 'c, I, F> 
+where I: Iterator<Item=(Event<'a>, Range<usize>)>,
+      'callback: 'a,
+      F: Context<'a, 'callback>,
+{
+    type Item = F::View;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use Event::*;
+        let (item, range): (Event<'a>, Range<usize>) = match self.pending.take() {
+            Some(event) => event,
+            None => self.stream.next()?
+        };
         let range = range.clone();
 
         let cx = self.cx;
@@ -228,41 +1334,44 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
                     None => panic!("didn't expect a closing tag")
                 }
             },
-            Text(s) => Ok(cx.render_text(s, range)),
+            Text(s) => Ok(self.text(s, range)),
             Code(s) => Ok(cx.render_code(s, range)),
+            InlineHtml(s) if self.is_component_start(&s) => self.html(&s, range),
             InlineHtml(s) => {
                 let attributes = ElementAttributes {
-                    on_click: Some(self.cx.make_md_handler(range, false)),
+                    on_click: Some(self.cx.make_md_tag_handler(range, MarkdownTag::Html, false)),
                     ..ElementAttributes::default()
                 };
-                Ok(self.cx.el_span_with_inner_html(s.to_string(), attributes))
+                Ok(self.inner_html(&s, attributes))
             },
             Html(_) => panic!("html outside html block"), 
-            FootnoteReference(_) => Err(HtmlError::not_implemented("footnotes refs")),
-            SoftBreak => Ok(self.next()?),
-            HardBreak => Ok(self.cx.el_br()),
-            Rule => Ok(cx.render_rule(range)),
-            TaskListMarker(m) => Ok(cx.render_tasklist_marker(m, range)),
-            #[cfg(features="maths")]
-            Math(disp, content) => render_maths(self.cx, &content, &disp, range),
-            #[cfg(not(features="maths"))]
-            Math(_, _) => Err(HtmlError::Math)
+            FootnoteReference(label) => {
+                let (number, occurrence) = self.document.borrow_mut().reference_footnote(&label);
+                Ok(cx.render_footnote_reference(&label, number, occurrence, range))
+            },
+            SoftBreak => {
+                let props = cx.props();
+                let soft_break = if props.hard_line_breaks {
+                    crate::SoftBreak::LineBreak
+                } else {
+                    props.soft_break
+                };
+                let view = match soft_break {
+                    crate::SoftBreak::Space => cx.el_text(" ".into()),
+                    crate::SoftBreak::LineBreak => cx.el_br(),
+                    crate::SoftBreak::None if props.show_break_markers => cx.el_empty(),
+                    crate::SoftBreak::None => return self.next(),
+                };
+                Ok(self.with_break_marker(view, false))
+            },
+            HardBreak => Ok(self.with_break_marker(cx.el_br(), true)),
+            Rule => Ok(cx.render_rule_on_line(rule_range(self.source, range.clone()), self.source_line(&range))),
+            TaskListMarker(m) => Ok(cx.render_tasklist_marker(m, task_marker_range(self.source, range))),
+            Math(disp, content) => render_maths(self.cx, &content, &disp, range.clone(), self.source_line(&range)),
         };
 
         Some(
-            rendered.unwrap_or_else(|e| self.cx.el_with_attributes(
-                    Span,
-                    self.cx.el_fragment(vec![
-                        self.cx.el_text(e.to_string().into()),
-                        self.cx.el_br(),
-                    ]),
-                    ElementAttributes {
-                        classes: vec!["markdown-error".to_string()],
-                        on_click: None,
-                        ..Default::default()
-                    }
-                )
-            )
+            rendered.unwrap_or_else(|e| render_error(self.cx, e.to_string()))
         )
     }
 }
@@ -274,101 +1383,308 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
 {
     /// creates a new renderer from a stream of events.
     /// It returns an iterator of [`F::View`]
-    pub fn new(cx: F, events: &'c mut I)-> Self 
+    pub fn new(cx: F, source: &'a str, events: &'c mut I)-> Self 
     {
 
         Self {
             __marker: PhantomData,
             cx,
+            source,
             stream: events,
             column_alignment: None,
             cell_index: 0,
             end_tag: None,
-            current_component: None,
+            parent: MarkdownTag::Text,
+            in_link: false,
+            depth: 0,
+            pending: None,
+            document: Rc::new(RefCell::new(DocumentState::new())),
+            lines: None,
+        }
+    }
+
+    /// the line of the element at `range`, if it is a top-level element
+    /// and [`MarkdownProps::source_line_attributes`] is set
+    fn source_line(&self, range: &Range<usize>) -> Option<usize> {
+        self.lines.as_ref().map(|lines| lines.line(range.start))
+    }
+
+    /// the attributes of the element at `range`
+    /// with the hover handlers of the context, and its source line
+    fn block_attributes(&self, range: &Range<usize>, tag: MarkdownTag) -> ElementAttributes<F::Handler<F::MouseEvent>> {
+        ElementAttributes {
+            attributes: self.source_line(range).into_iter().map(source_line_attribute).collect(),
+            ..block_attributes(self.cx, range, tag)
+        }
+    }
+
+    /// precedes the rendered line break `view` with a visible marker,
+    /// with [`MarkdownProps::show_break_markers`]
+    fn with_break_marker(&self, view: F::View, hard: bool) -> F::View {
+        let cx = self.cx;
+        if !cx.props().show_break_markers {
+            return view
+        }
+        let (marker, class) = match hard {
+            true => (HARD_BREAK_MARKER, "hard-break-marker"),
+            false => (SOFT_BREAK_MARKER, "soft-break-marker"),
+        };
+        let marker = cx.el_with_attributes(
+            Span,
+            cx.el_text(marker.into()),
+            ElementAttributes {
+                classes: vec!["break-marker".into(), class.into()],
+                attributes: vec![("aria-hidden".to_string(), "true".to_string())],
+                ..Default::default()
+            }
+        );
+        cx.el_fragment(vec![marker, view])
+    }
+
+    /// the inline markers enabled by the props, like `^` for superscript
+    fn inline_markers(&self) -> Vec<(&'a str, HtmlElement)> {
+        let mut markers = Vec::new();
+        if self.cx.props().mark {
+            markers.push(("==", Mark));
+        }
+        if self.cx.props().insert {
+            markers.push(("++", Ins));
+        }
+        if let Some(marker) = self.cx.props().kbd_marker.filter(|m| !m.is_empty()) {
+            markers.push((marker, Kbd));
+        }
+        if self.cx.props().superscript_subscript {
+            markers.push(("^", Sup));
+            markers.push(("~", Sub));
+        }
+        markers
+    }
+
+    /// renders a text, splitting the spans surrounded by inline markers
+    fn text(&self, s: CowStr<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let markers = self.inline_markers();
+        // if the source differs from the text, it contains escapes or entities,
+        // and the markers are not taken into account
+        if markers.is_empty() || self.source.get(range.clone()) != Some(&*s) {
+            return self.abbreviated_text(s, range)
+        }
+
+        let views = split_markers(&s, &markers).into_iter()
+            .map(|(r, part)| {
+                let r = range.start + r.start..range.start + r.end;
+                match part {
+                    MarkedText::Plain(t) => self.abbreviated_text(t.to_string().into(), r),
+                    MarkedText::Marked(e, t) => cx.el(e, self.abbreviated_text(t.to_string().into(), r)),
+                }
+            })
+            .collect();
+        cx.el_fragment(views)
+    }
+
+    /// renders a text, wrapping the abbreviations of the document
+    /// in an `abbr` element
+    fn abbreviated_text(&self, s: CowStr<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let document = self.document.borrow();
+        let parts = match self.in_link {
+            true => Vec::new(),
+            false => split_abbreviations(&s, &document.abbreviations),
+        };
+        if parts.iter().all(|(_, part)| matches!(part, MarkedText::Plain(_))) {
+            return cx.render_text_in(s, self.parent.clone(), range)
         }
+
+        let views = parts.into_iter()
+            .map(|(r, part)| {
+                let r = locate(self.source, &s, range.clone(), r);
+                match part {
+                    MarkedText::Plain(t) => cx.render_text_in(t.to_string().into(), self.parent.clone(), r),
+                    MarkedText::Marked(title, t) => cx.el_with_attributes(
+                        Abbr,
+                        cx.render_text_in(t.to_string().into(), self.parent.clone(), r),
+                        ElementAttributes {
+                            attributes: vec![("title".to_string(), title.to_string())],
+                            ..Default::default()
+                        }
+                    ),
+                }
+            })
+            .collect();
+        cx.el_fragment(views)
+    }
+
+    /// renders the footnote definitions collected so far,
+    /// in a section meant to be placed at the end of the document.
+    /// Returns None if there are no footnote definitions
+    pub fn footnote_section(&self) -> Option<F::View> {
+        let cx = self.cx;
+        let mut document = self.document.borrow_mut();
+        let footnotes = &mut document.footnotes;
+        let definitions = footnotes.take_definitions();
+        if definitions.is_empty() {
+            return None
+        }
+
+        // the referenced footnotes come first, so each one is at its number
+        let items: Vec<_> = definitions.into_iter()
+            .enumerate()
+            .map(|(i, (label, content))| {
+                let references = footnotes.references.get(&label).copied().unwrap_or(0);
+                cx.render_footnote_definition(&label, i + 1, content, references)
+            })
+            .collect();
+
+        let mut children = Vec::new();
+        let heading = cx.props().footnotes_heading.unwrap_or("Footnotes");
+        let label = if heading.is_empty() {"Footnotes"} else {heading};
+        if !heading.is_empty() {
+            children.push(cx.el(Heading(2), cx.el_text(heading.into())));
+        }
+        children.push(cx.el(Ol(1), cx.el_fragment(items)));
+
+        Some(cx.el_with_attributes(
+                Div,
+                cx.el_fragment(children),
+                ElementAttributes {
+                    classes: vec!["footnotes".into()],
+                    attributes: vec![("aria-label".to_string(), label.to_string())],
+                    ..Default::default()
+                }
+        ))
     }
 
     /// try to render `raw_html` as a custom component.
     /// - if it looks like `<Component/>` and Component is registered,
     ///     it will render the corresponding component
     /// - it it looks like `<Component>`, and Component is registered, 
-    /// it will extract markdown until it finds `<Component/>`
+    /// it will render the markdown until it finds `</Component>` as its children
     /// In any other cases, it will render the strinng as raw html.
-    fn html(&mut self, raw_html: &str, _range: Range<usize>) 
+    fn html(&mut self, raw_html: &str, range: Range<usize>) 
         -> Result<F::View, HtmlError> {
-
-            // TODO: refactor
-
-            match &self.current_component {
-                Some(current_name) => {
-                    if self.end_tag.is_some() {
-                        return Err(HtmlError::component(raw_html, 
-                                                        "please make sure there is a newline before the end of your component"))
-                    }
-                    match raw_html.parse() {
-                        Ok(CustomHtmlTag::End(name)) if &name==current_name => {
-                            Ok(self.next().unwrap_or(self.cx.el_empty()))
-                        },
-                        Ok(_) => Err(HtmlError::component(current_name, 
-                                                          "expected end of component")),
-                        Err(e) => Err(HtmlError::syntax(e))
-                    }
-                }
-                None => {
-                    if can_be_custom_component(raw_html) {
-                        match raw_html.parse() {
-                            Ok(CustomHtmlTag::Inline(s)) => self.custom_component_inline(s),
-                            Ok(CustomHtmlTag::End(name)) => Err(
-                                HtmlError::component(name, "expected start, not end")),
-                            Ok(CustomHtmlTag::Start(s)) => self.custom_component(s),
-                            Err(e) => Err(HtmlError::syntax(e))
-                        }
-                    }
-                    else {
-                        Ok(self.cx.el_span_with_inner_html(raw_html.to_string(), 
-                                                           Default::default()))
-                    }
+            if can_be_custom_component(raw_html) {
+                match raw_html.trim().parse() {
+                    Ok(CustomHtmlTag::Inline(s)) => self.custom_component_inline(s),
+                    Ok(CustomHtmlTag::End(name)) => Err(
+                        HtmlError::component(name, "expected start, not end")),
+                    Ok(CustomHtmlTag::Start(s)) => self.custom_component(s),
+                    Err(e) => Err(HtmlError::syntax(e))
                 }
             }
+            else {
+                let attributes = ElementAttributes {
+                    attributes: self.source_line(&range).into_iter().map(source_line_attribute).collect(),
+                    ..Default::default()
+                };
+                Ok(self.inner_html(raw_html, attributes))
+            }
         }
 
-    /// renders a custom component with childrens
+    /// returns true if `raw_html` is the start of a registered component,
+    /// like `<Component>` or `<Component/>`
+    fn is_component_start(&self, raw_html: &str) -> bool {
+        if !can_be_custom_component(raw_html) {
+            return false
+        }
+        match raw_html.trim().parse() {
+            Ok(CustomHtmlTag::Inline(c) | CustomHtmlTag::Start(c)) =>
+                self.cx.has_custom_component(&c.name),
+            _ => false
+        }
+    }
+
+    /// returns the html to render as inner html,
+    /// sanitized if `MarkdownProps::sanitize_html` is set
+    fn raw_html(&self, html: &str) -> String {
+        if self.cx.props().sanitize_html {
+            sanitize_html(html)
+        }
+        else if !self.cx.props().preserve_comments {
+            strip_html_comments(html)
+        }
+        else {
+            html.to_string()
+        }
+    }
+
+    /// renders the raw html `html` in a span, or nothing if it
+    /// is empty without its comments
+    fn inner_html(&self, html: &str, attributes: ElementAttributes<F::Handler<F::MouseEvent>>) -> F::View {
+        let html = self.raw_html(html);
+        match html.trim().is_empty() {
+            true => self.cx.el_fragment(vec![]),
+            false => self.cx.el_span_with_inner_html(html, attributes)
+        }
+    }
+
+    /// renders a custom component with childrens.
+    /// Its content, until the matching `</Component>`,
+    /// is rendered like the rest of the markdown
     fn custom_component(&mut self, description: ComponentCall) -> Result<F::View, HtmlError> {
         let name: &str = &description.name;
         if !self.cx.has_custom_component(name){
-            return Err(HtmlError::component(name, "not a valid component"))
+            return Ok(self.cx.render_component_error(ComponentError {
+                name: name.to_string(),
+                message: "not a valid component".to_string(),
+            }))
         }
 
-        let sub_renderer = Renderer {
-            __marker: PhantomData,
-            cx: self.cx,
-            stream: self.stream,
-            column_alignment: self.column_alignment.clone(),
-            cell_index: 0,
-            end_tag: self.end_tag,
-            current_component: Some(description.name.clone())
-        };
-        let children = self.cx.el_fragment(sub_renderer.collect());
+        let events = self.component_events(name);
+        let children = self.render_events(events, self.parent.clone());
 
         let props = MdComponentProps {
             attributes: description.attributes,
             children
         };
 
-        match self.cx.render_custom_component(name, props) {
-            Ok(x) => Ok(x),
-            Err(e) => Err(HtmlError::CustomComponent {
-                name: name.to_string(),
-                msg: e.0
-            })
+        Ok(self.call_component(name, props))
+    }
+
+    /// collects the events until the end tag of the component `name`,
+    /// without rendering them. Components with the same name can be nested.
+    /// Without an end tag, the component ends with its parent element
+    fn component_events(&mut self, name: &str) -> Events<'a> {
+        let mut events = Vec::new();
+        // the components with the same name opened inside of this one
+        let mut nested = 0;
+        // the depth of the markdown elements inside of the component
+        let mut depth = 0;
+        while let Some((event, range)) = self.pending.take().or_else(|| self.stream.next()) {
+            match &event {
+                Event::Html(s) | Event::InlineHtml(s) => match s.trim().parse() {
+                    Ok(CustomHtmlTag::Start(c)) if c.name == name => nested += 1,
+                    Ok(CustomHtmlTag::End(n)) if n == name && nested == 0 => {
+                        // the html block of the end tag is not part of the content
+                        if let Some((Event::Start(Tag::HtmlBlock), _)) = events.last() {
+                            events.pop();
+                            self.assert_closing_tag(TagEnd::HtmlBlock);
+                        }
+                        break
+                    },
+                    Ok(CustomHtmlTag::End(n)) if n == name => nested -= 1,
+                    _ => ()
+                },
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => {
+                    self.pending = Some((event, range));
+                    break
+                },
+                Event::End(_) => depth -= 1,
+                _ => ()
+            }
+            events.push((event, range));
         }
+        events
     }
 
     /// renders a custom component without childrens
     fn custom_component_inline(&mut self, description: ComponentCall) -> Result<F::View, HtmlError> {
         let name: &str = &description.name;
         if !self.cx.has_custom_component(name){
-            return Err(HtmlError::component(name, "not a valid component"))
+            return Ok(self.cx.render_component_error(ComponentError {
+                name: name.to_string(),
+                message: "not a valid component".to_string(),
+            }))
         }
 
         let props = MdComponentProps {
@@ -376,13 +1692,29 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
             children: self.cx.el_empty()
         };
 
-        match self.cx.render_custom_component(name, props) {
-            Ok(x) => Ok(x),
-            Err(e) => Err(HtmlError::CustomComponent {
-                name: name.to_string(),
-                msg: e.0
-            })
-        }
+        Ok(self.call_component(name, props))
+    }
+
+    /// renders the component `name`, or its error with
+    /// [`Context::render_component_error`] if it fails or panics
+    fn call_component(&self, name: &str, props: MdComponentProps<F::View>) -> F::View {
+        let cx = self.cx;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| cx.render_custom_component(name, props)));
+        let message = match result {
+            Ok(Ok(view)) => return view,
+            Ok(Err(e)) => e.0,
+            Err(payload) => match payload.downcast::<String>() {
+                Ok(message) => format!("panicked: {message}"),
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => format!("panicked: {message}"),
+                    Err(_) => "panicked".to_string(),
+                }
+            }
+        };
+        cx.render_component_error(ComponentError {
+            name: name.to_string(),
+            message,
+        })
     }
 
     /// renders events in a new renderer,
@@ -391,25 +1723,467 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
         let sub_renderer = Renderer {
             __marker: PhantomData,
             cx: self.cx,
+            source: self.source,
             stream: self.stream,
             column_alignment: self.column_alignment.clone(),
             cell_index: 0,
             end_tag: Some(as_closing_tag(&tag)),
-            current_component: self.current_component.clone(),
+            parent: markdown_tag(&tag).unwrap_or_else(|| self.parent.clone()),
+            in_link: self.in_link || matches!(tag, Tag::Link{..} | Tag::Image{..}),
+            depth: self.depth + is_block_container(&tag) as usize,
+            pending: None,
+            document: self.document.clone(),
+            lines: None,
         };
         self.cx.el_fragment(sub_renderer.collect())
     }
 
-    /// extract the text from the next text event
+    /// renders a list item, with the classes of a task if it has a checkbox.
+    /// Only the events that can hold the checkbox are read ahead
+    fn list_item(&mut self, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let mut head = Vec::new();
+        for event in self.stream.by_ref() {
+            let paragraph = matches!(event.0, Event::Start(Tag::Paragraph));
+            head.push(event);
+            if !paragraph || head.len() == 2 {
+                break
+            }
+        }
+        let mut attributes = block_attributes(cx, &range, MarkdownTag::ListItem);
+        if let Some(checked) = task_state(&head) {
+            let state = if checked {"checked"} else {"unchecked"};
+            attributes.classes = vec!["task-list-item".into(), state.into()];
+        }
+
+        // boxed, so that the items nested in this one use the same type
+        let mut stream: Box<dyn Iterator<Item=(Event<'a>, Range<usize>)> + '_> =
+            Box::new(head.into_iter().chain(&mut *self.stream));
+        let sub_renderer = Renderer {
+            __marker: PhantomData,
+            cx,
+            source: self.source,
+            stream: &mut stream,
+            column_alignment: self.column_alignment.clone(),
+            cell_index: 0,
+            end_tag: Some(TagEnd::Item),
+            parent: MarkdownTag::ListItem,
+            in_link: self.in_link,
+            depth: self.depth + 1,
+            pending: None,
+            document: self.document.clone(),
+            lines: None,
+        };
+        cx.el_with_attributes(Li, cx.el_fragment(sub_renderer.collect()), attributes)
+    }
+
+    /// extract the text from the text events until the end of the tag.
+    /// Returns None if the tag is empty
     fn children_text(&mut self, tag: Tag<'a>) -> Option<String> {
-        let text = match self.stream.next() {
-            Some((Event::Text(s), _)) => Some(s.to_string()),
-            None => None,
-            _ => panic!("expected string event, got something else")
+        let end = as_closing_tag(&tag);
+        let mut text: Option<String> = None;
+        loop {
+            match self.stream.next() {
+                Some((Event::Text(s), _)) => text.get_or_insert_with(String::new).push_str(&s),
+                Some((Event::End(t), _)) if t == end => return text,
+                None => return text,
+                _ => panic!("expected string event, got something else")
+            }
+        }
+    }
+
+    /// collects the events until the end of the tag, without rendering them
+    fn children_events(&mut self, tag: Tag<'a>) -> Events<'a> {
+        let end = as_closing_tag(&tag);
+        let mut events = Vec::new();
+        let mut depth = 0;
+        for (event, range) in self.stream.by_ref() {
+            match &event {
+                Event::Start(t) if as_closing_tag(t) == end => depth += 1,
+                Event::End(t) if *t == end => {
+                    if depth == 0 {
+                        break
+                    }
+                    depth -= 1
+                },
+                _ => ()
+            }
+            events.push((event, range));
+        }
+        events
+    }
+
+    /// renders buffered events in a new renderer,
+    /// inside the element described by `parent`
+    fn render_events(&self, events: Events<'a>, parent: MarkdownTag) -> F::View {
+        let mut events = events.into_iter();
+        let sub_renderer = Renderer {
+            __marker: PhantomData,
+            cx: self.cx,
+            source: self.source,
+            stream: &mut events,
+            column_alignment: self.column_alignment.clone(),
+            cell_index: 0,
+            end_tag: None,
+            in_link: self.in_link || matches!(parent, MarkdownTag::Link(_) | MarkdownTag::Image),
+            // the buffered events may belong to a block container
+            depth: self.depth + 1,
+            parent,
+            pending: None,
+            document: self.document.clone(),
+            lines: None,
         };
+        self.cx.el_fragment(sub_renderer.collect())
+    }
 
-        self.assert_closing_tag(as_closing_tag(&tag));
-        text
+    /// the alignment and attributes of the table cell in the column `column`
+    fn table_cell(&self, column: usize, head: bool) -> (Alignment, ElementAttributes<F::Handler<F::MouseEvent>>) {
+        // rows can have more cells than the table has columns
+        let align = self.column_alignment.as_ref()
+            .and_then(|a| a.get(column).copied())
+            .unwrap_or(Alignment::None);
+        let attributes = match head {
+            true => vec![("scope".to_string(), "col".to_string())],
+            false => vec![]
+        };
+        (align, ElementAttributes {
+            attributes,
+            ..Default::default()
+        })
+    }
+
+    /// renders a table with merged cells, with `table_spans`.
+    /// The whole table is buffered to compute the spans of its cells
+    fn table_with_spans(&mut self, tag: Tag<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        // the cells of each row, and whether the row is the head
+        let mut rows: Vec<(bool, Vec<Events<'a>>)> = Vec::new();
+        let mut caption = None;
+        let mut events = self.children_events(tag).into_iter();
+        while let Some((event, _)) = events.next() {
+            match event {
+                Event::Start(Tag::Paragraph) => {
+                    let content = events.by_ref()
+                        .take_while(|(e, _)| *e != Event::End(TagEnd::Paragraph))
+                        .collect();
+                    caption = Some(cx.el(Caption, self.render_events(content, MarkdownTag::Paragraph)));
+                },
+                Event::Start(Tag::TableHead) => rows.push((true, Vec::new())),
+                Event::Start(Tag::TableRow) => rows.push((false, Vec::new())),
+                Event::Start(Tag::TableCell) => {
+                    let cell = events.by_ref()
+                        .take_while(|(e, _)| *e != Event::End(TagEnd::TableCell))
+                        .collect();
+                    if let Some((_, cells)) = rows.last_mut() {
+                        cells.push(cell)
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        let merges: Vec<_> = rows.iter()
+            .map(|(head, cells)| (*head, cells.iter().map(cell_merge).collect()))
+            .collect();
+        let rows = caption.into_iter().chain(rows.into_iter().zip(cell_spans(&merges))
+            .map(|((head, cells), spans)| {
+                let cells = cells.into_iter().zip(spans).enumerate()
+                    .filter_map(|(column, (events, span))| {
+                        let (colspan, rowspan) = span?;
+                        let (align, mut attributes) = self.table_cell(column, head);
+                        if colspan > 1 {
+                            attributes.attributes.push(("colspan".to_string(), colspan.to_string()));
+                        }
+                        if rowspan > 1 {
+                            attributes.attributes.push(("rowspan".to_string(), rowspan.to_string()));
+                        }
+                        let content = self.render_events(events, MarkdownTag::TableCell);
+                        Some(cx.el_table_cell(content, head, align, attributes))
+                    })
+                    .collect();
+                cx.el(if head {Thead} else {Trow}, cx.el_fragment(cells))
+            }))
+            .collect();
+        let attributes = ElementAttributes {
+            attributes: self.source_line(&range).into_iter().map(source_line_attribute).collect(),
+            ..Default::default()
+        };
+        cx.el_with_attributes(Table, cx.el_fragment(rows), attributes)
+    }
+
+    /// renders a paragraph after looking at its content:
+    /// as a figure if it is only an image with a title and
+    /// `figure_for_titled_images` is set, as a definition list
+    /// if it looks like one and `definition_lists` is set,
+    /// or as a normal paragraph otherwise
+    fn buffered_paragraph(&mut self, tag: Tag<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let events = self.children_events(tag);
+
+        let embed = cx.props().auto_embed
+            .then(|| standalone_link_url(&events))
+            .flatten()
+            .and_then(|url| Some(EmbedDescription {
+                provider: cx.embed_provider(&url)?,
+                url,
+                range: range.clone(),
+            }))
+            .and_then(|embed| cx.render_embed(embed));
+        if let Some(embed) = embed {
+            return embed
+        }
+
+        if let Some(title) = cx.props().figure_for_titled_images
+            .then(|| standalone_image_title(&events))
+            .flatten() {
+            let image = self.render_events(events, MarkdownTag::Paragraph);
+            let caption = cx.el(Figcaption, cx.el_text(title.into()));
+            return cx.el(Figure, cx.el_fragment(vec![image, caption]))
+        }
+
+        let events = match cx.props().definition_lists {
+            true => match definition_list_lines(self.source, events) {
+                Ok(lines) => {
+                    let items = lines.into_iter()
+                        .map(|(definition, line)| {
+                            let e = if definition {DefinitionDetails} else {DefinitionTerm};
+                            cx.el(e, self.render_events(line, MarkdownTag::Paragraph))
+                        })
+                        .collect();
+                    return cx.el(DefinitionList, cx.el_fragment(items))
+                },
+                Err(events) => events
+            },
+            false => events
+        };
+
+        cx.el_with_attributes(
+            Paragraph,
+            self.render_events(events, MarkdownTag::Paragraph),
+            self.block_attributes(&range, MarkdownTag::Paragraph)
+        )
+    }
+
+    /// the first line of the source at `range`
+    fn first_line(&self, range: &Range<usize>) -> &'a str {
+        &self.source[range.start..line_end(self.source, range.start)]
+    }
+
+    /// renders a collapsible details block, from the paragraph
+    /// at `range` starting with `::: details` to a `:::` paragraph.
+    /// Without a closing paragraph, it ends with its parent element
+    fn details(&mut self, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let title = parse_details_marker(self.first_line(&range)).unwrap_or_default();
+
+        let mut content = vec![(Event::Start(Tag::Paragraph), range.clone())];
+        content.extend(self.children_events(Tag::Paragraph));
+        content.push((Event::End(TagEnd::Paragraph), range));
+        if !strip_first_line(self.source, &mut content) {
+            return cx.el(Paragraph, self.render_events(content, MarkdownTag::Paragraph))
+        }
+
+        let mut depth = 0;
+        let mut nested = 0;
+        while let Some((event, range)) = self.stream.next() {
+            match &event {
+                Event::End(_) if depth == 0 => {
+                    self.pending = Some((event, range));
+                    break
+                },
+                Event::Start(Tag::Paragraph) if depth == 0 => {
+                    if self.source[range.clone()].trim() == ":::" {
+                        if nested == 0 {
+                            self.children_events(Tag::Paragraph);
+                            break
+                        }
+                        nested -= 1;
+                    }
+                    else if parse_details_marker(self.first_line(&range)).is_some() {
+                        nested += 1;
+                    }
+                },
+                _ => ()
+            }
+            match &event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                _ => ()
+            }
+            content.push((event, range));
+        }
+
+        let summary = cx.el(Summary, cx.el_text(title.into()));
+        let content = self.render_events(content, self.parent.clone());
+        cx.el(
+            Details(cx.props().details_open),
+            cx.el_fragment(vec![summary, content])
+        )
+    }
+
+    /// renders a blockquote, as a github alert if it starts with
+    /// a marker like `[!NOTE]` and the alerts are enabled.
+    /// If the attributions are enabled, its last line starting with `— `
+    /// is rendered as a `footer` with the author in a `cite`
+    fn blockquote(&mut self, tag: Tag<'a>, range: Range<usize>) -> F::View {
+        let cx = self.cx;
+        let events = self.children_events(tag);
+        let (alert, events) = match cx.props().github_alerts {
+            true => match split_alert(self.source, events) {
+                Ok((kind, title, events)) => (Some((kind, title)), events),
+                Err(events) => (None, events)
+            },
+            false => (None, events)
+        };
+        let (events, attribution) = match cx.props().blockquote_attribution {
+            true => match split_attribution(events) {
+                Ok((events, attribution)) => (events, Some(attribution)),
+                Err(events) => (events, None)
+            },
+            false => (events, None)
+        };
+
+        let mut attributes = self.block_attributes(&range, MarkdownTag::BlockQuote);
+        let mut children = Vec::new();
+        if let Some((kind, title)) = alert {
+            children.push(cx.el_with_attributes(
+                Paragraph,
+                cx.el_text(title.into()),
+                ElementAttributes {
+                    classes: vec!["markdown-alert-title".into()],
+                    ..Default::default()
+                }
+            ));
+            attributes.classes = vec![
+                "markdown-alert".into(),
+                format!("markdown-alert-{kind}").into(),
+            ];
+        }
+        children.push(self.render_events(events, MarkdownTag::BlockQuote));
+        if let Some(attribution) = attribution {
+            let author = self.render_events(attribution, MarkdownTag::BlockQuote);
+            children.push(cx.el(Footer, cx.el_fragment(vec![
+                cx.el_text(ATTRIBUTION_MARKER.into()),
+                cx.el(Cite, author),
+            ])));
+        }
+        cx.el_with_attributes(BlockQuote, cx.el_fragment(children), attributes)
+    }
+
+    /// renders a link or an image.
+    /// Wikilinks are resolved with [`Context::resolve_wikilink`]
+    fn link(&mut self, tag: Tag<'a>, link_type: LinkType, dest_url: CowStr<'a>, title: CowStr<'a>, image: bool, range: Range<usize>)
+        -> Result<F::View, HtmlError> {
+        let cx = self.cx;
+        let id = match &tag {
+            Tag::Link{id, ..} | Tag::Image{id, ..} => id.to_string(),
+            _ => String::new()
+        };
+        let events = self.children_events(tag);
+
+        let broken = matches!(link_type,
+            LinkType::ReferenceUnknown | LinkType::CollapsedUnknown | LinkType::ShortcutUnknown);
+        if broken && dest_url.is_empty() {
+            let source = self.source.get(range.clone()).unwrap_or_default().to_string();
+            if !cx.has_custom_broken_links() {
+                return Ok(cx.el_text(source.into()))
+            }
+            let link = BrokenLink { reference: id, source, range };
+            return cx.render_broken_link(link).map_err(HtmlError::Link)
+        }
+        let alt = events_text(events.iter().map(|(e, _)| e));
+
+        // images can end with a size, like `<img.png =200x100>`
+        let (dest_url, width, height) = match image.then(|| parse_image_size(&dest_url)).flatten() {
+            Some((url, width, height)) => (CowStr::from(url.to_string()), width, height),
+            None => (dest_url, None, None)
+        };
+
+        let url = match link_type {
+            LinkType::WikiLink => cx.resolve_wikilink(&dest_url),
+            _ => Some(dest_url.to_string())
+        };
+
+        let url = match (url, cx.props().base_url) {
+            (Some(url), Some(base)) => Some(resolve_url(base, &url)),
+            (url, _) => url
+        };
+        let url = match (url, cx.props().url_rewriter) {
+            (Some(url), Some(rewrite)) => Some(rewrite(&url)),
+            (url, _) => url
+        };
+
+        // unsafe links and images are replaced by their content
+        let url = url.filter(|url| !cx.props().sanitize_html || is_safe_url(url));
+
+        let parent = match &url {
+            _ if image => MarkdownTag::Image,
+            Some(url) => MarkdownTag::Link(url.clone()),
+            None => self.parent.clone(),
+        };
+        let content = self.render_events(events, parent);
+
+        let url = match (url, link_type) {
+            (Some(url), _) => url,
+            (None, LinkType::WikiLink) => return Ok(cx.el_with_attributes(
+                    Span,
+                    content,
+                    ElementAttributes {
+                        classes: vec!["broken-link".into()],
+                        ..Default::default()
+                    }
+            )),
+            (None, _) => return Ok(content)
+        };
+
+        if image && cx.has_custom_images() {
+            let description = ImageDescription {
+                src: url,
+                alt,
+                title: title.to_string(),
+                width,
+                height,
+                range,
+            };
+            return cx.render_images(description).map_err(HtmlError::Link)
+        }
+
+        if image && !cx.has_custom_links() {
+            let title = Some(title.to_string()).filter(|title| !title.is_empty());
+            let remote = !url.trim_start().get(..5).is_some_and(|s| s.eq_ignore_ascii_case("data:"));
+            let attributes = ImageAttributes {
+                crossorigin: cx.props().image_crossorigin.filter(|_| remote),
+                referrer_policy: cx.props().image_referrer_policy
+                    .filter(|_| remote)
+                    .map(str::to_string),
+                loading: self.next_image_loading(),
+                width,
+                height,
+                aria_label: title.clone().filter(|_| cx.props().image_title_as_aria_label),
+                title,
+                presentation: alt.is_empty() && cx.props().decorative_images,
+            };
+            return Ok(cx.el_img_with_attributes(url, alt, attributes))
+        }
+
+        let description = LinkDescription {
+            url,
+            title: title.to_string(),
+            content,
+            link_type,
+            image,
+        };
+        cx.render_link(description).map_err(HtmlError::Link)
+    }
+
+    /// when to load the next image of the document:
+    /// the first `eager_images` images are never lazy
+    fn next_image_loading(&self) -> ImageLoading {
+        let images = self.document.borrow_mut().next_image();
+        match images > self.cx.props().eager_images {
+            true => self.cx.props().image_loading,
+            false => ImageLoading::Eager,
+        }
     }
 
     // check that the closing tag is what was expected
@@ -422,67 +2196,142 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     -> Result<F::View, HtmlError> 
     {
         let cx = self.cx;
+        // the blocks nested too deeply are rendered as plain text
+        if is_block_container(&tag) && cx.props().max_nesting_depth.is_some_and(|max| self.depth >= max) {
+            let events = self.children_events(tag);
+            return Ok(cx.el_text(events_text(events.iter().map(|(e, _)| e)).into()))
+        }
         Ok(match tag.clone() {
             Tag::HtmlBlock => {
-                let raw_html = match self.stream.next() {
-                    Some((Event::Html(s), _)) => s.to_string(),
-                    None => panic!("empty html"),
-                    _ => panic!("expected html event, got something else")
-                };
-                self.assert_closing_tag(TagEnd::HtmlBlock);
+                // a block on several lines has an html event per line
+                let mut raw_html = String::new();
+                loop {
+                    match self.stream.next() {
+                        Some((Event::Html(s), _)) => raw_html.push_str(&s),
+                        Some((Event::End(TagEnd::HtmlBlock), _)) => break,
+                        None => panic!("unterminated html block"),
+                        _ => panic!("expected html event, got something else")
+                    }
+                }
                 self.html(&raw_html, range)?
             },
-            Tag::Paragraph => cx.el(Paragraph, self.children(tag)),
-            Tag::Heading{level, ..} => cx.el(Heading(level as u8), self.children(tag)),
-            Tag::BlockQuote => cx.el(BlockQuote, self.children(tag)),
-            Tag::CodeBlock(k) => 
-                render_code_block(cx, self.children_text(tag).unwrap_or_default(), &k, range),
-            Tag::List(Some(n0)) => cx.el(Ol(n0 as i32), self.children(tag)),
-            Tag::List(None) => cx.el(Ul, self.children(tag)),
-            Tag::Item => cx.el(Li, self.children(tag)),
+            // the caption of a table, see `move_table_captions`
+            Tag::Paragraph if self.end_tag == Some(TagEnd::Table) =>
+                cx.el(Caption, self.children(tag)),
+            Tag::Paragraph if cx.props().details_blocks
+                && parse_details_marker(self.first_line(&range)).is_some() =>
+                self.details(range),
+            Tag::Paragraph if cx.props().definition_lists || cx.props().figure_for_titled_images
+                || cx.props().auto_embed =>
+                self.buffered_paragraph(tag, range),
+            Tag::Paragraph => cx.el_with_attributes(
+                Paragraph,
+                self.children(tag),
+                self.block_attributes(&range, MarkdownTag::Paragraph)
+            ),
+            Tag::Heading{level, id, ..} => {
+                let events = self.children_events(tag);
+                let slug = match id {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let slugify = cx.props().slugify.unwrap_or(crate::utils::slugify);
+                        slugify(&events_text(events.iter().map(|(e, _)| e)))
+                    }
+                };
+                let slug = self.document.borrow_mut().unique_slug(slug);
+                let text = events_text(events.iter().map(|(e, _)| e));
+                let mut content = self.render_events(events, MarkdownTag::Heading(level as u8));
+                if cx.props().heading_anchor_links {
+                    content = cx.el_fragment(vec![content, heading_anchor(cx, &slug, &text)]);
+                }
+                cx.render_heading(HeadingDescription {
+                    level: (level as u8).saturating_add(cx.props().heading_offset).min(6),
+                    content,
+                    slug,
+                    source_line: self.source_line(&range),
+                    range,
+                })
+            },
+            Tag::BlockQuote => self.blockquote(tag, range),
+            Tag::CodeBlock(k) => {
+                let mut fence = match &k {
+                    CodeBlockKind::Fenced(info) => CodeFence::parse(info),
+                    CodeBlockKind::Indented => CodeFence::default()
+                };
+                fence.source_line = self.source_line(&range);
+                let source = self.children_text(tag).unwrap_or_default();
+                match fence.lang.clone() {
+                    Some(lang) if cx.has_custom_code_block(&lang) => {
+                        let code = CodeBlockProps {
+                            fence,
+                            source,
+                            range,
+                        };
+                        cx.render_custom_code_block(code)
+                            .map_err(|msg| HtmlError::CustomCodeBlock{lang, msg})?
+                    },
+                    _ => cx.render_code_fence(&fence, &source, range)
+                }
+            },
+            Tag::List(start) => {
+                let (element, class) = match start {
+                    Some(n0) => (Ol(n0 as i32), cx.props().ordered_list_class),
+                    None => (Ul, cx.props().unordered_list_class),
+                };
+                let attributes = ElementAttributes {
+                    classes: class.map(|c| c.to_string().into()).into_iter().collect(),
+                    attributes: self.source_line(&range).into_iter().map(source_line_attribute).collect(),
+                    ..Default::default()
+                };
+                cx.el_with_attributes(element, self.children(tag), attributes)
+            },
+            Tag::Item => self.list_item(range),
+            Tag::Table(align) if cx.props().table_spans => {
+                self.column_alignment = Some(align);
+                self.table_with_spans(tag, range)
+            },
             Tag::Table(align) => {
                 self.column_alignment = Some(align);
-                cx.el(Table, self.children(tag))
+                let attributes = ElementAttributes {
+                    attributes: self.source_line(&range).into_iter().map(source_line_attribute).collect(),
+                    ..Default::default()
+                };
+                cx.el_with_attributes(Table, self.children(tag), attributes)
             }
             Tag::TableHead => cx.el(Thead, self.children(tag)),
             Tag::TableRow => cx.el(Trow, self.children(tag)),
             Tag::TableCell => {
-                let align = self.column_alignment.clone().unwrap()[self.cell_index];
+                // the cells of the head are directly inside it
+                let head = self.end_tag == Some(TagEnd::TableHead);
+                let (align, attributes) = self.table_cell(self.cell_index, head);
                 self.cell_index += 1;
-                cx.el_with_attributes(Tcell, self.children(tag), 
-                      ElementAttributes{
-                          style:Some(align_string(align).to_string()),
-                          ..Default::default()}
-                )
+                cx.el_table_cell(self.children(tag), head, align, attributes)
             },
             Tag::Emphasis => cx.el(Italics, self.children(tag)),
             Tag::Strong => cx.el(Bold, self.children(tag)),
+            // with `superscript_subscript`, a single tilde is a subscript
+            Tag::Strikethrough if cx.props().superscript_subscript
+                && self.source[range.clone()].starts_with('~')
+                && !self.source[range.clone()].starts_with("~~") =>
+                cx.el(Sub, self.children(tag)),
+            Tag::Strikethrough if cx.props().strikethrough_as_del => cx.el(Del, self.children(tag)),
             Tag::Strikethrough => cx.el(StrikeThrough, self.children(tag)),
-            Tag::Image{link_type, dest_url, title, ..} => {
-                let description = LinkDescription {
-                    url: dest_url.to_string(),
-                    title: title.to_string(),
-                    content: self.children(tag),
-                    link_type,
-                    image: true,
-                };
-                cx.render_link(description).map_err(HtmlError::Link)?
+            Tag::Image{link_type, dest_url, title, ..} =>
+                self.link(tag, link_type, dest_url, title, true, range)?,
+            Tag::Link{link_type, dest_url, title, ..} =>
+                self.link(tag, link_type, dest_url, title, false, range)?,
+            Tag::FootnoteDefinition(label) => {
+                let content = self.children(tag);
+                self.document.borrow_mut().define_footnote(&label, content);
+                cx.el_empty()
             },
-            Tag::Link{link_type, dest_url, title, ..} => {
-                let description = LinkDescription {
-                    url: dest_url.to_string(),
-                    title: title.to_string(),
-                    content: self.children(tag),
-                    link_type,
-                    image: false,
+            Tag::MetadataBlock(kind) => {
+                let format = match kind {
+                    MetadataBlockKind::YamlStyle => FrontmatterFormat::Yaml,
+                    MetadataBlockKind::PlusesStyle => FrontmatterFormat::Toml,
                 };
-                cx.render_link(description).map_err(HtmlError::Link)?
-            },
-            Tag::FootnoteDefinition(_) => 
-                return Err(HtmlError::not_implemented("footnote not implemented")),
-            Tag::MetadataBlock{..} => {
                 if let Some(text) = self.children_text(tag) {
-                    cx.set_frontmatter(text)
+                    cx.set_frontmatter_with_format(format, text)
                 }
                 cx.el_empty()
             }
@@ -491,3 +2340,850 @@ where I: Iterator<Item=(Event<'a>, Range<usize>)>,
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AnchorScroll,
+        MarkdownProps,
+        ComponentCreationError,
+    };
+
+    #[derive(Clone, Copy, Default)]
+    /// a context rendering html strings, with escaped text
+    struct TestContext<'a>(MarkdownProps<'a>);
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    thread_local! {
+        /// the tags of the click handlers created by `TestContext`
+        static HANDLER_TAGS: RefCell<Vec<MarkdownTag>> = const { RefCell::new(Vec::new()) };
+    }
+
+    impl<'a> Context<'a, 'a> for TestContext<'a> {
+        type View = String;
+        type Handler<T: 'a> = ();
+        type MouseEvent = ();
+
+        fn props(self) -> MarkdownProps<'a> { self.0 }
+        fn set_frontmatter(self, _frontmatter: String) {}
+        fn render_embed(self, embed: EmbedDescription) -> Option<String> {
+            Some(format!("<Embed {} {}>", embed.provider, embed.url))
+        }
+        fn render_links(self, _link: LinkDescription<String>) -> Result<String, String> {
+            Err("no custom links".to_string())
+        }
+        fn call_handler<T>(_callback: &(), _input: T) {}
+        fn make_md_handler(self, _position: Range<usize>, _stop_propagation: bool) {}
+        fn make_md_tag_handler(self, _position: Range<usize>, tag: MarkdownTag, _stop_propagation: bool) {
+            HANDLER_TAGS.with(|tags| tags.borrow_mut().push(tag))
+        }
+        #[cfg(feature="debug")]
+        fn send_debug_info(self, _info: Vec<String>) {}
+        fn el_with_attributes(self, e: HtmlElement, inside: String, _attributes: ElementAttributes<()>) -> String {
+            format!("<{e:?}>{inside}</{e:?}>")
+        }
+        fn el_span_with_inner_html(self, inner_html: String, _attributes: ElementAttributes<()>) -> String {
+            format!("<Span>{inner_html}</Span>")
+        }
+        fn el_hr(self, _attributes: ElementAttributes<()>) -> String { "<hr>".to_string() }
+        fn el_br(self) -> String { "<br>".to_string() }
+        fn el_fragment(self, children: Vec<String>) -> String { children.concat() }
+        fn el_a_with_attributes(self, children: String, href: String, attributes: ElementAttributes<()>) -> String {
+            let mut a = format!("<a href=\"{}\"", escape(&href));
+            for (name, value) in attributes.attributes {
+                a += &format!(" {name}=\"{}\"", escape(&value));
+            }
+            if attributes.on_click.is_some() {
+                a += " onclick";
+            }
+            format!("{a}>{children}</a>")
+        }
+        fn make_anchor_scroll_handler(self, _id: String, _scroll: AnchorScroll) -> Option<()> {
+            Some(())
+        }
+        fn el_img_with_attributes(self, src: String, alt: String, attributes: ImageAttributes) -> String {
+            let mut img = format!("<img src=\"{}\" alt=\"{}\"", escape(&src), escape(&alt));
+            if let Some(width) = attributes.width {
+                img += &format!(" width=\"{width}\"");
+            }
+            if attributes.loading == ImageLoading::Lazy {
+                img += " loading=\"lazy\"";
+            }
+            if let Some(title) = attributes.title {
+                img += &format!(" title=\"{}\"", escape(&title));
+            }
+            if attributes.presentation {
+                img += " role=\"presentation\"";
+            }
+            format!("{img}>")
+        }
+        fn el_text(self, text: CowStr<'a>) -> String { escape(&text) }
+        fn el_input_checkbox(self, checked: bool, _attributes: ElementAttributes<()>) -> String {
+            format!("<input checked=\"{checked}\">")
+        }
+        fn mount_dynamic_link_with(self, _rel: &str, _href: &str, _integrity: Option<&str>, _crossorigin: Option<&str>) {}
+        fn has_custom_component(self, name: &str) -> bool {
+            matches!(name, "Box" | "Fail" | "Panic")
+        }
+        fn render_custom_component(self, name: &str, input: MdComponentProps<String>) -> Result<String, ComponentCreationError> {
+            match name {
+                "Box" => Ok(format!("<Box>{}</Box>", input.children)),
+                "Panic" => panic!("boom"),
+                _ => Err(name.into())
+            }
+        }
+        fn has_custom_links(self) -> bool { false }
+    }
+
+    #[test]
+    fn inline_code_is_escaped(){
+        let cx = TestContext::default();
+        assert_eq!(
+            cx.render_code("<script>alert(1)</script>".into(), 0..0),
+            "<Code>&lt;script&gt;alert(1)&lt;/script&gt;</Code>"
+        );
+        assert_eq!(cx.render_code("&amp;".into(), 0..0), "<Code>&amp;amp;</Code>");
+    }
+
+    #[test]
+    fn code_block_is_escaped(){
+        let cx = TestContext::default();
+        let html = cx.render_code_block(None, "<script>\n&amp;\n", 0..0);
+        assert!(html.contains("&lt;script&gt;\n&amp;amp;</Code>"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn code_language_label(){
+        let cx = TestContext(MarkdownProps {
+            code_language_label: true,
+            ..Default::default()
+        });
+        let html = cx.render_code_fence(&CodeFence::parse("ts {1}"), "let a;\n", 0..0);
+        assert!(html.starts_with("<Div><Span>ts</Span><Pre>"));
+        let html = cx.render_code_block(None, "let a;\n", 0..0);
+        assert!(html.starts_with("<Pre>"));
+    }
+
+    #[test]
+    fn code_fences_without_highlighted_lines(){
+        let cx = TestContext(MarkdownProps {
+            code_language_label: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            cx.render_code_fence(&CodeFence::parse("rust,ignore"), "let a;\n", 0..0),
+            cx.render_code_block(Some("rust"), "let a;\n", 0..0)
+        );
+    }
+
+    #[test]
+    fn copy_button_needs_a_handler(){
+        let cx = TestContext(MarkdownProps {
+            code_copy_button: true,
+            ..Default::default()
+        });
+        // the context has no copy handler
+        let html = cx.render_code_block(None, "let a;\n", 0..0);
+        assert!(html.starts_with("<Pre>"));
+        assert!(!html.contains("Copy"));
+    }
+
+    #[test]
+    fn code_block_trailing_newline(){
+        let cx = TestContext::default();
+        let html = cx.render_code_block(None, "a\n\nb\n", 0..0);
+        assert_eq!(html, cx.render_code_block(None, "a\n\nb", 0..0));
+        assert!(html.contains("<Code>a\n\nb</Code>"));
+    }
+
+    #[cfg(feature="syntect")]
+    #[test]
+    fn highlight_cache_drops_the_oldest_block(){
+        let mut cache = HighlightCache::default();
+        let key = |i: usize| ("rust".to_string(), DEFAULT_THEME.to_string(), format!("let a = {i};"));
+        for i in 0..=HIGHLIGHT_CACHE_CAPACITY {
+            cache.insert(key(i), Arc::new(HighlightedCode { lines: vec![] }));
+        }
+        assert!(cache.get(&key(0)).is_none());
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(HIGHLIGHT_CACHE_CAPACITY)).is_some());
+        assert_eq!(cache.blocks.len(), HIGHLIGHT_CACHE_CAPACITY);
+    }
+
+    #[test]
+    fn new_tab_links(){
+        let link = |url: &str| LinkDescription {
+            url: url.to_string(),
+            content: "see".to_string(),
+            title: String::new(),
+            link_type: LinkType::Inline,
+            image: false,
+        };
+        let cx = TestContext(MarkdownProps {
+            open_links_in_new_tab: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            cx.render_link(link("https://example.com")).unwrap(),
+            "<a href=\"https://example.com\" target=\"_blank\" rel=\"noopener noreferrer\">see</a>"
+        );
+        assert_eq!(cx.render_link(link("page.html")).unwrap(), "<a href=\"page.html\">see</a>");
+    }
+
+    #[test]
+    fn anchor_scroll_handlers(){
+        let link = |url: &str| LinkDescription {
+            url: url.to_string(),
+            content: "see".to_string(),
+            title: String::new(),
+            link_type: LinkType::Inline,
+            image: false,
+        };
+        let cx = TestContext(MarkdownProps {
+            anchor_scroll: Some(AnchorScroll::default()),
+            ..Default::default()
+        });
+        assert_eq!(cx.render_link(link("#section")).unwrap(), "<a href=\"#section\" onclick>see</a>");
+        assert_eq!(cx.render_link(link("https://example.com")).unwrap(), "<a href=\"https://example.com\">see</a>");
+        assert_eq!(cx.render_link(link("page#section")).unwrap(), "<a href=\"page#section\">see</a>");
+        // without `anchor_scroll`, the browser jumps to the section
+        let cx = TestContext::default();
+        assert_eq!(cx.render_link(link("#section")).unwrap(), "<a href=\"#section\">see</a>");
+    }
+
+    #[test]
+    fn link_text_handlers(){
+        HANDLER_TAGS.with(|tags| tags.borrow_mut().clear());
+        let events = paragraph(vec![
+            Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: "https://example.com".into(),
+                title: "".into(),
+                id: "".into(),
+            }),
+            Event::Text("see".into()),
+            Event::End(TagEnd::Link),
+        ]);
+        render(TestContext::default(), events);
+        let tags = HANDLER_TAGS.with(|tags| tags.take());
+        assert!(tags.contains(&MarkdownTag::Link("https://example.com".to_string())), "{tags:?}");
+    }
+
+    #[test]
+    fn image_attributes(){
+        let cx = TestContext(MarkdownProps {
+            image_loading: ImageLoading::Lazy,
+            eager_images: 1,
+            decorative_images: true,
+            ..Default::default()
+        });
+        let image = |url: &'static str, alt: &'static str| vec![
+            Event::Start(Tag::Image {
+                link_type: LinkType::Inline,
+                dest_url: url.into(),
+                title: "".into(),
+                id: "".into(),
+            }),
+            Event::Text(alt.into()),
+            Event::End(TagEnd::Image),
+        ];
+        assert_eq!(
+            render(cx, paragraph([image("a.png =200x", "a"), image("b.png", "")].concat())),
+            "<Paragraph><img src=\"a.png\" alt=\"a\" width=\"200\">\
+            <img src=\"b.png\" alt=\"\" loading=\"lazy\" role=\"presentation\"></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn multi_line_definitions(){
+        let source = "Term\nOther term\n: a definition\n  that continues\n: other";
+        let text = |t: &'static str| {
+            let start = source.find(t).unwrap();
+            (Event::Text(t.into()), start..start + t.len())
+        };
+        let soft_break = |after: &'static str| {
+            let end = source.find(after).unwrap() + after.len();
+            (Event::SoftBreak, end..end + 1)
+        };
+        let events = vec![
+            text("Term"),
+            soft_break("Term"),
+            text("Other term"),
+            soft_break("Other term"),
+            text(": a definition"),
+            soft_break(": a definition"),
+            text("that continues"),
+            soft_break("that continues"),
+            text(": other"),
+        ];
+        let lines: Vec<(bool, Vec<Event>)> = definition_list_lines(source, events).unwrap()
+            .into_iter()
+            .map(|(definition, line)| (definition, line.into_iter().map(|(e, _)| e).collect()))
+            .collect();
+        assert_eq!(lines, vec![
+            (false, vec![Event::Text("Term".into())]),
+            (false, vec![Event::Text("Other term".into())]),
+            (true, vec![Event::Text("a definition".into()), Event::SoftBreak, Event::Text("that continues".into())]),
+            (true, vec![Event::Text("other".into())]),
+        ]);
+    }
+
+    #[test]
+    fn lists_nested_in_definitions(){
+        let source = "Term\n: definition\n  - nested\n\n- outside\n";
+        let range = |t: &str| {
+            let start = source.find(t).unwrap();
+            start..start + t.len()
+        };
+        let list = |item: &'static str| vec![
+            (Event::Start(Tag::List(None)), range(item)),
+            (Event::Start(Tag::Item), range(item)),
+            (Event::Text(item[2..].into()), range(&item[2..])),
+            (Event::End(TagEnd::Item), range(item)),
+            (Event::End(TagEnd::List(false)), range(item)),
+        ];
+        let mut events = vec![
+            (Event::Start(Tag::Paragraph), range("Term\n: definition")),
+            (Event::Text("Term".into()), range("Term")),
+            (Event::SoftBreak, range("\n")),
+            (Event::Text(": definition".into()), range(": definition")),
+            (Event::End(TagEnd::Paragraph), range("Term\n: definition")),
+        ];
+        events.extend(list("- nested"));
+        events.extend(list("- outside"));
+        crate::nest_definition_lists(source, &mut events);
+        let cx = TestContext(MarkdownProps {
+            definition_lists: true,
+            ..Default::default()
+        });
+        let html: String = EventRenderer::new(cx, source, events.into_iter()).collect();
+        assert_eq!(
+            html,
+            "<DefinitionList><DefinitionTerm><Span>Term</Span></DefinitionTerm>\
+            <DefinitionDetails><Span>definition</Span>\
+            <Ul><Li><Span>nested</Span></Li></Ul></DefinitionDetails></DefinitionList>\
+            <Ul><Li><Span>outside</Span></Li></Ul>"
+        );
+    }
+
+    /// renders `events` as a whole document
+    fn render(cx: TestContext, events: Vec<Event<'static>>) -> String {
+        let events = events.into_iter().map(|e| (e, 0..0));
+        EventRenderer::new(cx, "", events).collect()
+    }
+
+    fn html_block(html: &'static str) -> Vec<Event<'static>> {
+        vec![
+            Event::Start(Tag::HtmlBlock),
+            Event::Html(html.into()),
+            Event::End(TagEnd::HtmlBlock),
+        ]
+    }
+
+    #[test]
+    fn html_comments_are_removed(){
+        let cx = TestContext::default();
+        assert_eq!(render(cx, html_block("<!-- secret -->\n")), "");
+        assert_eq!(render(cx, paragraph(vec![Event::InlineHtml("<!-- secret -->".into())])),
+            "<Paragraph></Paragraph>");
+
+        let cx = TestContext(MarkdownProps {
+            preserve_comments: true,
+            ..Default::default()
+        });
+        assert_eq!(render(cx, html_block("<!-- note -->")), "<Span><!-- note --></Span>");
+    }
+
+    #[test]
+    fn multi_line_html_comments_are_removed(){
+        let cx = TestContext::default();
+        let events = vec![
+            Event::Start(Tag::HtmlBlock),
+            Event::Html("<!--\n".into()),
+            Event::Html("secret\n".into()),
+            Event::Html("-->\n".into()),
+            Event::End(TagEnd::HtmlBlock),
+        ];
+        assert_eq!(render(cx, events), "");
+    }
+
+    #[test]
+    fn failing_components_are_contained(){
+        let cx = TestContext::default();
+        let mut events = html_block("<Panic/>");
+        events.extend(html_block("<Fail/>"));
+        events.extend(html_block("<Unknown/>"));
+        events.extend([
+            Event::Start(Tag::Paragraph),
+            Event::Text("after".into()),
+            Event::End(TagEnd::Paragraph),
+        ]);
+        let html = render(cx, events);
+        assert!(html.contains("Custom component `Panic` failed: `panicked: boom`"));
+        assert!(html.contains("Custom component `Fail` failed"));
+        assert!(html.contains("Custom component `Unknown` failed: `not a valid component`"));
+        assert!(html.ends_with("<Paragraph><Span>after</Span></Paragraph>"));
+    }
+
+    fn paragraph(events: Vec<Event<'static>>) -> Vec<Event<'static>> {
+        let mut paragraph = vec![Event::Start(Tag::Paragraph)];
+        paragraph.extend(events);
+        paragraph.push(Event::End(TagEnd::Paragraph));
+        paragraph
+    }
+
+    #[test]
+    fn component_children_are_rendered(){
+        let cx = TestContext::default();
+        let mut events = html_block("<Box>");
+        events.extend(html_block("<Box title='x'>"));
+        events.extend(paragraph(vec![
+            Event::Start(Tag::Strong),
+            Event::Text("bold".into()),
+            Event::End(TagEnd::Strong),
+        ]));
+        events.extend(html_block("</Box>"));
+        events.extend(html_block("</Box>"));
+        events.extend(paragraph(vec![Event::Text("after".into())]));
+        assert_eq!(
+            render(cx, events),
+            "<Box><Box><Paragraph><Bold><Span>bold</Span></Bold></Paragraph></Box></Box>\
+            <Paragraph><Span>after</Span></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn inline_components(){
+        let cx = TestContext::default();
+        let events = paragraph(vec![
+            Event::InlineHtml("<Box>".into()),
+            Event::Start(Tag::Emphasis),
+            Event::Text("a".into()),
+            Event::End(TagEnd::Emphasis),
+            Event::InlineHtml("</Box>".into()),
+            Event::Text("b".into()),
+            // without its end tag, the component ends with the paragraph
+            Event::InlineHtml("<Box>".into()),
+            Event::Text("c".into()),
+        ]);
+        assert_eq!(
+            render(cx, events),
+            "<Paragraph><Box><Italics><Span>a</Span></Italics></Box><Span>b</Span>\
+            <Box><Span>c</Span></Box></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn inline_markers(){
+        let source = "a ++b++ ||c|| ==d==";
+        let cx = TestContext(MarkdownProps {
+            insert: true,
+            kbd_marker: Some("||"),
+            ..Default::default()
+        });
+        let events = vec![(Event::Text(source.into()), 0..source.len())];
+        let html: String = EventRenderer::new(cx, source, events.into_iter()).collect();
+        assert_eq!(
+            html,
+            "<Span>a </Span><Ins><Span>b</Span></Ins><Span> </Span>\
+            <Kbd><Span>c</Span></Kbd><Span> ==d==</Span>"
+        );
+    }
+
+    #[test]
+    fn titled_images_are_figures(){
+        let cx = TestContext(MarkdownProps {
+            figure_for_titled_images: true,
+            ..Default::default()
+        });
+        let image = |title: &'static str| vec![
+            Event::Start(Tag::Image {
+                link_type: LinkType::Inline,
+                dest_url: "x.png".into(),
+                title: title.into(),
+                id: "".into(),
+            }),
+            Event::Text("alt".into()),
+            Event::End(TagEnd::Image),
+        ];
+        assert_eq!(
+            render(cx, paragraph(image("Caption"))),
+            "<Figure><img src=\"x.png\" alt=\"alt\" title=\"Caption\"><Figcaption>Caption</Figcaption></Figure>"
+        );
+        assert_eq!(
+            render(cx, paragraph(image(""))),
+            "<Paragraph><img src=\"x.png\" alt=\"alt\"></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn merged_table_cells(){
+        let cx = TestContext(MarkdownProps {
+            table_spans: true,
+            ..Default::default()
+        });
+        let cell = |text: &'static str| {
+            let mut events = vec![Event::Start(Tag::TableCell)];
+            events.extend((!text.is_empty()).then(|| Event::Text(text.into())));
+            events.push(Event::End(TagEnd::TableCell));
+            events
+        };
+        let events = [
+            vec![Event::Start(Tag::Table(vec![Alignment::None; 2])), Event::Start(Tag::TableHead)],
+            cell("a"), cell("b"),
+            vec![Event::End(TagEnd::TableHead), Event::Start(Tag::TableRow)],
+            cell("c"), cell(""),
+            vec![Event::End(TagEnd::TableRow), Event::Start(Tag::TableRow)],
+            cell("^"),
+            vec![Event::End(TagEnd::TableRow), Event::End(TagEnd::Table)],
+        ].concat();
+        assert_eq!(
+            render(cx, events),
+            "<Table><Thead><TheadCell><Span>a</Span></TheadCell><TheadCell><Span>b</Span></TheadCell></Thead>\
+            <Trow><Tcell><Span>c</Span></Tcell></Trow><Trow></Trow></Table>"
+        );
+    }
+
+    #[test]
+    fn element_keys(){
+        let events = vec![
+            (Event::Start(Tag::Paragraph), 0..2),
+            (Event::Text("a".into()), 0..1),
+            (Event::End(TagEnd::Paragraph), 0..2),
+            (Event::Rule, 3..6),
+            (Event::FootnoteReference("n".into()), 7..11),
+            (Event::Start(Tag::FootnoteDefinition("n".into())), 12..18),
+            (Event::Text("b".into()), 17..18),
+            (Event::End(TagEnd::FootnoteDefinition), 12..18),
+        ];
+        let mut renderer = EventRenderer::new(TestContext::default(), "", events.into_iter());
+        let mut keys = Vec::new();
+        while renderer.next().is_some() {
+            keys.push(renderer.key());
+        }
+        assert_eq!(keys, vec!["0-2", "3-6", "7-11", "12-18", FOOTNOTES_KEY]);
+    }
+
+    #[test]
+    fn break_markers(){
+        let events = || paragraph(vec![
+            Event::Text("a".into()),
+            Event::SoftBreak,
+            Event::Text("b".into()),
+            Event::HardBreak,
+            Event::Text("c".into()),
+        ]);
+        assert_eq!(
+            render(TestContext::default(), events()),
+            "<Paragraph><Span>a</Span> <Span>b</Span><br><Span>c</Span></Paragraph>"
+        );
+        let cx = TestContext(MarkdownProps {
+            show_break_markers: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            render(cx, events()),
+            "<Paragraph><Span>a</Span><Span>↵</Span> <Span>b</Span><Span>¶</Span><br><Span>c</Span></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn rule_ranges(){
+        let source = "a\n\n---\n\n  ***  \n___";
+        for rule in ["---", "***", "___"] {
+            let start = source.find(rule).unwrap();
+            let line_end = line_end(source, start);
+            // the event of the rule includes its indentation and its line ending
+            let line_start = source[..start].rfind('\n').unwrap() + 1;
+            let range = rule_range(source, line_start..(line_end + 1).min(source.len()));
+            assert_eq!(range, start..start + 3);
+        }
+    }
+
+    #[test]
+    fn nested_task_markers(){
+        let source = "- [ ] a\n  1. [x] b\n     - [X] c\n  2. text [ ] d\n3. [ ] e\n";
+        // the events of the markers can start at their item
+        let items = ["- [ ]", "1. [x]", "- [X]", "3. [ ]"];
+        let mut start = 0;
+        for item in items {
+            let item_start = start + source[start..].find(item).unwrap();
+            let marker = task_marker_range(source, item_start..item_start + 1);
+            assert_eq!(&source[marker.clone()], &item[item.len()-3..]);
+            assert_eq!(marker.end, item_start + item.len());
+            // the position of the marker itself is kept
+            assert_eq!(task_marker_range(source, marker.clone()), marker);
+            start = marker.end;
+        }
+        // the items without a marker on their line keep their position
+        let text = source.find("2. text").unwrap();
+        assert_eq!(task_marker_range(source, text..text + 2), text..text + 2);
+    }
+
+    #[test]
+    fn nested_task_markers_in_markdown(){
+        let source = "- [ ] a\n  1. [x] b\n     - [X] c\n  2. text [ ] d\n3. [ ] e\n";
+        let markers: Vec<(bool, Range<usize>)> = crate::parse(source, &MarkdownProps::default())
+            .into_iter()
+            .filter_map(|(event, range)| match event {
+                Event::TaskListMarker(checked) => Some((checked, task_marker_range(source, range))),
+                _ => None
+            })
+            .collect();
+        let items = [(false, "- [ ] a"), (true, "1. [x] b"), (true, "- [X] c"), (false, "3. [ ] e")];
+        let expected: Vec<(bool, Range<usize>)> = items.into_iter()
+            .map(|(checked, item)| {
+                let marker = source.find(item).unwrap() + item.find('[').unwrap();
+                (checked, marker..marker + 3)
+            })
+            .collect();
+        assert_eq!(markers, expected);
+    }
+
+    #[test]
+    fn embeds(){
+        let cx = TestContext(MarkdownProps {
+            auto_embed: true,
+            ..Default::default()
+        });
+        let link = |url: &'static str| vec![
+            Event::Start(Tag::Link {
+                link_type: LinkType::Autolink,
+                dest_url: url.into(),
+                title: "".into(),
+                id: "".into(),
+            }),
+            Event::Text(url.into()),
+            Event::End(TagEnd::Link),
+        ];
+        assert_eq!(
+            render(cx, paragraph(link("https://youtu.be/abc"))),
+            "<Embed youtube https://youtu.be/abc>"
+        );
+        // the links with some text around them stay links
+        let mut events = vec![Event::Text("see ".into())];
+        events.extend(link("https://youtu.be/abc"));
+        assert_eq!(
+            render(cx, paragraph(events)),
+            "<Paragraph><Span>see </Span><a href=\"https://youtu.be/abc\"><Span>https://youtu.be/abc</Span></a></Paragraph>"
+        );
+    }
+
+    #[test]
+    fn blockquote_attribution(){
+        let cx = TestContext(MarkdownProps {
+            blockquote_attribution: true,
+            ..Default::default()
+        });
+        let quote = |events: Vec<Event<'static>>| [
+            vec![Event::Start(Tag::BlockQuote)],
+            events,
+            vec![Event::End(TagEnd::BlockQuote)],
+        ].concat();
+        assert_eq!(
+            render(cx, quote(paragraph(vec![
+                Event::Text("Quote".into()),
+                Event::SoftBreak,
+                Event::Text("— Author".into()),
+            ]))),
+            "<BlockQuote><Paragraph><Span>Quote</Span></Paragraph>\
+            <Footer>— <Cite><Span>Author</Span></Cite></Footer></BlockQuote>"
+        );
+        // only the last paragraph can be an attribution
+        assert_eq!(
+            render(cx, quote([
+                paragraph(vec![Event::Text("— Not an author".into())]),
+                paragraph(vec![Event::Text("Quote".into())]),
+            ].concat())),
+            "<BlockQuote><Paragraph><Span>— Not an author</Span></Paragraph>\
+            <Paragraph><Span>Quote</Span></Paragraph></BlockQuote>"
+        );
+        assert_eq!(
+            render(cx, quote([
+                paragraph(vec![Event::Text("Quote".into())]),
+                paragraph(vec![Event::Text("— Author".into())]),
+            ].concat())),
+            "<BlockQuote><Paragraph><Span>Quote</Span></Paragraph>\
+            <Footer>— <Cite><Span>Author</Span></Cite></Footer></BlockQuote>"
+        );
+    }
+
+    #[test]
+    fn table_captions(){
+        let table = || vec![
+            Event::Start(Tag::Table(vec![Alignment::None])),
+            Event::Start(Tag::TableHead),
+            Event::Start(Tag::TableCell),
+            Event::Text("a".into()),
+            Event::End(TagEnd::TableCell),
+            Event::End(TagEnd::TableHead),
+            Event::End(TagEnd::Table),
+        ];
+        let events = [
+            table(),
+            paragraph(vec![Event::Text("Table: Second".into())]),
+            table(),
+        ].concat();
+        let mut events: Vec<_> = events.into_iter().map(|e| (e, 0..0)).collect();
+        crate::move_table_captions(&mut events, "Table:");
+        let events = events.into_iter().map(|(e, _)| e).collect();
+        let cx = TestContext(MarkdownProps::default());
+        assert_eq!(
+            render(cx, events),
+            "<Table><Thead><TheadCell><Span>a</Span></TheadCell></Thead></Table>\
+            <Table><Caption><Span>Second</Span></Caption><Thead><TheadCell><Span>a</Span></TheadCell></Thead></Table>"
+        );
+    }
+
+    #[test]
+    fn footnotes_in_reference_order(){
+        let cx = TestContext::default();
+        let definition = |label: &'static str, text: &'static str| [
+            vec![Event::Start(Tag::FootnoteDefinition(label.into()))],
+            paragraph(vec![Event::Text(text.into())]),
+            vec![Event::End(TagEnd::FootnoteDefinition)],
+        ].concat();
+        let events = [
+            definition("a", "A"),
+            paragraph(vec![
+                Event::FootnoteReference("b".into()),
+                Event::FootnoteReference("a".into()),
+                Event::FootnoteReference("b".into()),
+            ]),
+            definition("unused", "U"),
+            definition("b", "B"),
+        ].concat();
+        let html = render(cx, events);
+        assert!(html.starts_with(
+            "<Paragraph>\
+            <Sup><a href=\"#fn-b\">1</a></Sup>\
+            <Sup><a href=\"#fn-a\">2</a></Sup>\
+            <Sup><a href=\"#fn-b\">1</a></Sup>\
+            </Paragraph>"
+        ), "{html}");
+        let b = html.find("<Span>B</Span>").unwrap();
+        let a = html.find("<Span>A</Span>").unwrap();
+        let unused = html.find("<Span>U</Span>").unwrap();
+        assert!(b < a && a < unused);
+        assert!(html.contains("<Span>↩</Span><Sup>1</Sup></a><a href=\"#fnref-b-2\" aria-label=\"Back to reference 1 (2)\"><Span>↩</Span><Sup>2</Sup>"));
+    }
+
+    #[cfg(feature="maths")]
+    #[test]
+    fn display_math_is_a_block(){
+        let cx = TestContext::default();
+        let display = render_maths(cx, "x^2", &MathMode::Display, 0..0, None).ok().unwrap();
+        let inline = render_maths(cx, "x^2", &MathMode::Inline, 0..0, None).ok().unwrap();
+        assert!(display.starts_with("<Div>"));
+        assert!(inline.starts_with("<Span>"));
+    }
+
+    #[cfg(any(feature="maths", feature="mathml"))]
+    #[test]
+    fn invalid_math_is_shown_as_source(){
+        let backends = [
+            #[cfg(feature="maths")]
+            MathBackend::Katex,
+            #[cfg(feature="mathml")]
+            MathBackend::MathMl,
+        ];
+        for math_backend in backends {
+            let cx = TestContext(MarkdownProps {
+                math_backend,
+                ..Default::default()
+            });
+            for tex in ["\\frac{1}{2", "\\unknowncommand x"] {
+                let html = render_maths(cx, tex, &MathMode::Inline, 0..0, None).ok().unwrap();
+                assert_eq!(html, format!("<Span>{tex}</Span>"));
+            }
+        }
+    }
+
+    #[test]
+    fn strikethrough_as_del(){
+        let strikethrough = vec![
+            Event::Start(Tag::Strikethrough),
+            Event::Text("old".into()),
+            Event::End(TagEnd::Strikethrough),
+        ];
+        let cx = TestContext::default();
+        assert_eq!(render(cx, strikethrough.clone()), "<StrikeThrough><Span>old</Span></StrikeThrough>");
+        let cx = TestContext(MarkdownProps {
+            strikethrough_as_del: true,
+            ..Default::default()
+        });
+        assert_eq!(render(cx, strikethrough), "<Del><Span>old</Span></Del>");
+    }
+
+    #[test]
+    fn heading_anchor_links(){
+        let cx = TestContext(MarkdownProps {
+            heading_anchor_links: true,
+            ..Default::default()
+        });
+        let events = vec![
+            Event::Start(Tag::Heading {
+                level: pulldown_cmark_wikilink::HeadingLevel::H2,
+                id: None,
+                classes: vec![],
+                attrs: vec![],
+            }),
+            Event::Text("Some title".into()),
+            Event::End(TagEnd::Heading(pulldown_cmark_wikilink::HeadingLevel::H2)),
+        ];
+        assert_eq!(
+            render(cx, events),
+            "<Heading(2)><Span>Some title</Span><a href=\"#some-title\" aria-label=\"Link to Some title\"><Span>#</Span></a></Heading(2)>"
+        );
+    }
+
+    #[test]
+    fn heading_offset(){
+        let cx = TestContext(MarkdownProps {
+            heading_offset: 2,
+            ..Default::default()
+        });
+        let heading = |level| vec![
+            Event::Start(Tag::Heading { level, id: None, classes: vec![], attrs: vec![] }),
+            Event::Text("a".into()),
+            Event::End(TagEnd::Heading(level)),
+        ];
+        assert_eq!(render(cx, heading(pulldown_cmark_wikilink::HeadingLevel::H1)), "<Heading(3)><Span>a</Span></Heading(3)>");
+        assert_eq!(render(cx, heading(pulldown_cmark_wikilink::HeadingLevel::H5)), "<Heading(6)><Span>a</Span></Heading(6)>");
+    }
+
+    #[test]
+    fn truncation_keeps_whole_blocks(){
+        let events = [
+            paragraph(vec![Event::Text("a".into())]),
+            paragraph(vec![Event::Text("b".into())]),
+            vec![Event::Rule],
+        ].concat();
+        let mut events: Vec<_> = events.into_iter().map(|e| (e, 0..0)).collect();
+        assert!(!crate::truncate_events(&mut events, 7));
+        assert!(crate::truncate_events(&mut events, 5));
+        assert_eq!(events.len(), 3);
+        assert!(crate::truncate_events(&mut events, 2));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn tight_single_paragraph(){
+        let cx = TestContext(MarkdownProps::default());
+        let single: Vec<_> = paragraph(vec![Event::Text("a".into())])
+            .into_iter().map(|e| (e, 0..0)).collect();
+
+        let mut events = single.clone();
+        crate::unwrap_single_paragraph(&mut events);
+        assert_eq!(render(cx, events.into_iter().map(|(e, _)| e).collect()), "<Span>a</Span>");
+
+        let mut events = [single.clone(), single].concat();
+        crate::unwrap_single_paragraph(&mut events);
+        assert_eq!(events.len(), 6);
+    }
+}
@@ -0,0 +1,269 @@
+/// the html tags that are kept by [`sanitize_html`]
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "b", "blockquote", "br", "caption", "code", "dd", "del",
+    "details", "div", "dl", "dt", "em", "figcaption", "figure",
+    "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd",
+    "li", "mark", "ol", "p", "pre", "q", "s", "small", "span", "strong",
+    "sub", "summary", "sup", "table", "tbody", "td", "tfoot", "th",
+    "thead", "tr", "u", "ul",
+];
+
+/// the tags that are removed together with their content
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "iframe", "noscript", "textarea", "title", "template", "xmp",
+];
+
+/// the attributes that are kept on every allowed tag
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "class", "id", "title", "lang", "dir",
+];
+
+/// the attributes that are kept on some tags only
+const ALLOWED_TAG_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("img", "src"),
+    ("img", "alt"),
+    ("img", "width"),
+    ("img", "height"),
+    ("td", "colspan"),
+    ("td", "rowspan"),
+    ("th", "colspan"),
+    ("th", "rowspan"),
+    ("ol", "start"),
+    ("details", "open"),
+];
+
+/// the attributes containing an url
+const URL_ATTRIBUTES: &[&str] = &["href", "src"];
+
+/// the url schemes that can be used in links and images
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// returns true if `url` is relative, or uses a safe scheme
+/// (not `javascript:`, `data:`, ...)
+pub(crate) fn is_safe_url(url: &str) -> bool {
+    // browsers ignore whitespace and control characters in the scheme
+    let url: String = url.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let scheme_end = url.find(['/', '?', '#']).unwrap_or(url.len());
+    let prefix = &url[..scheme_end];
+    match prefix.find(':') {
+        Some(i) => SAFE_SCHEMES.contains(&prefix[..i].to_ascii_lowercase().as_str()),
+        // an encoded `:` could hide a scheme
+        None => !prefix.contains('&'),
+    }
+}
+
+fn is_allowed_attribute(tag: &str, name: &str) -> bool {
+    ALLOWED_ATTRIBUTES.contains(&name)
+        || ALLOWED_TAG_ATTRIBUTES.contains(&(tag, name))
+}
+
+/// an html tag, as found in the source
+struct Tag<'s> {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, Option<&'s str>)>,
+    /// the length of the tag in the source
+    len: usize,
+}
+
+/// parses the tag at the start of `s`, which must start with `<`.
+/// Returns None if it is not a valid tag
+fn parse_tag(s: &str) -> Option<Tag<'_>> {
+    let mut i = 1;
+    let closing = s[i..].starts_with('/');
+    if closing {
+        i += 1;
+    }
+
+    let name_len = s[i..]
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(s.len() - i);
+    if name_len == 0 || !s[i..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None
+    }
+    let name = s[i..i+name_len].to_ascii_lowercase();
+    i += name_len;
+
+    let mut attributes = Vec::new();
+    loop {
+        i += s[i..].find(|c: char| !c.is_whitespace() && c != '/')?;
+        if s[i..].starts_with('>') {
+            return Some(Tag { name, closing, attributes, len: i + 1 })
+        }
+
+        let attr_len = s[i..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))?;
+        let attr_name = s[i..i+attr_len].to_ascii_lowercase();
+        i += attr_len;
+        i += s[i..].find(|c: char| !c.is_whitespace())?;
+
+        let value = if s[i..].starts_with('=') {
+            i += 1;
+            i += s[i..].find(|c: char| !c.is_whitespace())?;
+            let value = match s[i..].chars().next()? {
+                quote @ ('"' | '\'') => {
+                    let len = s[i+1..].find(quote)?;
+                    let value = &s[i+1..i+1+len];
+                    i += len + 2;
+                    value
+                },
+                _ => {
+                    let len = s[i..]
+                        .find(|c: char| c.is_whitespace() || c == '>')?;
+                    let value = &s[i..i+len];
+                    i += len;
+                    value
+                }
+            };
+            Some(value)
+        }
+        else {
+            None
+        };
+        attributes.push((attr_name, value));
+    }
+}
+
+/// writes an allowed tag to `out`, without its unsafe attributes
+fn write_tag(out: &mut String, tag: &Tag) {
+    if tag.closing {
+        out.push_str(&format!("</{}>", tag.name));
+        return
+    }
+    out.push('<');
+    out.push_str(&tag.name);
+    for (name, value) in &tag.attributes {
+        if name.starts_with("on") || !is_allowed_attribute(&tag.name, name) {
+            continue
+        }
+        match value {
+            Some(value) if URL_ATTRIBUTES.contains(&name.as_str()) && !is_safe_url(value) => (),
+            Some(value) => {
+                let value = value
+                    .replace('"', "&quot;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                out.push_str(&format!(" {name}=\"{value}\""));
+            },
+            None => out.push_str(&format!(" {name}")),
+        }
+    }
+    out.push('>');
+}
+
+/// removes the comments of `html`, like `<!-- note -->`,
+/// and the end of `html` after an unterminated comment
+pub(crate) fn strip_html_comments(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<!--") {
+        out.push_str(&rest[..start]);
+        rest = rest[start..].find("-->").map(|end| &rest[start+end+3..]).unwrap_or("");
+    }
+    out.push_str(rest);
+    out
+}
+
+/// removes the unsafe parts of `html`: the tags that are not
+/// in an allowlist (`<script>`, `<iframe>`, ...), the event handlers
+/// (`onclick=...`), and the urls with an unsafe scheme (`javascript:...`).
+/// The text is kept, and stray `<` are escaped
+pub(crate) fn sanitize_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|end| &rest[end+3..]).unwrap_or("");
+            continue
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map(|end| &rest[end+1..]).unwrap_or("");
+            continue
+        }
+
+        match parse_tag(rest) {
+            Some(tag) if !tag.closing && DROPPED_TAGS.contains(&tag.name.as_str()) => {
+                let closing = format!("</{}", tag.name);
+                rest = &rest[tag.len..];
+                rest = match rest.to_ascii_lowercase().find(&closing) {
+                    Some(end) => rest[end..].find('>').map(|i| &rest[end+i+1..]).unwrap_or(""),
+                    None => "",
+                };
+            },
+            Some(tag) => {
+                if ALLOWED_TAGS.contains(&tag.name.as_str()) {
+                    write_tag(&mut out, &tag);
+                }
+                rest = &rest[tag.len..];
+            },
+            None => {
+                out.push_str("&lt;");
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unsafe_urls(){
+        assert!(is_safe_url("https://example.com"));
+        assert!(is_safe_url("/docs/page?a=1&b=2#top"));
+        assert!(is_safe_url("mailto:someone@example.com"));
+        assert!(!is_safe_url("javascript:alert(1)"));
+        assert!(!is_safe_url(" JavaScript:alert(1)"));
+        assert!(!is_safe_url("java\tscript:alert(1)"));
+        assert!(!is_safe_url("javascript&#58;alert(1)"));
+        assert!(!is_safe_url("data:text/html,<script>alert(1)</script>"));
+    }
+
+    #[test]
+    fn keeps_safe_html(){
+        let html = r#"<p class="note">some <b>bold</b> text &amp; a <a href="/page">link</a></p>"#;
+        assert_eq!(sanitize_html(html), html);
+    }
+
+    #[test]
+    fn removes_scripts(){
+        assert_eq!(sanitize_html("a<script>alert('<b>')</script>b"), "ab");
+        assert_eq!(sanitize_html("a<SCRIPT src=x></SCRIPT >b"), "ab");
+        assert_eq!(sanitize_html("a<!-- <script> -->b"), "ab");
+    }
+
+    #[test]
+    fn removes_comments(){
+        assert_eq!(strip_html_comments("<!-- secret -->"), "");
+        assert_eq!(strip_html_comments("<p>a<!-- b --></p><!--[if IE]>c<![endif]-->"), "<p>a</p>");
+        assert_eq!(strip_html_comments("a<!-- unterminated"), "a");
+    }
+
+    #[test]
+    fn removes_unsafe_attributes(){
+        assert_eq!(
+            sanitize_html(r#"<img src="x.png" onerror="alert(1)" alt='a "b"'>"#),
+            r#"<img src="x.png" alt="a &quot;b&quot;">"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="javascript:alert(1)" style="x">link</a>"#),
+            "<a>link</a>"
+        );
+    }
+
+    #[test]
+    fn escapes_invalid_tags(){
+        assert_eq!(sanitize_html("1 < 2 <b"), "1 &lt; 2 &lt;b");
+        assert_eq!(sanitize_html("<svg onload=alert(1)>x</svg>"), "x");
+    }
+}
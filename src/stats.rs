@@ -0,0 +1,92 @@
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd};
+
+use crate::MarkdownProps;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// the settings of [`document_stats`]
+pub struct StatsOptions {
+    /// the reading speed used to estimate the reading time
+    pub words_per_minute: usize,
+    /// count the content of the code blocks
+    pub include_code_blocks: bool,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        Self {
+            words_per_minute: 200,
+            include_code_blocks: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// statistics about the text of a document
+pub struct DocumentStats {
+    /// the number of words
+    pub words: usize,
+    /// the number of characters of the text, including spaces
+    pub characters: usize,
+    /// the estimated reading time, rounded up to the next minute
+    pub reading_minutes: usize,
+}
+
+/// computes the statistics of the text and code of `events`
+fn stats<'e, 'a: 'e>(events: impl IntoIterator<Item=&'e Event<'a>>, options: StatsOptions)
+    -> DocumentStats {
+    let mut result = DocumentStats::default();
+    let mut in_code_block = false;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Text(s) | Event::Code(s) if options.include_code_blocks || !in_code_block => {
+                result.words += s.split_whitespace().count();
+                result.characters += s.chars().count();
+            },
+            _ => ()
+        }
+    }
+    result.reading_minutes = result.words.div_ceil(options.words_per_minute.max(1));
+    result
+}
+
+/// `document_stats(source, props, options)` counts the words and characters
+/// of the text of `source`, and estimates its reading time.
+/// The document is parsed like [`crate::render_markdown`] does with the same `props`
+pub fn document_stats(source: &str, props: &MarkdownProps, options: StatsOptions) -> DocumentStats {
+    let events = crate::parse(source, props);
+    stats(events.iter().map(|(e, _)| e), options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pulldown_cmark_wikilink::CodeBlockKind;
+
+    #[test]
+    fn words_and_reading_time(){
+        let events = vec![
+            Event::Text("one two three".into()),
+            Event::Code("four".into()),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)),
+            Event::Text("let five = 5;".into()),
+            Event::End(TagEnd::CodeBlock),
+        ];
+        let options = StatsOptions {
+            words_per_minute: 3,
+            ..Default::default()
+        };
+        assert_eq!(stats(&events, options), DocumentStats {
+            words: 4,
+            characters: 17,
+            reading_minutes: 2,
+        });
+
+        let options = StatsOptions {
+            include_code_blocks: true,
+            ..options
+        };
+        assert_eq!(stats(&events, options).words, 8);
+    }
+}
@@ -0,0 +1,244 @@
+use core::ops::Range;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pulldown_cmark_wikilink::Event;
+
+use crate::render::DocumentState;
+use crate::Context;
+
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// the state of a document written progressively, like the answer
+/// of a chat bot, rendered with [`StreamingRenderer::render`]
+/// each time some text is appended to it.
+///
+/// The top-level elements that can't change anymore are committed:
+/// they are rendered once, and only the end of the document after them
+/// is rendered again.
+/// The whole document is parsed and transformed at each render,
+/// but the frontmatter is set and the diagnostics of an element are reported
+/// only once, when they are committed.
+/// The link reference definitions and the abbreviations don't apply
+/// to the elements committed before they were written
+pub struct StreamingRenderer<V> {
+    /// the source of the committed elements
+    committed: String,
+    /// the state of the document after the committed elements
+    state: Option<DocumentState<V>>,
+}
+
+impl<V> Default for StreamingRenderer<V> {
+    fn default() -> Self {
+        Self {
+            committed: String::new(),
+            state: None,
+        }
+    }
+}
+
+/// the result of [`StreamingRenderer::render`]
+pub struct StreamingUpdate<V> {
+    /// the top-level elements committed by this render, in order.
+    /// They come after the elements committed by the previous renders,
+    /// and are never rendered again
+    pub committed: Vec<V>,
+    /// the end of the document, still being written,
+    /// like an unterminated code block.
+    /// It replaces the pending view of the previous render
+    pub pending: V,
+}
+
+/// a top-level element rendered by [`StreamingRenderer::render`]
+struct Block<V> {
+    view: V,
+    /// the position of the element in the source
+    range: Range<usize>,
+    /// the state of the document after this element
+    state: DocumentState<V>,
+}
+
+impl<V> StreamingRenderer<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// forgets the committed elements, to render another document
+    pub fn clear(&mut self) {
+        *self = Self::default()
+    }
+}
+
+impl<V: Clone> StreamingRenderer<V> {
+    /// renders the elements of `source` after the committed ones.
+    /// `source` is the whole document written so far: the text is only
+    /// appended to it between two calls. Otherwise, it is rendered from scratch.
+    ///
+    /// An element is committed once the next one started on a complete line,
+    /// so the end of the document stays pending until more text
+    /// is appended after it
+    pub fn render<'a, 'callback, F>(&mut self, cx: F, source: &'a str) -> StreamingUpdate<V>
+    where F: Context<'a, 'callback, View=V>,
+          'callback: 'a,
+    {
+        if !source.starts_with(&self.committed) {
+            self.clear();
+        }
+        let stream = crate::parse(source, &cx.props());
+        let diagnostics = match cx.has_diagnostic_reporter() {
+            true => crate::diagnostics::diagnose(cx, source, &stream),
+            false => Vec::new(),
+        };
+        let prepared = crate::transform(cx, source, stream);
+        if prepared.has_math {
+            crate::mount_math_stylesheet(cx);
+        }
+
+        // the committed elements are skipped, unless the document
+        // doesn't start a top-level element where they end anymore
+        let mut start = top_level_start(&prepared.stream, self.committed.len());
+        if start.is_none() {
+            self.clear();
+            start = Some(0);
+        }
+        let offset = self.committed.len();
+
+        // the events consumed by the renderer for the current element
+        let consumed: Rc<RefCell<Events<'a>>> = Default::default();
+        let events = prepared.stream.into_iter()
+            .skip(start.unwrap_or(0))
+            .inspect(|event| consumed.borrow_mut().push(event.clone()));
+        let mut renderer = crate::render_events(cx, source, events);
+        if let Some(state) = &self.state {
+            renderer = renderer.with_state(state.clone());
+        }
+        let mut renderer = renderer.with_abbreviations(prepared.abbreviations);
+
+        let mut blocks = Vec::new();
+        let mut footnotes = None;
+        while let Some(view) = renderer.next() {
+            let events = std::mem::take(&mut *consumed.borrow_mut());
+            let (Some((_, first)), Some((_, last))) = (events.first(), events.last()) else {
+                // the footnote section comes last, without events
+                footnotes = Some(view);
+                continue
+            };
+            blocks.push(Block {
+                view,
+                range: first.start..last.end,
+                state: renderer.state(),
+            });
+        }
+
+        // the last element can be continued by the next text,
+        // like the one before it if the last one started on an incomplete line
+        let complete = source.rfind('\n').map_or(0, |i| i + 1);
+        let pending_start = match &blocks[..] {
+            [.., last] if last.range.start < complete => blocks.len() - 1,
+            _ => blocks.len().saturating_sub(2),
+        };
+        let pending_blocks = blocks.split_off(pending_start);
+
+        if let Some(last) = blocks.last() {
+            if offset == 0 {
+                if let (Some((format, raw)), _) = crate::frontmatter(source, &cx.props()) {
+                    cx.set_frontmatter_with_format(format, raw.to_string());
+                }
+            }
+            let committed = offset..last.range.end;
+            for diagnostic in diagnostics.into_iter().filter(|d| committed.contains(&d.range.start)) {
+                cx.report_diagnostic(diagnostic)
+            }
+            self.committed = source[..last.range.end].to_string();
+            self.state = Some(last.state.clone());
+        }
+        let committed = blocks.into_iter().map(|block| block.view).collect();
+        let pending: Vec<_> = pending_blocks.into_iter().map(|block| block.view).chain(footnotes).collect();
+
+        StreamingUpdate {
+            committed,
+            pending: cx.el_fragment(pending),
+        }
+    }
+}
+
+/// the index of the first event of `events` at or after `offset`
+/// in the source, if it starts a top-level element
+fn top_level_start(events: &Events, offset: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, (event, range)) in events.iter().enumerate() {
+        if range.start >= offset {
+            return (depth == 0).then_some(i)
+        }
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => ()
+        }
+    }
+    Some(events.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature="html")]
+    #[test]
+    fn streaming(){
+        use crate::MarkdownProps;
+
+        crate::html::with_html_context(MarkdownProps::default(), |cx| {
+            let mut renderer = StreamingRenderer::new();
+            // the unterminated code block stays pending
+            let update = renderer.render(cx, "a\n\n```\nco");
+            assert_eq!(update.committed, vec!["<p><span>a</span></p>".to_string()]);
+            assert!(update.pending.contains("co"));
+            // the code block is followed by an incomplete line
+            let update = renderer.render(cx, "a\n\n```\ncode\n```\n\nb");
+            assert!(update.committed.is_empty());
+            assert!(update.pending.contains("code"));
+            let update = renderer.render(cx, "a\n\n```\ncode\n```\n\nb\n\nc");
+            assert_eq!(update.committed.len(), 1);
+            assert_eq!(update.pending, "<p><span>b</span></p><p><span>c</span></p>");
+        })
+    }
+
+    #[cfg(feature="html")]
+    #[test]
+    fn streaming_tight(){
+        use crate::MarkdownProps;
+
+        let props = MarkdownProps {
+            tight: true,
+            ..Default::default()
+        };
+        crate::html::with_html_context(props, |cx| {
+            let mut renderer = StreamingRenderer::new();
+            let update = renderer.render(cx, "a\n\nb\n");
+            assert_eq!(update.committed, vec!["<p><span>a</span></p>".to_string()]);
+            // the pending paragraph is not the only paragraph of the document
+            let update = renderer.render(cx, "a\n\nb\nc");
+            assert!(update.pending.starts_with("<p>"));
+        })
+    }
+
+    #[test]
+    fn top_level_starts(){
+        use crate::{Tag, TagEnd};
+
+        let events = vec![
+            (Event::Start(Tag::Paragraph), 0..2),
+            (Event::Text("a".into()), 0..1),
+            (Event::End(TagEnd::Paragraph), 0..2),
+            (Event::Start(Tag::BlockQuote), 3..6),
+            (Event::Text("b".into()), 5..6),
+            (Event::End(TagEnd::BlockQuote), 3..6),
+        ];
+        assert_eq!(top_level_start(&events, 0), Some(0));
+        assert_eq!(top_level_start(&events, 3), Some(3));
+        assert_eq!(top_level_start(&events, 6), Some(6));
+        // inside the quote
+        assert_eq!(top_level_start(&events, 5), None);
+    }
+}
@@ -0,0 +1,186 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd};
+
+use crate::MarkdownProps;
+use crate::utils::{events_text, slugify, Slugs};
+
+#[derive(Debug, Clone, PartialEq)]
+/// a heading of the table of contents
+pub struct TocEntry {
+    /// the level of the heading, from 1 to 6
+    pub level: u8,
+    /// the text of the heading, without formatting
+    pub text: String,
+    /// the id given to the heading by [`crate::render_markdown`]
+    pub slug: String,
+    /// the position of the heading in the source
+    pub range: Range<usize>,
+}
+
+/// `extract_toc(source, props)` lists the headings of `source`.
+/// The entries are flat and in document order: nesting can be
+/// recovered from their `level`.
+/// The slugs are the ids [`crate::render_markdown`] gives to the headings
+/// when it is called with the same `props`: the headings are read
+/// after the same transformations of the events, like the emoji shortcodes.
+/// Only the references to undefined links are kept as text,
+/// as they are for a [`crate::Context`] without custom broken links.
+pub fn extract_toc(source: &str, props: &MarkdownProps) -> Vec<TocEntry> {
+    let slugify = props.slugify.unwrap_or(slugify);
+    let mut slugs = Slugs::default();
+    let mut toc = Vec::new();
+
+    let stream = crate::parse(source, props);
+    let mut events = crate::transform_events(props, source, stream, false).stream.into_iter();
+    while let Some((event, range)) = events.next() {
+        if let Event::Start(Tag::Heading{level, id, ..}) = event {
+            let inner: Vec<_> = events.by_ref()
+                .map(|(e, _)| e)
+                .take_while(|e| !matches!(e, Event::End(TagEnd::Heading(_))))
+                .collect();
+            let text = events_text(&inner);
+            let slug = match id {
+                Some(id) => id.to_string(),
+                None => slugify(&text)
+            };
+            toc.push(TocEntry {
+                level: level as u8,
+                slug: slugs.unique(slug),
+                text,
+                range,
+            });
+        }
+    }
+    toc
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// a section of the outline of a document, returned by [`document_outline`]
+pub struct OutlineNode {
+    /// the level of the heading, from 1 to 6,
+    /// or 0 for the content before the first heading
+    pub level: u8,
+    /// the text of the heading, without formatting
+    pub text: String,
+    /// the id given to the heading by [`crate::render_markdown`]
+    pub slug: String,
+    /// the position of the heading in the source,
+    /// empty for the content before the first heading
+    pub heading_range: Range<usize>,
+    /// the position of the whole section in the source, from its heading
+    /// to the next heading of the same or a higher level
+    pub section_range: Range<usize>,
+    /// the sections of a lower level inside of this one
+    pub children: Vec<OutlineNode>,
+}
+
+/// `document_outline(source, props)` lists the sections of `source`,
+/// nested by the level of their heading, like [`extract_toc`] lists
+/// the headings. The content before the first heading, if any,
+/// is the first section, with a level of 0 and without heading
+pub fn document_outline(source: &str, props: &MarkdownProps) -> Vec<OutlineNode> {
+    let toc = extract_toc(source, props);
+    let (_, body) = crate::frontmatter(source, props);
+    let start = source.len() - body.len();
+    let end = toc.first().map_or(source.len(), |entry| entry.range.start);
+
+    let mut outline = Vec::new();
+    if !source[start..end].trim().is_empty() {
+        outline.push(OutlineNode {
+            level: 0,
+            text: String::new(),
+            slug: String::new(),
+            heading_range: start..start,
+            section_range: start..end,
+            children: Vec::new(),
+        });
+    }
+    outline.extend(nest(&toc, source.len()));
+    outline
+}
+
+/// nests the headings of `entries`, whose sections end at `end`
+fn nest(entries: &[TocEntry], end: usize) -> Vec<OutlineNode> {
+    let mut nodes = Vec::new();
+    let mut i = 0;
+    while let Some(entry) = entries.get(i) {
+        // the next heading of the same or a higher level
+        let next = entries[i+1..].iter()
+            .position(|e| e.level <= entry.level)
+            .map_or(entries.len(), |j| i + 1 + j);
+        let section_end = entries.get(next).map_or(end, |e| e.range.start);
+        nodes.push(OutlineNode {
+            level: entry.level,
+            text: entry.text.clone(),
+            slug: entry.slug.clone(),
+            heading_range: entry.range.clone(),
+            section_range: entry.range.start..section_end,
+            children: nest(&entries[i+1..next], section_end),
+        });
+        i = next;
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_sections(){
+        let entry = |level, start: usize| TocEntry {
+            level,
+            text: String::new(),
+            slug: String::new(),
+            range: start..start + 5,
+        };
+        // # a, ### b, ## c, # d
+        let outline = nest(&[entry(1, 0), entry(3, 10), entry(2, 20), entry(1, 30)], 40);
+        let sections: Vec<_> = outline.iter()
+            .map(|n| (n.level, n.section_range.clone(), n.children.len()))
+            .collect();
+        assert_eq!(sections, vec![(1, 0..30, 2), (1, 30..40, 0)]);
+        let children: Vec<_> = outline[0].children.iter()
+            .map(|n| (n.level, n.heading_range.clone(), n.section_range.clone()))
+            .collect();
+        assert_eq!(children, vec![(3, 10..15, 10..20), (2, 20..25, 20..30)]);
+    }
+
+    #[test]
+    fn toc_levels_and_slugs(){
+        let source = "# Intro\n\ntext\n\n## Intro\n\n### `code` *title*\n";
+        let toc = extract_toc(source, &MarkdownProps::default());
+        let entries: Vec<_> = toc.iter()
+            .map(|e| (e.level, e.text.as_str(), e.slug.as_str()))
+            .collect();
+        assert_eq!(entries, vec![
+            (1, "Intro", "intro"),
+            (2, "Intro", "intro-1"),
+            (3, "code title", "code-title"),
+        ]);
+        assert!(source[toc[0].range.clone()].starts_with("# Intro"));
+    }
+
+    #[cfg(all(feature="html", feature="emoji"))]
+    #[test]
+    fn slugs_are_the_rendered_ids(){
+        let props = MarkdownProps {
+            emoji_shortcodes: true,
+            inline_footnotes: true,
+            ..Default::default()
+        };
+        let source = "# hi :wave:\n\n## title^[a note]\n\n## title\n";
+        let html = crate::render_html(source, props);
+        let ids: Vec<&str> = html.match_indices("<h")
+            .filter_map(|(i, _)| {
+                let tag = &html[i..i + html[i..].find('>')?];
+                let start = tag.find(" id=\"")? + 5;
+                Some(&tag[start..start + tag[start..].find('"')?])
+            })
+            .collect();
+        let slugs: Vec<String> = extract_toc(source, &props).into_iter().map(|e| e.slug).collect();
+        assert_eq!(slugs, ids);
+        assert_eq!(slugs, vec!["hi-", "title", "title-1"]);
+    }
+}
@@ -1,4 +1,7 @@
-use pulldown_cmark_wikilink::{Tag, TagEnd};
+use core::ops::Range;
+use std::collections::{BTreeMap, BTreeSet};
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd};
 
 pub fn as_closing_tag(t: &Tag) -> TagEnd {
     match t {
@@ -23,3 +26,743 @@ pub fn as_closing_tag(t: &Tag) -> TagEnd {
     }
 }
 
+
+/// the start of each line of a source,
+/// to convert the positions in the source to line numbers
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    /// the line containing the byte `offset`, starting at 1
+    pub fn line(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+}
+
+/// the prefix and the id-safe name of the ids of the footnote `label`.
+/// The inline footnotes, whose labels start with `^`, get their own prefix,
+/// so their ids can't be the ones of a footnote written like `[^label]`
+fn footnote_id_parts(label: &str) -> (&'static str, String) {
+    match label.strip_prefix('^') {
+        Some(name) => ("inline-", name.to_string()),
+        None => ("", label.split_whitespace().collect::<Vec<_>>().join("-")),
+    }
+}
+
+/// the html id of the definition of the footnote `label`
+pub fn footnote_id(label: &str) -> String {
+    let (prefix, name) = footnote_id_parts(label);
+    format!("{prefix}fn-{name}")
+}
+
+/// the html id of the `occurrence`-th reference to the footnote `label`
+pub fn footnote_reference_id(label: &str, occurrence: usize) -> String {
+    let (prefix, name) = footnote_id_parts(label);
+    match occurrence {
+        1 => format!("{prefix}fnref-{name}"),
+        n => format!("{prefix}fnref-{name}-{n}")
+    }
+}
+
+/// returns true if `url` is an absolute `http` or `https` url,
+/// on a different host than the `base` url of the document
+pub fn is_external_url(url: &str, base: Option<&str>) -> bool {
+    let lowercase = url.trim_start().to_ascii_lowercase();
+    if !(lowercase.starts_with("http://") || lowercase.starts_with("https://")) {
+        return false
+    }
+    match (url_host(url), base.and_then(url_host)) {
+        (Some(host), Some(base_host)) => !host.eq_ignore_ascii_case(base_host),
+        _ => true
+    }
+}
+
+/// the host of an absolute url, like `example.com` in `https://example.com/a`
+fn url_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.trim_start().split_once("://")?;
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// returns true if `url` starts with a scheme, like `https:` or `mailto:`
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        None => false
+    }
+}
+
+/// removes the `.` and `..` segments of a path
+fn remove_dot_segments(path: &str) -> String {
+    let (absolute, path) = match path.strip_prefix('/') {
+        Some(p) => (true, p),
+        None => (false, path)
+    };
+
+    let segments: Vec<&str> = path.split('/').collect();
+    let n = segments.len();
+    let mut output: Vec<&str> = Vec::new();
+    for (i, segment) in segments.into_iter().enumerate() {
+        let last = i + 1 == n;
+        match segment {
+            "." => (),
+            ".." => {
+                output.pop();
+            },
+            s => output.push(s),
+        }
+        // a trailing `.` or `..` refers to a directory
+        if last && (segment == "." || segment == "..") {
+            output.push("");
+        }
+    }
+
+    let path = output.join("/");
+    if absolute {format!("/{path}")} else {path}
+}
+
+/// the hosts of the media providers recognized by [`embed_provider`],
+/// with the start of the path of their media
+const EMBED_PROVIDERS: &[(&str, &str, &str)] = &[
+    ("youtube", "youtube.com", "/watch?"),
+    ("youtube", "youtube.com", "/shorts/"),
+    ("youtube", "youtube.com", "/embed/"),
+    ("youtube", "youtu.be", "/"),
+    ("vimeo", "vimeo.com", "/"),
+    ("vimeo", "player.vimeo.com", "/video/"),
+];
+
+/// `embed_provider(url)` returns the name of the provider of the media
+/// at `url`, like `youtube` for `https://www.youtube.com/watch?v=id`
+/// or `vimeo` for `https://vimeo.com/123`
+pub fn embed_provider(url: &str) -> Option<&'static str> {
+    let host = url_host(url)?.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").or_else(|| host.strip_prefix("m.")).unwrap_or(&host);
+    let path = &url[url.find("://")? + 3..];
+    let path = &path[path.find(['/', '?', '#']).unwrap_or(path.len())..];
+    EMBED_PROVIDERS.iter()
+        .find(|(_, h, start)| *h == host && path.starts_with(start) && path.len() > start.len())
+        .map(|(provider, _, _)| *provider)
+}
+
+/// the query parameters removed by [`strip_tracking_params`],
+/// besides the ones starting with `utm_`
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "mc_eid", "igshid", "yclid"];
+
+/// `strip_tracking_params(url)` removes the tracking parameters
+/// from the query of `url`, like `utm_source` or `fbclid`.
+/// It can be used as the [`crate::MarkdownProps::url_rewriter`].
+/// The urls without tracking parameters, like most `mailto:` urls
+/// or fragments like `#anchor`, are returned unchanged
+pub fn strip_tracking_params(url: &str) -> String {
+    let Some(query_start) = url.find('?').filter(|&i| !url[..i].contains('#')) else {
+        return url.to_string()
+    };
+    let query_end = url[query_start..].find('#').map_or(url.len(), |i| query_start + i);
+    let params: Vec<_> = url[query_start+1..query_end].split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or_default();
+            !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name)
+        })
+        .collect();
+    let query = match params.is_empty() {
+        true => String::new(),
+        false => format!("?{}", params.join("&")),
+    };
+    format!("{}{query}{}", &url[..query_start], &url[query_end..])
+}
+
+/// `resolve_url(base, url)` resolves the relative url `url`
+/// against the url of the document `base`, which can be an absolute url
+/// or just a path.
+/// Absolute and protocol-relative urls, urls with a scheme like `mailto:`
+/// and fragments like `#anchor` are returned unchanged
+pub fn resolve_url(base: &str, url: &str) -> String {
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || has_scheme(url) {
+        return url.to_string()
+    }
+
+    // the base, without its query and fragment
+    let base = &base[..base.find(['?', '#']).unwrap_or(base.len())];
+
+    // split the base between `scheme://host` and the path
+    let (origin, base_path) = match base.find("://") {
+        Some(i) => {
+            let path_start = base[i+3..].find('/').map_or(base.len(), |j| i + 3 + j);
+            base.split_at(path_start)
+        },
+        None => ("", base)
+    };
+
+    // split the url between its path and its query or fragment
+    let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+
+    let path = if path.is_empty() {
+        base_path.to_string()
+    }
+    else if path.starts_with('/') {
+        remove_dot_segments(path)
+    }
+    else {
+        let directory = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+        let directory = if directory.is_empty() && !origin.is_empty() {"/"} else {directory};
+        remove_dot_segments(&format!("{directory}{path}"))
+    };
+
+    format!("{origin}{path}{suffix}")
+}
+
+/// `parse_image_size(url)` splits the size at the end of an image url,
+/// like `img.png =200x100`, `img.png =200x` or `img.png =x100`.
+/// Since urls can't contain spaces, the url must be written
+/// between angle brackets: `![alt](<img.png =200x100>)`.
+/// Returns None if the url doesn't end with a valid size
+pub fn parse_image_size(url: &str) -> Option<(&str, Option<u32>, Option<u32>)> {
+    let (url, size) = url.trim_end().rsplit_once(" =")?;
+    let (width, height) = size.split_once('x')?;
+    let parse = |s: &str| match s {
+        "" => Some(None),
+        s => s.parse().ok().map(Some),
+    };
+    match (parse(width)?, parse(height)?) {
+        (None, None) => None,
+        (width, height) => Some((url.trim_end(), width, height))
+    }
+}
+
+/// returns true if the text `text`, parsed at `range`, is written as is in `source`.
+/// With escapes or entities, its parts can't be located in the source
+pub fn is_verbatim(source: &str, text: &str, range: &Range<usize>) -> bool {
+    source.get(range.clone()) == Some(text)
+}
+
+/// `locate(source, text, range, part)` is the position in `source`
+/// of the bytes `part` of the text `text`, parsed at `range`.
+/// It is the whole `range` if the text is not written as is in the source
+pub fn locate(source: &str, text: &str, range: Range<usize>, part: Range<usize>) -> Range<usize> {
+    match is_verbatim(source, text, &range) {
+        true => range.start + part.start..range.start + part.end,
+        false => range,
+    }
+}
+
+/// the text content of a list of events, without any formatting
+pub fn events_text<'e, 'a: 'e>(events: impl IntoIterator<Item=&'e Event<'a>>) -> String {
+    let mut text = String::new();
+    for e in events {
+        match e {
+            Event::Text(s) | Event::Code(s) => text.push_str(s),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => ()
+        }
+    }
+    text
+}
+
+/// `slugify(text)` gives the github-style anchor of a heading:
+/// lowercase, without punctuation, and with spaces replaced by `-`
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None
+        })
+        .collect()
+}
+
+#[derive(Clone, Default)]
+/// the slugs already used in a document.
+/// Repeated slugs get a numeric suffix: `intro`, `intro-1`, `intro-2`
+pub struct Slugs(BTreeSet<String>);
+
+impl Slugs {
+    /// returns `slug`, or `slug` with a suffix if it was already used.
+    /// An empty slug is replaced by `section`
+    pub fn unique(&mut self, slug: String) -> String {
+        let slug = match slug.is_empty() {
+            true => "section".to_string(),
+            false => slug,
+        };
+        if self.0.insert(slug.clone()) {
+            return slug
+        }
+        let mut n = 1;
+        loop {
+            let candidate = format!("{slug}-{n}");
+            if self.0.insert(candidate.clone()) {
+                return candidate
+            }
+            n += 1;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// a part of a text split by `split_markers`
+pub enum MarkedText<'t, M> {
+    /// text outside of any marker
+    Plain(&'t str),
+    /// text surrounded by the marker `M`
+    Marked(M, &'t str),
+}
+
+/// `split_markers(text, markers)` splits `text` on the spans surrounded
+/// by one of the `markers`, like `^sup^` or `==mark==`.
+/// Each part is returned with its position in `text`.
+/// The marked text must be non-empty and must not start or end with a space,
+/// and doubled markers are ignored. Unmatched markers are kept as plain text
+pub fn split_markers<'t, M: Copy>(text: &'t str, markers: &[(&str, M)]) 
+    -> Vec<(Range<usize>, MarkedText<'t, M>)> {
+    let mut parts = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let found = markers.iter().find_map(|&(delimiter, m)| {
+            if !rest.starts_with(delimiter) || rest[delimiter.len()..].starts_with(delimiter) {
+                return None
+            }
+            let start = i + delimiter.len();
+            let end = start + text[start..].find(delimiter)?;
+            let inner = &text[start..end];
+            if inner.is_empty() 
+                || inner.starts_with(char::is_whitespace) 
+                || inner.ends_with(char::is_whitespace) {
+                return None
+            }
+            Some((m, start, end, end + delimiter.len()))
+        });
+
+        match found {
+            Some((m, start, end, next)) => {
+                if plain_start < i {
+                    parts.push((plain_start..i, MarkedText::Plain(&text[plain_start..i])));
+                }
+                parts.push((i..next, MarkedText::Marked(m, &text[start..end])));
+                i = next;
+                plain_start = next;
+            },
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if plain_start < text.len() {
+        parts.push((plain_start..text.len(), MarkedText::Plain(&text[plain_start..])));
+    }
+    parts
+}
+
+#[cfg(feature="emoji")]
+/// `replace_shortcodes(text)` replaces the known shortcodes of `text`,
+/// like `:smile:`, by their emoji.
+/// Returns None if `text` doesn't contain any known shortcode
+pub fn replace_shortcodes(text: &str) -> Option<String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-';
+
+    let mut result = String::new();
+    let mut replaced = false;
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start+1..];
+        let len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+        let emoji = Some(&after[..len])
+            .filter(|name| !name.is_empty() && after[len..].starts_with(':'))
+            .and_then(emojis::get_by_shortcode);
+        match emoji {
+            Some(emoji) => {
+                result.push_str(&rest[..start]);
+                result.push_str(emoji.as_str());
+                rest = &after[len+1..];
+                replaced = true;
+            },
+            None => {
+                result.push_str(&rest[..start+1]);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    replaced.then_some(result)
+}
+
+/// the kinds of github alerts, with their default title
+const ALERT_KINDS: &[(&str, &str)] = &[
+    ("note", "Note"),
+    ("tip", "Tip"),
+    ("important", "Important"),
+    ("warning", "Warning"),
+    ("caution", "Caution"),
+];
+
+/// parses the first line of a github alert, like `[!NOTE]`,
+/// or `[!WARNING] custom title`.
+/// Returns the kind of the alert in lowercase, and its title
+pub fn parse_alert_marker(line: &str) -> Option<(&'static str, String)> {
+    let rest = line.trim().strip_prefix("[!")?;
+    let (kind, title) = rest.split_once(']')?;
+    let (kind, default_title) = ALERT_KINDS.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(kind))?;
+    let title = match title.trim() {
+        "" => default_title.to_string(),
+        title => title.to_string(),
+    };
+    Some((kind, title))
+}
+
+/// parses the first line of a details block, like `::: details Some title`.
+/// Returns the title of the block, `Details` by default
+pub fn parse_details_marker(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix(":::")?.trim_start();
+    let title = rest.strip_prefix("details")?;
+    if !(title.is_empty() || title.starts_with(char::is_whitespace)) {
+        return None
+    }
+    Some(match title.trim() {
+        "" => "Details".to_string(),
+        title => title.to_string(),
+    })
+}
+
+/// parses an abbreviation definition, like `*[HTML]: HyperText Markup Language`.
+/// Returns the abbreviation and its title
+pub fn parse_abbreviation(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("*[")?;
+    let (abbreviation, title) = rest.split_once("]:")?;
+    if abbreviation.is_empty() || abbreviation.contains(['[', ']']) {
+        return None
+    }
+    Some((abbreviation, title.trim()))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// splits `text` on the whole-word occurrences of the keys of `abbreviations`,
+/// which are marked with their title. The matching is case-sensitive,
+/// and the longest abbreviation is used when several ones match.
+/// Each part is returned with its position in `text`
+pub fn split_abbreviations<'t, 'm>(text: &'t str, abbreviations: &'m BTreeMap<String, String>)
+    -> Vec<(Range<usize>, MarkedText<'t, &'m str>)> {
+    let mut sorted: Vec<_> = abbreviations.iter().collect();
+    sorted.sort_by_key(|(abbreviation, _)| std::cmp::Reverse(abbreviation.len()));
+
+    let mut parts = Vec::new();
+    let mut plain_start = 0;
+    let mut previous: Option<char> = None;
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let at_word_start = !previous.is_some_and(is_word_char);
+        let found = sorted.iter()
+            .find(|(abbreviation, _)| at_word_start
+                && rest.starts_with(abbreviation.as_str())
+                && !rest[abbreviation.len()..].starts_with(is_word_char));
+
+        match found {
+            Some((abbreviation, title)) => {
+                if plain_start < i {
+                    parts.push((plain_start..i, MarkedText::Plain(&text[plain_start..i])));
+                }
+                let end = i + abbreviation.len();
+                parts.push((i..end, MarkedText::Marked(title.as_str(), &text[i..end])));
+                previous = text[..end].chars().next_back();
+                i = end;
+                plain_start = end;
+            },
+            None => {
+                let c = rest.chars().next();
+                previous = c;
+                i += c.map_or(1, char::len_utf8);
+            }
+        }
+    }
+
+    if plain_start < text.len() {
+        parts.push((plain_start..text.len(), MarkedText::Plain(&text[plain_start..])));
+    }
+    parts
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// how a table cell is merged with its neighbours
+pub enum CellMerge {
+    /// a cell on its own
+    None,
+    /// a cell containing only `^`, merged into the cell above it
+    Up,
+    /// an empty cell, merged into the cell on its left
+    Left,
+}
+
+/// computes the `(colspan, rowspan)` of each cell of a table,
+/// given for each row if it is the head, and the merge of its cells.
+/// Merged cells have no span. The cells are not merged across the head
+/// and the body, and the cells without a neighbour to merge into,
+/// like in ragged rows, stay on their own
+pub fn cell_spans(rows: &[(bool, Vec<CellMerge>)]) -> Vec<Vec<Option<(usize, usize)>>> {
+    let mut spans: Vec<Vec<Option<(usize, usize)>>> = Vec::with_capacity(rows.len());
+    // the row and column of the cell covering each cell
+    let mut owners: Vec<Vec<(usize, usize)>> = Vec::with_capacity(rows.len());
+
+    for (r, (head, merges)) in rows.iter().enumerate() {
+        spans.push(vec![None; merges.len()]);
+        owners.push(Vec::with_capacity(merges.len()));
+        for (c, merge) in merges.iter().enumerate() {
+            let above = match r.checked_sub(1) {
+                Some(p) if rows[p].0 == *head => owners[p].get(c).copied(),
+                _ => None
+            };
+            let left = c.checked_sub(1).map(|l| owners[r][l]);
+            let owner = match (merge, above, left) {
+                // the first column of the cell above extends its rowspan,
+                // its other columns follow
+                (CellMerge::Up, Some((or, oc)), _) if oc == c => {
+                    if let Some((_, rowspan)) = &mut spans[or][oc] {
+                        *rowspan += 1
+                    }
+                    Some((or, oc))
+                },
+                (CellMerge::Up, Some(o), _) if owners[r].get(o.1) == Some(&o) => Some(o),
+                (CellMerge::Left, _, Some((or, oc))) => {
+                    let colspan = spans[or][oc].map_or(1, |(colspan, _)| colspan);
+                    match spans[or][oc].as_mut() {
+                        Some((colspan, _)) if or == r && oc + *colspan == c => {
+                            *colspan += 1;
+                            Some((or, oc))
+                        },
+                        // a column of a cell spanning from above
+                        _ if oc + colspan > c => Some((or, oc)),
+                        _ => None
+                    }
+                },
+                _ => None
+            };
+            match owner {
+                Some(owner) => owners[r].push(owner),
+                None => {
+                    owners[r].push((r, c));
+                    spans[r][c] = Some((1, 1));
+                }
+            }
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn located_parts(){
+        let source = "a *b c* d";
+        assert_eq!(locate(source, "b c", 3..6, 2..3), 5..6);
+        // the entity is decoded, so the parts can't be located
+        let source = "a &amp; b";
+        assert_eq!(locate(source, "a & b", 0..9, 4..5), 0..9);
+    }
+
+    #[test]
+    fn footnote_ids(){
+        assert_eq!(footnote_id("my note"), "fn-my-note");
+        assert_eq!(footnote_reference_id("my note", 2), "fnref-my-note-2");
+        // the inline footnotes don't share the ids of the other ones
+        assert_eq!(footnote_id("^1"), "inline-fn-1");
+        assert_eq!(footnote_reference_id("^1", 1), "inline-fnref-1");
+    }
+
+    #[test]
+    fn line_index(){
+        let lines = LineIndex::new("a\n\nbc\n");
+        assert_eq!(lines.line(0), 1);
+        assert_eq!(lines.line(1), 1);
+        assert_eq!(lines.line(2), 2);
+        assert_eq!(lines.line(4), 3);
+        assert_eq!(lines.line(7), 4);
+    }
+
+    #[test]
+    fn split_sup_sub(){
+        let markers = [("^", 'p'), ("~", 'b')];
+        let parts: Vec<_> = split_markers("x^2^ + H~2~O", &markers)
+            .into_iter().map(|(_, p)| p).collect();
+        assert_eq!(parts, vec![
+            MarkedText::Plain("x"),
+            MarkedText::Marked('p', "2"),
+            MarkedText::Plain(" + H"),
+            MarkedText::Marked('b', "2"),
+            MarkedText::Plain("O"),
+        ]);
+    }
+
+    #[test]
+    fn split_unmatched(){
+        let markers = [("==", ())];
+        let parts: Vec<_> = split_markers("a == b and ==c ==", &markers)
+            .into_iter().map(|(_, p)| p).collect();
+        assert_eq!(parts, vec![MarkedText::Plain("a == b and ==c ==")]);
+    }
+
+    #[test]
+    fn abbreviations(){
+        assert_eq!(parse_abbreviation("*[HTML]: HyperText Markup Language"), Some(("HTML", "HyperText Markup Language")));
+        assert_eq!(parse_abbreviation("[HTML]: /html"), None);
+
+        let abbreviations = BTreeMap::from([
+            ("HTML".to_string(), "HyperText Markup Language".to_string()),
+            ("HTML5".to_string(), "version 5".to_string()),
+        ]);
+        let parts: Vec<_> = split_abbreviations("HTML, HTML5 but not XHTML or html", &abbreviations)
+            .into_iter()
+            .map(|(_, part)| part)
+            .collect();
+        assert_eq!(parts, vec![
+            MarkedText::Marked("HyperText Markup Language", "HTML"),
+            MarkedText::Plain(", "),
+            MarkedText::Marked("version 5", "HTML5"),
+            MarkedText::Plain(" but not XHTML or html"),
+        ]);
+    }
+
+    #[cfg(feature="emoji")]
+    #[test]
+    fn shortcodes(){
+        assert_eq!(replace_shortcodes("Hello :wave:!").as_deref(), Some("Hello 👋!"));
+        assert_eq!(replace_shortcodes("a :not_an_emoji: b"), None);
+        assert_eq!(replace_shortcodes("time 12:30:00"), None);
+    }
+
+    #[test]
+    fn embed_providers(){
+        assert_eq!(embed_provider("https://www.youtube.com/watch?v=abc"), Some("youtube"));
+        assert_eq!(embed_provider("https://youtu.be/abc"), Some("youtube"));
+        assert_eq!(embed_provider("https://vimeo.com/123"), Some("vimeo"));
+        assert_eq!(embed_provider("https://www.youtube.com/"), None);
+        assert_eq!(embed_provider("https://example.com/watch?v=abc"), None);
+        assert_eq!(embed_provider("/youtu.be/abc"), None);
+    }
+
+    #[test]
+    fn tracking_params(){
+        assert_eq!(
+            strip_tracking_params("https://example.com/a?utm_source=x&id=2&fbclid=y#top"),
+            "https://example.com/a?id=2#top"
+        );
+        assert_eq!(strip_tracking_params("https://example.com/?utm_medium=x"), "https://example.com/");
+        assert_eq!(strip_tracking_params("mailto:someone@example.com?subject=hi"), "mailto:someone@example.com?subject=hi");
+        assert_eq!(strip_tracking_params("#section?utm_source=x"), "#section?utm_source=x");
+    }
+
+    #[test]
+    fn external_urls(){
+        assert!(is_external_url("https://example.com/a", None));
+        assert!(is_external_url("HTTP://example.com", None));
+        assert!(is_external_url("https://example.com/a", Some("https://docs.rs/")));
+        assert!(!is_external_url("https://Example.com/a", Some("https://example.com/docs/")));
+        assert!(!is_external_url("#section", None));
+        assert!(!is_external_url("./page.md", None));
+        assert!(!is_external_url("mailto:me@example.com", None));
+    }
+
+    #[test]
+    fn resolve_relative_urls(){
+        let base = "https://example.com/docs/guide/index.md";
+        assert_eq!(resolve_url(base, "./image.png"), "https://example.com/docs/guide/image.png");
+        assert_eq!(resolve_url(base, "../x.md#part"), "https://example.com/docs/x.md#part");
+        assert_eq!(resolve_url(base, "/root.png"), "https://example.com/root.png");
+        assert_eq!(resolve_url("https://example.com", "a.png"), "https://example.com/a.png");
+        assert_eq!(resolve_url("/docs/", "../../a.png"), "/a.png");
+        assert_eq!(resolve_url("docs/page.md", "img/a.png"), "docs/img/a.png");
+    }
+
+    #[test]
+    fn resolve_keeps_absolute_urls(){
+        let base = "https://example.com/docs/";
+        for url in ["https://other.org/a", "//cdn.org/a.png", "mailto:me@example.com", "#anchor"] {
+            assert_eq!(resolve_url(base, url), url);
+        }
+    }
+
+    #[test]
+    fn image_size(){
+        assert_eq!(parse_image_size("img.png =200x100"), Some(("img.png", Some(200), Some(100))));
+        assert_eq!(parse_image_size("img.png =200x"), Some(("img.png", Some(200), None)));
+        assert_eq!(parse_image_size("img.png =x100"), Some(("img.png", None, Some(100))));
+        assert_eq!(parse_image_size("img.png =x"), None);
+        assert_eq!(parse_image_size("img.png =200"), None);
+        assert_eq!(parse_image_size("img.png =ax100"), None);
+        assert_eq!(parse_image_size("img.png"), None);
+    }
+
+    #[test]
+    fn slugify_punctuation(){
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  snake_case and-dash "), "snake_case-and-dash");
+    }
+
+    #[test]
+    fn unique_slugs(){
+        let mut slugs = Slugs::default();
+        assert_eq!(slugs.unique("intro".into()), "intro");
+        assert_eq!(slugs.unique("intro".into()), "intro-1");
+        assert_eq!(slugs.unique("intro-1".into()), "intro-1-1");
+        assert_eq!(slugs.unique("intro".into()), "intro-2");
+        assert_eq!(slugs.unique("".into()), "section");
+        assert_eq!(slugs.unique("".into()), "section-1");
+    }
+
+    #[test]
+    fn alert_markers(){
+        assert_eq!(parse_alert_marker("[!NOTE]"), Some(("note", "Note".to_string())));
+        assert_eq!(parse_alert_marker("[!warning] Be careful "), Some(("warning", "Be careful".to_string())));
+        assert_eq!(parse_alert_marker("[!OTHER]"), None);
+        assert_eq!(parse_alert_marker("[NOTE]"), None);
+    }
+
+    #[test]
+    fn details_markers(){
+        assert_eq!(parse_details_marker("::: details"), Some("Details".to_string()));
+        assert_eq!(parse_details_marker(":::details How does it work?"), Some("How does it work?".to_string()));
+        assert_eq!(parse_details_marker("::: detailsx"), None);
+        assert_eq!(parse_details_marker(":::"), None);
+    }
+
+    #[test]
+    fn table_spans(){
+        use CellMerge::{Up, Left};
+        let n = CellMerge::None;
+        let rows = vec![
+            (true, vec![n, n, n]),
+            (false, vec![Up, n, Left]),
+            (false, vec![n, Up, Left]),
+            (false, vec![Left, n]),
+            (false, vec![n, n, n, Up]),
+        ];
+        assert_eq!(cell_spans(&rows), vec![
+            vec![Some((1, 1)), Some((1, 1)), Some((1, 1))],
+            vec![Some((1, 1)), Some((2, 2)), None],
+            vec![Some((1, 1)), None, None],
+            vec![Some((1, 1)), Some((1, 1))],
+            vec![Some((1, 1)), Some((1, 1)), Some((1, 1)), Some((1, 1))],
+        ]);
+    }
+}